@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use aspirin_eats::{api::handle_request, db::AspirinEatsDb, http::HttpRequest};
+use libfuzzer_sys::fuzz_target;
+
+thread_local! {
+    static DB: AspirinEatsDb = AspirinEatsDb::in_memory().expect("Failed to open database");
+}
+
+// Parse arbitrary bytes as a request and route it, exactly as the origin would after reading it
+// off a socket. Errors are fine, panics are not.
+fuzz_target!(|data: &[u8]| {
+    let Ok(raw) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(request) = HttpRequest::from_str(raw) else {
+        return;
+    };
+    let _ = request.to_string();
+    DB.with(|db| {
+        let _ = handle_request(db, &request);
+    });
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use aspirin_eats::food::{Order, OrderRequest};
+use libfuzzer_sys::fuzz_target;
+
+// Anything that parses as an order must serialize back to something that parses to the same
+// order, since the database stores orders as JSON and expects to read them back.
+fuzz_target!(|data: &[u8]| {
+    let Ok(raw) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(request) = OrderRequest::from_str(raw) {
+        assert_eq!(OrderRequest::from_str(&request.to_string()).unwrap(), request);
+
+        let order = Order::from(request);
+        assert_eq!(Order::from_str(&order.to_string()).unwrap(), order);
+    }
+
+    if let Ok(order) = Order::from_str(raw) {
+        assert_eq!(Order::from_str(&order.to_string()).unwrap(), order);
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use aspirin_eats::http::read_request;
+use libfuzzer_sys::fuzz_target;
+
+// Read arbitrary bytes as if they had arrived on a socket, whatever headers and Content-Length they
+// claim. Whatever is read must be exactly the start of what was sent. Errors are fine, panics are
+// not.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = read_request(data) {
+        assert!(data.starts_with(message.as_bytes()));
+    }
+});
@@ -0,0 +1,996 @@
+use std::str::FromStr;
+
+use schemars::{gen::SchemaGenerator, schema::Schema};
+
+use crate::{
+    db::AspirinEatsDb,
+    dispatch::{Assignment, Driver, DriverRequest},
+    error::AspirinEatsError,
+    food::{
+        ItemChange, ItemsChangeRecord, MenuItem, Order, OrderEvent, OrderRequest, OrderStatus,
+        StockLevel,
+    },
+    http::{HttpRequest, HttpResponse, ACTOR_HEADER, IDEMPOTENCY_KEY_HEADER},
+    openapi,
+    receipt::{Receipt, ReceiptFormat},
+};
+
+/// Function that handles a request to a route, given the values of the route's path parameters
+type Handler = fn(&AspirinEatsDb, &HttpRequest, &[&str]) -> Result<HttpResponse, AspirinEatsError>;
+
+/// Generates the JSON schema of a request or response body
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// Shape of a request or response body
+#[derive(Clone, Copy)]
+pub enum Body {
+    /// No body
+    Empty,
+
+    /// Plain text
+    Text,
+
+    /// JSON matching the schema of some Rust type
+    Json(SchemaFn),
+}
+
+/// A single entry in the API's route table
+pub struct Route {
+    /// HTTP method the route responds to
+    pub method: &'static str,
+
+    /// Path pattern, with path parameters written as `{name}`
+    pub path: &'static str,
+
+    /// Short human readable description of what the route does
+    pub summary: &'static str,
+
+    /// Body the route expects in the request
+    pub request: Body,
+
+    /// Status code and body sent back on success
+    pub response: (u16, Body),
+
+    handler: Handler,
+}
+
+/// Every route served by the API. Requests are dispatched from this table, and the OpenAPI
+/// description is generated from it
+pub const ROUTES: &[Route] = &[
+    Route {
+        method: "GET",
+        path: "/",
+        summary: "Welcome message",
+        request: Body::Empty,
+        response: (200, Body::Text),
+        handler: welcome,
+    },
+    Route {
+        method: "GET",
+        path: "/orders",
+        summary: "List every order",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<Order>>),
+        ),
+        handler: list_orders,
+    },
+    Route {
+        method: "POST",
+        path: "/orders",
+        summary: "Place a new order",
+        request: Body::Json(SchemaGenerator::subschema_for::<OrderRequest>),
+        response: (201, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: place_order,
+    },
+    Route {
+        method: "DELETE",
+        path: "/orders",
+        summary: "Remove every order",
+        request: Body::Empty,
+        response: (200, Body::Text),
+        handler: remove_all_orders,
+    },
+    Route {
+        method: "GET",
+        path: "/orders/{id}",
+        summary: "Get a single order",
+        request: Body::Empty,
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: get_order,
+    },
+    Route {
+        method: "DELETE",
+        path: "/orders/{id}",
+        summary: "Remove a single order",
+        request: Body::Empty,
+        response: (200, Body::Text),
+        handler: remove_order,
+    },
+    Route {
+        method: "PUT",
+        path: "/orders/{id}/status",
+        summary: "Set the status of an order",
+        request: Body::Json(SchemaGenerator::subschema_for::<OrderStatus>),
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: update_status,
+    },
+    Route {
+        method: "PUT",
+        path: "/orders/{id}/items",
+        summary: "Replace the items of a pending order",
+        request: Body::Json(SchemaGenerator::subschema_for::<Vec<MenuItem>>),
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: replace_items,
+    },
+    Route {
+        method: "PATCH",
+        path: "/orders/{id}/items",
+        summary: "Add, remove or swap items in a pending order",
+        request: Body::Json(SchemaGenerator::subschema_for::<Vec<ItemChange>>),
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: change_items,
+    },
+    Route {
+        method: "GET",
+        path: "/orders/{id}/items/history",
+        summary: "Every change made to the items of an order, oldest first",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<ItemsChangeRecord>>),
+        ),
+        handler: item_history,
+    },
+    Route {
+        method: "GET",
+        path: "/orders/{id}/history",
+        summary: "Every change made to an order, oldest first, including after it was deleted",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<OrderEvent>>),
+        ),
+        handler: order_history,
+    },
+    Route {
+        method: "POST",
+        path: "/orders/{id}/restore",
+        summary: "Bring back a deleted order as it was when it was deleted",
+        request: Body::Empty,
+        response: (201, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: restore_order,
+    },
+    Route {
+        method: "GET",
+        path: "/orders/{id}/receipt",
+        summary: "Itemized receipt, as text/plain or text/html depending on the Accept header",
+        request: Body::Empty,
+        response: (200, Body::Text),
+        handler: receipt,
+    },
+    Route {
+        method: "GET",
+        path: "/inventory",
+        summary: "Stock of every tracked ingredient",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<StockLevel>>),
+        ),
+        handler: get_inventory,
+    },
+    Route {
+        method: "POST",
+        path: "/inventory/restock",
+        summary: "Add to the stock of some ingredients",
+        request: Body::Json(SchemaGenerator::subschema_for::<Vec<StockLevel>>),
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<StockLevel>>),
+        ),
+        handler: restock,
+    },
+    Route {
+        method: "GET",
+        path: "/kitchen/queue",
+        summary: "Pending orders waiting for a cook, oldest first",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<Order>>),
+        ),
+        handler: kitchen_queue,
+    },
+    Route {
+        method: "POST",
+        path: "/kitchen/queue/claim",
+        summary: "Start preparing the oldest pending order",
+        request: Body::Empty,
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: claim_order,
+    },
+    Route {
+        method: "GET",
+        path: "/kitchen/ready",
+        summary: "Prepared orders waiting for a driver, oldest first",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<Order>>),
+        ),
+        handler: ready_orders,
+    },
+    Route {
+        method: "POST",
+        path: "/kitchen/orders/{id}/ready",
+        summary: "Mark an order as prepared and ready for a driver",
+        request: Body::Empty,
+        response: (200, Body::Json(SchemaGenerator::subschema_for::<Order>)),
+        handler: mark_ready,
+    },
+    Route {
+        method: "GET",
+        path: "/drivers",
+        summary: "List every driver and what they are delivering",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<Driver>>),
+        ),
+        handler: list_drivers,
+    },
+    Route {
+        method: "POST",
+        path: "/drivers",
+        summary: "Add a driver",
+        request: Body::Json(SchemaGenerator::subschema_for::<DriverRequest>),
+        response: (201, Body::Json(SchemaGenerator::subschema_for::<Driver>)),
+        handler: add_driver,
+    },
+    Route {
+        method: "POST",
+        path: "/drivers/{id}/delivered",
+        summary: "Complete a driver's delivery and make them available again",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Option<Order>>),
+        ),
+        handler: delivered,
+    },
+    Route {
+        method: "POST",
+        path: "/dispatch",
+        summary: "Hand ready orders to available drivers",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<Vec<Assignment>>),
+        ),
+        handler: dispatch,
+    },
+    Route {
+        method: "GET",
+        path: "/openapi.json",
+        summary: "This API description",
+        request: Body::Empty,
+        response: (
+            200,
+            Body::Json(SchemaGenerator::subschema_for::<serde_json::Value>),
+        ),
+        handler: api_description,
+    },
+];
+
+/// Actor recorded for requests that change orders without an `X-Actor` header
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Route an HTTP request to the appropriate action on the database
+///
+/// Errors:
+/// - `NotFound` if the path (or the order it refers to) does not exist
+/// - `MethodNotAllowed` if the path exists but does not support the method
+/// - `InvalidRequest`/`ParseError` if the request is malformed
+pub fn handle_request(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+) -> Result<HttpResponse, AspirinEatsError> {
+    let method = request.method.as_deref().unwrap_or("GET");
+    let segments = path_segments(request.path.as_deref().unwrap_or("/"));
+
+    let mut path_exists = false;
+    for route in ROUTES {
+        if let Some(params) = match_path(route.path, &segments) {
+            if route.method == method {
+                let actor = request.header(ACTOR_HEADER).unwrap_or(ANONYMOUS_ACTOR);
+                return db.acting_as(actor, || (route.handler)(db, request, &params));
+            }
+            path_exists = true;
+        }
+    }
+
+    if path_exists {
+        Err(AspirinEatsError::MethodNotAllowed)
+    } else {
+        Err(AspirinEatsError::NotFound)
+    }
+}
+
+/// Collapse a concrete request path into the route it matched, so that metrics are not labelled
+/// with one series per order id
+pub fn route_label(path: Option<&str>) -> &'static str {
+    let segments = path_segments(path.unwrap_or("/"));
+    if segments == ["metrics"] {
+        return "/metrics";
+    }
+
+    ROUTES
+        .iter()
+        .find(|route| match_path(route.path, &segments).is_some())
+        .map_or("unknown", |route| route.path)
+}
+
+fn welcome(
+    _: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    Ok(HttpResponse::new(200, "OK", "Welcome to Aspirin Eats!"))
+}
+
+fn list_orders(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let orders = db.get_all_orders()?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&orders)?))
+}
+
+fn place_order(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let mut order: Order = OrderRequest::from_str(body(request)?)?.into();
+    match request.header(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) => order = db.place_order_once(key, order)?,
+        None => order.id = Some(db.place_order(order.clone())?),
+    }
+    Ok(json_response(201, "Created", &order.to_string()))
+}
+
+fn remove_all_orders(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    db.reset_orders()?;
+    Ok(HttpResponse::new(200, "OK", "All orders removed"))
+}
+
+fn get_order(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db
+        .get_order(parse_id(params[0])?)?
+        .ok_or(AspirinEatsError::NotFound)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn remove_order(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let id = parse_id(params[0])?;
+    db.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+    db.remove_order(id)?;
+    Ok(HttpResponse::new(
+        200,
+        "OK",
+        &format!("Order {} removed", id),
+    ))
+}
+
+fn update_status(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let id = parse_id(params[0])?;
+    if !db.update_status(id, OrderStatus::from_str(body(request)?)?)? {
+        return Err(AspirinEatsError::NotFound);
+    }
+    let order = db.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn replace_items(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let id = parse_id(params[0])?;
+    let food: Vec<MenuItem> = serde_json::from_str(body(request)?)?;
+    let order = db.modify_items(id, food)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn change_items(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let id = parse_id(params[0])?;
+    let changes: Vec<ItemChange> = serde_json::from_str(body(request)?)?;
+    let order = db.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+    if order.status != OrderStatus::Pending {
+        return Err(AspirinEatsError::NotPending);
+    }
+
+    let mut food = order.food;
+    for change in &changes {
+        if !change.apply(&mut food) {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+    }
+    let order = db.modify_items(id, food)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn item_history(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let id = parse_id(params[0])?;
+    db.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+    let changes = db.get_item_changes(id)?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&changes)?))
+}
+
+fn order_history(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let events = db.get_order_events(parse_id(params[0])?)?;
+    if events.is_empty() {
+        return Err(AspirinEatsError::NotFound);
+    }
+    Ok(json_response(200, "OK", &serde_json::to_string(&events)?))
+}
+
+fn restore_order(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db.restore_order(parse_id(params[0])?)?;
+    Ok(json_response(201, "Created", &order.to_string()))
+}
+
+fn receipt(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db
+        .get_order(parse_id(params[0])?)?
+        .ok_or(AspirinEatsError::NotFound)?;
+    let format = ReceiptFormat::negotiate(request.header("Accept"))
+        .ok_or(AspirinEatsError::NotAcceptable)?;
+    Ok(
+        HttpResponse::new(200, "OK", &Receipt::new(&order).render(format))
+            .with_header("Content-Type", format.content_type())
+            .with_header("Vary", "Accept"),
+    )
+}
+
+fn get_inventory(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let inventory = db.get_inventory()?;
+    Ok(json_response(
+        200,
+        "OK",
+        &serde_json::to_string(&inventory)?,
+    ))
+}
+
+fn restock(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let levels: Vec<StockLevel> = serde_json::from_str(body(request)?)?;
+    db.restock(&levels)?;
+    get_inventory(db, request, &[])
+}
+
+fn kitchen_queue(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let orders = db.get_orders_by_status(OrderStatus::Pending)?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&orders)?))
+}
+
+fn claim_order(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db.claim_next_order()?.ok_or(AspirinEatsError::NotFound)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn ready_orders(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let orders = db.get_ready_orders()?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&orders)?))
+}
+
+fn mark_ready(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db.mark_ready(parse_id(params[0])?)?;
+    Ok(json_response(200, "OK", &order.to_string()))
+}
+
+fn list_drivers(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let drivers = db.get_drivers()?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&drivers)?))
+}
+
+fn add_driver(
+    db: &AspirinEatsDb,
+    request: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let driver = DriverRequest::from_str(body(request)?)?;
+    let id = db.add_driver(&driver.name)?;
+    let driver = db.get_driver(id)?.ok_or(AspirinEatsError::NotFound)?;
+    Ok(json_response(201, "Created", &driver.to_string()))
+}
+
+fn delivered(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    params: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let order = db.complete_delivery(parse_id(params[0])?)?;
+    Ok(json_response(200, "OK", &serde_json::to_string(&order)?))
+}
+
+fn dispatch(
+    db: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    let assignments = db.dispatch()?;
+    Ok(json_response(
+        200,
+        "OK",
+        &serde_json::to_string(&assignments)?,
+    ))
+}
+
+fn api_description(
+    _: &AspirinEatsDb,
+    _: &HttpRequest,
+    _: &[&str],
+) -> Result<HttpResponse, AspirinEatsError> {
+    Ok(json_response(200, "OK", &openapi::document().to_string()))
+}
+
+/// Match path segments against a route pattern, returning the values of its path parameters
+fn match_path<'a>(pattern: &str, segments: &[&'a str]) -> Option<Vec<&'a str>> {
+    let pattern = path_segments(pattern);
+    if pattern.len() != segments.len() {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    for (expected, actual) in pattern.iter().zip(segments) {
+        if expected.starts_with('{') && expected.ends_with('}') {
+            params.push(*actual);
+        } else if expected != actual {
+            return None;
+        }
+    }
+    Some(params)
+}
+
+/// Split a path into its non-empty segments, ignoring any query string
+fn path_segments(path: &str) -> Vec<&str> {
+    let path = path.split('?').next().unwrap_or_default();
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+fn body(request: &HttpRequest) -> Result<&str, AspirinEatsError> {
+    request
+        .body
+        .as_deref()
+        .ok_or(AspirinEatsError::InvalidRequest)
+}
+
+fn parse_id(id: &str) -> Result<i64, AspirinEatsError> {
+    id.parse().map_err(|_| AspirinEatsError::InvalidRequest)
+}
+
+fn json_response(status_code: u16, status_text: &str, body: &str) -> HttpResponse {
+    HttpResponse::new(status_code, status_text, body)
+        .with_header("Content-Type", "application/json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: Option<&str>) -> HttpRequest {
+        HttpRequest {
+            method: Some(method.to_string()),
+            path: Some(path.to_string()),
+            headers: Vec::new(),
+            body: body.map(str::to_string),
+        }
+    }
+
+    const ORDER_JSON: &str = r#"{"customer":"Amit","food":["Fries","Drink"]}"#;
+
+    #[test]
+    fn test_welcome() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let response = handle_request(&db, &request("GET", "/", None)).unwrap();
+        assert_eq!(response.body(), "Welcome to Aspirin Eats!");
+    }
+
+    #[test]
+    fn test_post_and_get_order() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let response = handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+        assert_eq!(response.status_code(), 201);
+
+        let response = handle_request(&db, &request("GET", "/orders/1", None)).unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.id, Some(1));
+        assert_eq!(order.food, vec![MenuItem::Fries, MenuItem::Drink]);
+        assert_eq!(order.status, OrderStatus::Pending);
+        assert_eq!(order.total, 8.0);
+    }
+
+    #[test]
+    fn test_repeated_idempotency_key_places_one_order() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let mut post = request("POST", "/orders", Some(ORDER_JSON));
+        post.set_header(IDEMPOTENCY_KEY_HEADER, "key-1");
+
+        let first = handle_request(&db, &post).unwrap();
+        let repeat = handle_request(&db, &post).unwrap();
+        assert_eq!(repeat.status_code(), 201);
+        assert_eq!(repeat.body(), first.body());
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_delete_orders() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+
+        handle_request(&db, &request("DELETE", "/orders/1", None)).unwrap();
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+
+        handle_request(&db, &request("DELETE", "/orders", None)).unwrap();
+        assert!(db.get_all_orders().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_status() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+
+        let response = handle_request(
+            &db,
+            &request("PUT", "/orders/1/status", Some(r#""Preparing""#)),
+        )
+        .unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.status, OrderStatus::Preparing);
+
+        assert!(matches!(
+            handle_request(
+                &db,
+                &request("PUT", "/orders/2/status", Some(r#""Preparing""#))
+            ),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_modify_items() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+
+        let response = handle_request(
+            &db,
+            &request("PUT", "/orders/1/items", Some(r#"["Drink","Drink"]"#)),
+        )
+        .unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.food, vec![MenuItem::Drink, MenuItem::Drink]);
+        assert_eq!(order.total, 6.0);
+
+        let response = handle_request(
+            &db,
+            &request(
+                "PATCH",
+                "/orders/1/items",
+                Some(r#"[{"Remove":0},{"Add":"Fries"}]"#),
+            ),
+        )
+        .unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.food, vec![MenuItem::Drink, MenuItem::Fries]);
+        assert_eq!(order.total, 8.0);
+
+        let response =
+            handle_request(&db, &request("GET", "/orders/1/items/history", None)).unwrap();
+        let history: Vec<ItemsChangeRecord> = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].before, vec![MenuItem::Fries, MenuItem::Drink]);
+        assert_eq!(history[1].after, order.food);
+
+        // A change to an item that isn't there rejects the whole request
+        assert!(matches!(
+            handle_request(
+                &db,
+                &request(
+                    "PATCH",
+                    "/orders/1/items",
+                    Some(r#"[{"Remove":0},{"Remove":5}]"#)
+                )
+            ),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+        assert_eq!(db.get_item_changes(1).unwrap().len(), 2);
+
+        handle_request(
+            &db,
+            &request("PUT", "/orders/1/status", Some(r#""Preparing""#)),
+        )
+        .unwrap();
+        assert!(matches!(
+            handle_request(
+                &db,
+                &request("PUT", "/orders/1/items", Some(r#"["Fries"]"#))
+            ),
+            Err(AspirinEatsError::NotPending)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/2/items/history", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_history_and_restore() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+        let mut remove = request("DELETE", "/orders", None);
+        remove.set_header(ACTOR_HEADER, "oops");
+        handle_request(&db, &remove).unwrap();
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/1", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+
+        let response = handle_request(&db, &request("GET", "/orders/1/history", None)).unwrap();
+        let events: Vec<OrderEvent> = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].actor, ANONYMOUS_ACTOR);
+        assert_eq!(events[1].actor, "oops");
+
+        let response = handle_request(&db, &request("POST", "/orders/1/restore", None)).unwrap();
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(Order::from_str(response.body()).unwrap().id, Some(1));
+        assert!(handle_request(&db, &request("GET", "/orders/1", None)).is_ok());
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/orders/1/restore", None)),
+            Err(AspirinEatsError::NotDeleted)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/2/history", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_receipt_content_negotiation() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+
+        let response = handle_request(&db, &request("GET", "/orders/1/receipt", None)).unwrap();
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert!(response.body().contains("Customer: Amit"));
+
+        let mut html = request("GET", "/orders/1/receipt", None);
+        html.set_header("Accept", "text/html");
+        let response = handle_request(&db, &html).unwrap();
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert!(response.body().starts_with("<!DOCTYPE html>"));
+
+        let mut json = request("GET", "/orders/1/receipt", None);
+        json.set_header("Accept", "application/json");
+        assert!(matches!(
+            handle_request(&db, &json),
+            Err(AspirinEatsError::NotAcceptable)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/2/receipt", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_inventory() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let burger = r#"{"customer":"Amit","food":[{"Burger":{"bun":"Plain","patty":"Veggie","toppings":[]}}]}"#;
+
+        let response = handle_request(
+            &db,
+            &request(
+                "POST",
+                "/inventory/restock",
+                Some(r#"[{"ingredient":{"Patty":"Veggie"},"quantity":1}]"#),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            response.body(),
+            r#"[{"ingredient":{"Patty":"Veggie"},"quantity":1}]"#
+        );
+
+        handle_request(&db, &request("POST", "/orders", Some(burger))).unwrap();
+        let response = handle_request(&db, &request("GET", "/inventory", None)).unwrap();
+        assert_eq!(
+            response.body(),
+            r#"[{"ingredient":{"Patty":"Veggie"},"quantity":0}]"#
+        );
+
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/orders", Some(burger))),
+            Err(AspirinEatsError::OutOfStock(_))
+        ));
+
+        // Cancelling the first order frees up its patty again
+        handle_request(
+            &db,
+            &request("PUT", "/orders/1/status", Some(r#""Cancelled""#)),
+        )
+        .unwrap();
+        let response = handle_request(&db, &request("POST", "/orders", Some(burger))).unwrap();
+        assert_eq!(response.status_code(), 201);
+    }
+
+    #[test]
+    fn test_dispatch_workflow() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+        handle_request(&db, &request("POST", "/orders", Some(ORDER_JSON))).unwrap();
+
+        let response = handle_request(&db, &request("GET", "/kitchen/queue", None)).unwrap();
+        let queue: Vec<Order> = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        let response = handle_request(&db, &request("POST", "/kitchen/queue/claim", None)).unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.id, Some(1));
+        assert_eq!(order.status, OrderStatus::Preparing);
+
+        // Only orders being prepared can be marked ready, and only once
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/kitchen/orders/2/ready", None)),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+        handle_request(&db, &request("POST", "/kitchen/orders/1/ready", None)).unwrap();
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/kitchen/orders/1/ready", None)),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+
+        let response = handle_request(
+            &db,
+            &request("POST", "/drivers", Some(r#"{"name":"Dana"}"#)),
+        )
+        .unwrap();
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.body(), r#"{"id":1,"name":"Dana","order":null}"#);
+
+        let response = handle_request(&db, &request("POST", "/dispatch", None)).unwrap();
+        assert_eq!(response.body(), r#"[{"order":1,"driver":1}]"#);
+        assert_eq!(
+            db.get_order(1).unwrap().unwrap().status,
+            OrderStatus::Transporting
+        );
+
+        let response = handle_request(&db, &request("POST", "/drivers/1/delivered", None)).unwrap();
+        let order = Order::from_str(response.body()).unwrap();
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/drivers/1/delivered", None)),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+
+        // Claiming from an empty queue
+        handle_request(&db, &request("POST", "/kitchen/queue/claim", None)).unwrap();
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/kitchen/queue/claim", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/7", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/orders/abc", None)),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("PUT", "/orders", None)),
+            Err(AspirinEatsError::MethodNotAllowed)
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("POST", "/orders", Some("not json"))),
+            Err(AspirinEatsError::ParseError(_))
+        ));
+        assert!(matches!(
+            handle_request(&db, &request("GET", "/menu", None)),
+            Err(AspirinEatsError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!(route_label(None), "/");
+        assert_eq!(route_label(Some("/orders/")), "/orders");
+        assert_eq!(route_label(Some("/orders/42")), "/orders/{id}");
+        assert_eq!(
+            route_label(Some("/orders/42/status")),
+            "/orders/{id}/status"
+        );
+        assert_eq!(route_label(Some("/metrics")), "/metrics");
+        assert_eq!(route_label(Some("/favicon.ico")), "unknown");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+};
+
+use aspirin_eats::{
+    client::AspirinEatsClient,
+    error::AspirinEatsError,
+    food::{Bun, Burger, MenuItem, Order, OrderRequest, OrderStatus, Patty, Topping},
+};
+
+const USAGE: &str = "Usage: aspirin <server-addr> <command>
+
+Commands:
+    list                   List every order
+    get <id>               Show a single order
+    order                  Interactively place a new order
+    cancel <id>            Cancel an order
+    status <id> <status>   Set an order's status (pending, preparing, transporting, completed, cancelled)
+    receipt <id>           Print the receipt for an order
+    remove <id>            Remove an order
+    history <id>           Show every change made to an order
+    restore <id>           Bring back a removed order
+
+Changes are recorded as made by $USER.";
+
+fn main() {
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() < 3 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let mut client = AspirinEatsClient::new(&args[1]);
+    if let Ok(user) = env::var("USER") {
+        client = client.with_actor(&user);
+    }
+    let command = args[2..].iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match command.as_slice() {
+        ["list"] => client.list_orders().map(|orders| {
+            for order in orders {
+                print_order(&order);
+            }
+        }),
+        ["get", id] => parse_id(id)
+            .and_then(|id| client.get_order(id))
+            .map(|order| print_order(&order)),
+        ["order"] => read_order_request()
+            .and_then(|request| client.place_order(request))
+            .map(|order| print_order(&order)),
+        ["cancel", id] => parse_id(id)
+            .and_then(|id| client.cancel_order(id))
+            .map(|order| print_order(&order)),
+        ["status", id, status] => parse_id(id)
+            .and_then(|id| Ok((id, parse_status(status)?)))
+            .and_then(|(id, status)| client.update_status(id, status))
+            .map(|order| print_order(&order)),
+        ["receipt", id] => parse_id(id)
+            .and_then(|id| client.receipt(id))
+            .map(|receipt| print!("{}", receipt)),
+        ["remove", id] => parse_id(id).and_then(|id| client.remove_order(id)),
+        ["history", id] => parse_id(id)
+            .and_then(|id| client.order_history(id))
+            .map(|events| {
+                for event in events {
+                    println!("{} {:?} by {}", event.at_ms, event.kind, event.actor);
+                    if let Some(order) = event.after.as_ref().or(event.before.as_ref()) {
+                        print!("    ");
+                        print_order(order);
+                    }
+                }
+            }),
+        ["restore", id] => parse_id(id)
+            .and_then(|id| client.restore_order(id))
+            .map(|order| print_order(&order)),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn print_order(order: &Order) {
+    println!(
+        "#{} {} [{:?}] ${:.2} {:?}",
+        order.id.unwrap_or_default(),
+        order.customer,
+        order.status,
+        order.total,
+        order.food
+    );
+}
+
+fn parse_id(id: &str) -> Result<i64, AspirinEatsError> {
+    id.parse().map_err(|_| AspirinEatsError::InvalidRequest)
+}
+
+fn parse_status(status: &str) -> Result<OrderStatus, AspirinEatsError> {
+    match status.to_lowercase().as_str() {
+        "pending" => Ok(OrderStatus::Pending),
+        "preparing" => Ok(OrderStatus::Preparing),
+        "transporting" => Ok(OrderStatus::Transporting),
+        "completed" => Ok(OrderStatus::Completed),
+        "cancelled" => Ok(OrderStatus::Cancelled),
+        _ => Err(AspirinEatsError::InvalidRequest),
+    }
+}
+
+/// Prompt for a customer name and menu items on stdin
+fn read_order_request() -> Result<OrderRequest, AspirinEatsError> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    print!("Customer name: ");
+    io::stdout().flush()?;
+    let customer = lines.next().transpose()?.unwrap_or_default();
+
+    println!("Add items one per line, then an empty line to place the order:");
+    println!("    fries | drink | burger <bun> <patty> [toppings...]");
+    let mut food = Vec::new();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = match lines.next().transpose()? {
+            Some(line) if !line.trim().is_empty() => line,
+            _ => break,
+        };
+        match parse_menu_item(&line) {
+            Some(item) => food.push(item),
+            None => println!("Didn't understand {:?}, try again", line.trim()),
+        }
+    }
+
+    if customer.trim().is_empty() || food.is_empty() {
+        return Err(AspirinEatsError::InvalidRequest);
+    }
+    Ok(OrderRequest {
+        customer: customer.trim().to_string(),
+        food,
+    })
+}
+
+fn parse_menu_item(line: &str) -> Option<MenuItem> {
+    let words = line
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match words.as_slice() {
+        ["fries"] => Some(MenuItem::Fries),
+        ["drink"] => Some(MenuItem::Drink),
+        ["burger", bun, patty, toppings @ ..] => {
+            let bun = match *bun {
+                "sesame" => Bun::Sesame,
+                "plain" => Bun::Plain,
+                "glutenfree" => Bun::GlutenFree,
+                _ => return None,
+            };
+            let patty = match *patty {
+                "beef" => Patty::Beef,
+                "chicken" => Patty::Chicken,
+                "veggie" => Patty::Veggie,
+                _ => return None,
+            };
+            let toppings = toppings
+                .iter()
+                .map(|topping| match *topping {
+                    "lettuce" => Some(Topping::Lettuce),
+                    "tomato" => Some(Topping::Tomato),
+                    "onion" => Some(Topping::Onion),
+                    "pickle" => Some(Topping::Pickle),
+                    "cheese" => Some(Topping::Cheese),
+                    "bacon" => Some(Topping::Bacon),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(MenuItem::Burger(Burger::new(bun, patty, toppings)))
+        }
+        _ => None,
+    }
+}
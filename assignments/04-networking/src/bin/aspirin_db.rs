@@ -0,0 +1,92 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+    str::FromStr,
+};
+
+use aspirin_eats::{
+    db::AspirinEatsDb,
+    error::AspirinEatsError,
+    export::{export_orders, import_orders, Format},
+};
+
+const USAGE: &str = "Usage: aspirin-db <db-path> <command>
+
+Commands:
+    export [--format jsonl|csv] [<file>]   Write every order to <file> (default stdout)
+    import [--format jsonl|csv] [<file>]   Insert orders from <file> (default stdin), keeping their ids
+    backup <file>                          Copy a consistent snapshot of the database to <file>
+
+If --format is not given it is taken from the file extension, falling back to jsonl.";
+
+fn main() {
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() < 3 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let db_path = &args[1];
+    let command = args[2..].iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match command.as_slice() {
+        ["export", rest @ ..] => parse_transfer_args(rest).and_then(|(format, file)| {
+            let db = AspirinEatsDb::from_path(db_path)?;
+            let orders = db.get_all_orders()?;
+            match file {
+                Some(file) => export_orders(&orders, format, BufWriter::new(File::create(file)?)),
+                None => export_orders(&orders, format, io::stdout().lock()),
+            }?;
+            eprintln!("Exported {} orders", orders.len());
+            Ok(())
+        }),
+        ["import", rest @ ..] => parse_transfer_args(rest).and_then(|(format, file)| {
+            let orders = match file {
+                Some(file) => import_orders(BufReader::new(File::open(file)?), format),
+                None => import_orders(io::stdin().lock(), format),
+            }?;
+            let mut db = AspirinEatsDb::from_path(db_path)?;
+            let imported = db.import_orders(&orders)?;
+            eprintln!("Imported {} orders", imported);
+            Ok(())
+        }),
+        ["backup", dest] => AspirinEatsDb::from_path(db_path)
+            .and_then(|db| db.backup_to(dest))
+            .map_err(AspirinEatsError::from),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Parse `[--format <format>] [<file>]`
+fn parse_transfer_args<'a>(
+    args: &[&'a str],
+) -> Result<(Format, Option<&'a str>), AspirinEatsError> {
+    let (format, file) = match args {
+        [] => (None, None),
+        [file] => (None, Some(*file)),
+        ["--format", format] => (Some(*format), None),
+        ["--format", format, file] => (Some(*format), Some(*file)),
+        _ => return Err(AspirinEatsError::InvalidRequest),
+    };
+
+    let format = match (format, file) {
+        (Some(format), _) => Format::from_str(format)?,
+        (None, Some(file)) => Path::new(file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| Format::from_str(ext).ok())
+            .unwrap_or(Format::JsonLines),
+        (None, None) => Format::JsonLines,
+    };
+    Ok((format, file))
+}
@@ -0,0 +1,61 @@
+use std::{collections::HashMap, env};
+
+use aspirin_eats::{
+    config::LoadConfig,
+    load::{LoadGenerator, LoadOptions},
+};
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", LoadConfig::usage());
+        return;
+    }
+    let config = match LoadConfig::load(&args, &env::vars().collect::<HashMap<_, _>>()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, LoadConfig::usage());
+            std::process::exit(2);
+        }
+    };
+
+    let seed = match config.seed {
+        0 => rand::random(),
+        seed => seed,
+    };
+    let generator = match LoadGenerator::new(LoadOptions {
+        target: config.target.clone(),
+        connections: config.connections,
+        requests: config.requests,
+        mix: config.mix,
+        seed,
+    }) {
+        Ok(generator) => generator,
+        Err(e) => {
+            eprintln!("Could not reach {}: {}", config.target, e);
+            std::process::exit(1);
+        }
+    };
+
+    eprintln!(
+        "Sending {} requests to {} over {} connections (seed {})",
+        config.requests, config.target, config.connections, seed
+    );
+    println!("{}", generator.run());
+
+    if config.verify {
+        match generator.verify() {
+            Ok(problems) if problems.is_empty() => println!("Database is consistent"),
+            Ok(problems) => {
+                for problem in &problems {
+                    println!("Inconsistent: {}", problem);
+                }
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to verify the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -1,9 +1,89 @@
-use aspirin_eats::db::AspirinEatsDb;
+use std::{
+    collections::HashMap,
+    env,
+    net::TcpListener,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-/// Change this path to match where you want to store the database file
-const DB_PATH: &str =
-    "/home/amit/Documents/code/aspirin/dev-aspirin/assignments/05-networking/aspirin_eats.db";
+use aspirin_eats::{
+    config::OriginConfig,
+    db::AspirinEatsDb,
+    logging::AccessLog,
+    server::OriginServer,
+    shutdown::{self, Shutdown},
+};
 
 fn main() {
-    let db = AspirinEatsDb::from_path(DB_PATH).expect("Failed to open database");
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", OriginConfig::usage());
+        return;
+    }
+    let config = match OriginConfig::load(&args, &env::vars().collect::<HashMap<_, _>>()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, OriginConfig::usage());
+            std::process::exit(2);
+        }
+    };
+
+    let db = AspirinEatsDb::from_path(&config.db_path).expect("Failed to open database");
+    let server = Arc::new(OriginServer::new(db, AccessLog::stdout()));
+
+    let shutdown = Shutdown::new();
+    shutdown
+        .register_signals()
+        .expect("Failed to register signal handlers");
+
+    let retention = config
+        .retention_interval
+        .filter(|_| !config.retention.is_empty())
+        .map(|interval| {
+            let server = Arc::clone(&server);
+            let shutdown = shutdown.clone();
+            let policy = config.retention.clone();
+            thread::spawn(move || {
+                let mut last_run = None::<Instant>;
+                while !shutdown.is_triggered() {
+                    if last_run.is_none_or(|at| at.elapsed() >= interval) {
+                        match server.apply_retention(&policy) {
+                            // stdout is the access log, so progress goes with the errors
+                            Ok(report) => eprintln!(
+                                "Retention: anonymized {} orders, deleted {}",
+                                report.anonymized, report.deleted
+                            ),
+                            Err(e) => eprintln!("Retention failed: {}", e),
+                        }
+                        last_run = Some(Instant::now());
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+            })
+        });
+
+    let listener = TcpListener::bind(&config.bind).expect("Failed to bind to address");
+    let handler = {
+        let server = Arc::clone(&server);
+        Arc::new(move |mut stream| {
+            if let Err(e) = server.handle_connection(&mut stream) {
+                eprintln!("Failed to handle connection: {}", e);
+            }
+        })
+    };
+    let abandoned =
+        shutdown::serve(listener, &shutdown, &config.serve, handler).expect("Server failed");
+    if let Some(retention) = retention {
+        let _ = retention.join();
+    }
+
+    if abandoned > 0 {
+        eprintln!("Shutting down with {} requests still in flight", abandoned);
+        return;
+    }
+    match Arc::try_unwrap(server) {
+        Ok(server) => server.close().expect("Failed to close database"),
+        Err(_) => eprintln!("Database still in use, exiting without closing it"),
+    }
 }
@@ -1,12 +1,42 @@
-use std::env;
+use std::{collections::HashMap, env, net::TcpListener, sync::Arc};
+
+use aspirin_eats::{
+    config::ProxyConfig,
+    logging::AccessLog,
+    proxy::ReverseProxy,
+    shutdown::{self, Shutdown},
+};
 
 fn main() {
-    let args = env::args().collect::<Vec<String>>();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <proxy-from> <proxy-to>", args[0]);
-        std::process::exit(2);
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", ProxyConfig::usage());
+        return;
     }
+    let config = match ProxyConfig::load(&args, &env::vars().collect::<HashMap<_, _>>()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, ProxyConfig::usage());
+            std::process::exit(2);
+        }
+    };
+
+    let proxy = ReverseProxy::new(&config.origin, AccessLog::stdout())
+        .with_upstream_options(config.upstream);
+    let shutdown = Shutdown::new();
+    shutdown
+        .register_signals()
+        .expect("Failed to register signal handlers");
 
-    let proxy_addr = &args[1];
-    let origin_addr = &args[2];
+    let listener = TcpListener::bind(&config.bind).expect("Failed to bind to address");
+    let handler = Arc::new(move |mut stream| {
+        if let Err(e) = proxy.handle_connection(&mut stream) {
+            eprintln!("Failed to handle connection: {}", e);
+        }
+    });
+    let abandoned =
+        shutdown::serve(listener, &shutdown, &config.serve, handler).expect("Proxy failed");
+    if abandoned > 0 {
+        eprintln!("Shutting down with {} requests still in flight", abandoned);
+    }
 }
@@ -0,0 +1,341 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    str::FromStr,
+};
+
+use crate::{
+    error::AspirinEatsError,
+    food::{ItemChange, MenuItem, Order, OrderEvent, OrderRequest, OrderStatus, StockLevel},
+    http::{HttpRequest, HttpResponse, ACTOR_HEADER},
+};
+
+/// Typed client for the Aspirin Eats API, usable against either the origin or the proxy
+pub struct AspirinEatsClient {
+    addr: String,
+
+    /// Sent as the `X-Actor` header so the server knows who made any changes
+    actor: Option<String>,
+}
+
+impl AspirinEatsClient {
+    /// Create a client that talks to the server at `addr` (e.g. "127.0.0.1:8080")
+    pub fn new(addr: &str) -> Self {
+        AspirinEatsClient {
+            addr: addr.to_string(),
+            actor: None,
+        }
+    }
+
+    /// Identify every request as coming from `actor`, so it is recorded in the history of the
+    /// orders it changes
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    /// Place a new order, returning it as stored by the server
+    pub fn place_order(&self, order: OrderRequest) -> Result<Order, AspirinEatsError> {
+        let response = self.send("POST", "/orders", Some(order.to_string()))?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Get a single order by ID
+    pub fn get_order(&self, id: i64) -> Result<Order, AspirinEatsError> {
+        let response = self.send("GET", &format!("/orders/{}", id), None)?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Get every order
+    pub fn list_orders(&self) -> Result<Vec<Order>, AspirinEatsError> {
+        let response = self.send("GET", "/orders", None)?;
+        Ok(serde_json::from_str(response.body())?)
+    }
+
+    /// Set the status of an order, returning the updated order
+    pub fn update_status(&self, id: i64, status: OrderStatus) -> Result<Order, AspirinEatsError> {
+        let response = self.send(
+            "PUT",
+            &format!("/orders/{}/status", id),
+            Some(status.to_string()),
+        )?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Replace every item in a pending order, returning the updated order
+    pub fn replace_items(&self, id: i64, food: &[MenuItem]) -> Result<Order, AspirinEatsError> {
+        let response = self.send(
+            "PUT",
+            &format!("/orders/{}/items", id),
+            Some(serde_json::to_string(food)?),
+        )?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Add, remove or swap items in a pending order, returning the updated order
+    pub fn change_items(&self, id: i64, changes: &[ItemChange]) -> Result<Order, AspirinEatsError> {
+        let response = self.send(
+            "PATCH",
+            &format!("/orders/{}/items", id),
+            Some(serde_json::to_string(changes)?),
+        )?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Get every change made to an order, oldest first. Works for deleted orders too
+    pub fn order_history(&self, id: i64) -> Result<Vec<OrderEvent>, AspirinEatsError> {
+        let response = self.send("GET", &format!("/orders/{}/history", id), None)?;
+        Ok(serde_json::from_str(response.body())?)
+    }
+
+    /// Bring back a deleted order, returning it as it was when it was deleted
+    pub fn restore_order(&self, id: i64) -> Result<Order, AspirinEatsError> {
+        let response = self.send("POST", &format!("/orders/{}/restore", id), None)?;
+        Ok(Order::from_str(response.body())?)
+    }
+
+    /// Mark an order as cancelled, returning the updated order
+    pub fn cancel_order(&self, id: i64) -> Result<Order, AspirinEatsError> {
+        self.update_status(id, OrderStatus::Cancelled)
+    }
+
+    /// Get the plain text receipt for an order
+    pub fn receipt(&self, id: i64) -> Result<String, AspirinEatsError> {
+        let response = self.send("GET", &format!("/orders/{}/receipt", id), None)?;
+        Ok(response.body().to_string())
+    }
+
+    /// Delete an order. It can be brought back with `restore_order` until the origin's retention
+    /// job purges it
+    pub fn remove_order(&self, id: i64) -> Result<(), AspirinEatsError> {
+        self.send("DELETE", &format!("/orders/{}", id), None)?;
+        Ok(())
+    }
+
+    /// Get the stock of every tracked ingredient
+    pub fn inventory(&self) -> Result<Vec<StockLevel>, AspirinEatsError> {
+        let response = self.send("GET", "/inventory", None)?;
+        Ok(serde_json::from_str(response.body())?)
+    }
+
+    /// Add to the stock of some ingredients, returning the new stock of every tracked ingredient
+    pub fn restock(&self, levels: &[StockLevel]) -> Result<Vec<StockLevel>, AspirinEatsError> {
+        let response = self.send(
+            "POST",
+            "/inventory/restock",
+            Some(serde_json::to_string(levels)?),
+        )?;
+        Ok(serde_json::from_str(response.body())?)
+    }
+
+    /// Send a single request and return the response if it was successful
+    ///
+    /// Errors:
+    /// - Error statuses are mapped back to the `AspirinEatsError` that produced them
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<HttpResponse, AspirinEatsError> {
+        let mut request = HttpRequest {
+            method: Some(method.to_string()),
+            path: Some(path.to_string()),
+            headers: Vec::new(),
+            body,
+        };
+        request.set_header("Host", &self.addr);
+        if let Some(actor) = &self.actor {
+            request.set_header(ACTOR_HEADER, actor);
+        }
+        if let Some(length) = request.body.as_ref().map(String::len) {
+            request.set_header("Content-Length", &length.to_string());
+        }
+
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.write_all(request.to_string().as_bytes())?;
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw)?;
+
+        let response = HttpResponse::from_str(&raw)?;
+        match response.status_code() {
+            200..=299 => Ok(response),
+            400 => Err(AspirinEatsError::InvalidRequest),
+            404 => Err(AspirinEatsError::NotFound),
+            405 => Err(AspirinEatsError::MethodNotAllowed),
+            406 => Err(AspirinEatsError::NotAcceptable),
+            413 => Err(AspirinEatsError::PayloadTooLarge),
+            409 => match response.body() {
+                body if body.starts_with("Out of stock: ") => Err(AspirinEatsError::OutOfStock(
+                    serde_json::from_str(&body["Out of stock: ".len()..]).unwrap_or_default(),
+                )),
+                body if body == AspirinEatsError::NotDeleted.to_string() => {
+                    Err(AspirinEatsError::NotDeleted)
+                }
+                body if body == AspirinEatsError::OrderFinished.to_string() => {
+                    Err(AspirinEatsError::OrderFinished)
+                }
+                _ => Err(AspirinEatsError::NotPending),
+            },
+            502 => Err(AspirinEatsError::BadGateway),
+            503 => Err(AspirinEatsError::ServiceUnavailable),
+            504 => Err(AspirinEatsError::GatewayTimeout),
+            status => Err(AspirinEatsError::UnexpectedStatus(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::Arc, thread};
+
+    use super::*;
+    use crate::{
+        db::AspirinEatsDb,
+        food::{Bun, Burger, Ingredient, MenuItem, Patty},
+        logging::AccessLog,
+        server::OriginServer,
+        shutdown::{serve, ServeOptions, Shutdown},
+    };
+
+    /// Run an origin server backed by an in-memory database for the duration of `test`
+    fn with_origin<F: FnOnce(AspirinEatsClient)>(test: F) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Arc::new(OriginServer::new(
+            AspirinEatsDb::in_memory().unwrap(),
+            AccessLog::disabled(),
+        ));
+        let shutdown = Shutdown::new();
+
+        let handle = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let handler = Arc::new(move |mut stream| {
+                    let _ = server.handle_connection(&mut stream);
+                });
+                serve(listener, &shutdown, &ServeOptions::default(), handler)
+            })
+        };
+
+        test(AspirinEatsClient::new(&addr));
+        shutdown.trigger();
+        handle.join().unwrap().unwrap();
+    }
+
+    fn order_request() -> OrderRequest {
+        OrderRequest {
+            customer: "Amit".to_string(),
+            food: vec![MenuItem::Fries, MenuItem::Drink],
+        }
+    }
+
+    #[test]
+    fn test_place_and_get_order() {
+        with_origin(|client| {
+            let placed = client.place_order(order_request()).unwrap();
+            assert_eq!(placed.id, Some(1));
+            assert_eq!(placed.total, 8.0);
+
+            assert_eq!(client.get_order(1).unwrap(), placed);
+            assert_eq!(client.list_orders().unwrap(), vec![placed]);
+        });
+    }
+
+    #[test]
+    fn test_status_updates() {
+        with_origin(|client| {
+            let id = client.place_order(order_request()).unwrap().id.unwrap();
+
+            let order = client.update_status(id, OrderStatus::Preparing).unwrap();
+            assert_eq!(order.status, OrderStatus::Preparing);
+
+            let order = client.cancel_order(id).unwrap();
+            assert_eq!(order.status, OrderStatus::Cancelled);
+        });
+    }
+
+    #[test]
+    fn test_change_items() {
+        with_origin(|client| {
+            let id = client.place_order(order_request()).unwrap().id.unwrap();
+
+            let order = client.replace_items(id, &[MenuItem::Fries]).unwrap();
+            assert_eq!(order.total, 5.0);
+            let order = client
+                .change_items(id, &[ItemChange::Add(MenuItem::Drink)])
+                .unwrap();
+            assert_eq!(order.food, vec![MenuItem::Fries, MenuItem::Drink]);
+
+            client.update_status(id, OrderStatus::Preparing).unwrap();
+            assert!(matches!(
+                client.replace_items(id, &[MenuItem::Fries]),
+                Err(AspirinEatsError::NotPending)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_restore_removed_order() {
+        with_origin(|client| {
+            let client = client.with_actor("front-desk");
+            let placed = client.place_order(order_request()).unwrap();
+            let id = placed.id.unwrap();
+            client.remove_order(id).unwrap();
+
+            let history = client.order_history(id).unwrap();
+            assert!(history.iter().all(|event| event.actor == "front-desk"));
+            assert_eq!(history.last().unwrap().before, Some(placed.clone()));
+
+            assert_eq!(client.restore_order(id).unwrap(), placed);
+            assert!(matches!(
+                client.restore_order(id),
+                Err(AspirinEatsError::NotDeleted)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_maps_error_statuses() {
+        with_origin(|client| {
+            assert!(matches!(
+                client.get_order(42),
+                Err(AspirinEatsError::NotFound)
+            ));
+
+            let id = client.place_order(order_request()).unwrap().id.unwrap();
+            client.remove_order(id).unwrap();
+            assert!(matches!(
+                client.cancel_order(id),
+                Err(AspirinEatsError::NotFound)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_out_of_stock() {
+        with_origin(|client| {
+            let patty = Ingredient::Patty(Patty::Chicken);
+            let levels = client
+                .restock(&[StockLevel {
+                    ingredient: patty.clone(),
+                    quantity: 0,
+                }])
+                .unwrap();
+            assert_eq!(client.inventory().unwrap(), levels);
+
+            let request = OrderRequest {
+                customer: "Amit".to_string(),
+                food: vec![MenuItem::Burger(Burger::new(
+                    Bun::Sesame,
+                    Patty::Chicken,
+                    Vec::new(),
+                ))],
+            };
+            match client.place_order(request) {
+                Err(AspirinEatsError::OutOfStock(missing)) => assert_eq!(missing, vec![patty]),
+                other => panic!("expected OutOfStock, got {:?}", other),
+            }
+        });
+    }
+}
@@ -0,0 +1,755 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    error::AspirinEatsError, load::Mix, retention::RetentionPolicy, shutdown::ServeOptions,
+    upstream::UpstreamOptions,
+};
+
+/// Config file used when neither `--config` nor `ASPIRIN_CONFIG` is given, if it exists
+const DEFAULT_CONFIG_FILE: &str = "aspirin.toml";
+
+/// Where a configuration value came from, so errors can point at the right place to fix it
+#[derive(Debug, PartialEq, Clone)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli(String),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "the default"),
+            Source::File(path) => write!(f, "config file {}", path.display()),
+            Source::Env(var) => write!(f, "environment variable {}", var),
+            Source::Cli(flag) => write!(f, "command line flag {}", flag),
+        }
+    }
+}
+
+/// A setting one of the binaries understands
+struct Key {
+    name: &'static str,
+    default: &'static str,
+    help: &'static str,
+}
+
+const ORIGIN_KEYS: &[Key] = &[
+    Key {
+        name: "bind",
+        default: "127.0.0.1:8080",
+        help: "Address to listen on",
+    },
+    Key {
+        name: "db_path",
+        default: "aspirin_eats.db",
+        help: "SQLite database file, created if it does not exist",
+    },
+    Key {
+        name: "workers",
+        default: "8",
+        help: "Maximum number of connections handled at once",
+    },
+    Key {
+        name: "read_timeout_ms",
+        default: "5000",
+        help: "How long to wait on a slow client, 0 to wait forever",
+    },
+    Key {
+        name: "drain_timeout_ms",
+        default: "10000",
+        help: "How long in-flight requests get to finish on shutdown",
+    },
+    Key {
+        name: "anonymize_after_days",
+        default: "",
+        help: "Remove customer names from orders this many days after they reach a status, e.g. completed=90,cancelled=90",
+    },
+    Key {
+        name: "delete_after_days",
+        default: "",
+        help: "Permanently delete orders this many days after they reach a status, e.g. completed=365",
+    },
+    Key {
+        name: "retention_interval_ms",
+        default: "3600000",
+        help: "How often to apply the retention policy, 0 to never",
+    },
+];
+
+const PROXY_KEYS: &[Key] = &[
+    Key {
+        name: "bind",
+        default: "127.0.0.1:8000",
+        help: "Address to listen on",
+    },
+    Key {
+        name: "origin",
+        default: "127.0.0.1:8080",
+        help: "Address of the origin server",
+    },
+    Key {
+        name: "workers",
+        default: "8",
+        help: "Maximum number of connections handled at once",
+    },
+    Key {
+        name: "read_timeout_ms",
+        default: "5000",
+        help: "How long to wait on a slow client, 0 to wait forever",
+    },
+    Key {
+        name: "drain_timeout_ms",
+        default: "10000",
+        help: "How long in-flight requests get to finish on shutdown",
+    },
+    Key {
+        name: "connect_timeout_ms",
+        default: "1000",
+        help: "How long to wait for a connection to the origin",
+    },
+    Key {
+        name: "upstream_timeout_ms",
+        default: "5000",
+        help: "How long to wait for the origin to answer",
+    },
+    Key {
+        name: "retries",
+        default: "2",
+        help: "Retries for failed idempotent requests",
+    },
+    Key {
+        name: "retry_backoff_ms",
+        default: "50",
+        help: "Delay before the first retry, doubled each time",
+    },
+    Key {
+        name: "breaker_threshold",
+        default: "5",
+        help: "Consecutive failures that open the circuit, 0 to disable",
+    },
+    Key {
+        name: "breaker_cooldown_ms",
+        default: "5000",
+        help: "How long the circuit stays open before probing the origin",
+    },
+];
+
+const LOAD_KEYS: &[Key] = &[
+    Key {
+        name: "target",
+        default: "127.0.0.1:8000",
+        help: "Address of the origin or proxy to load",
+    },
+    Key {
+        name: "connections",
+        default: "8",
+        help: "Number of requests in flight at once",
+    },
+    Key {
+        name: "requests",
+        default: "1000",
+        help: "Total number of requests to send",
+    },
+    Key {
+        name: "mix",
+        default: "get=70,post=20,delete=10",
+        help: "Relative weights of each kind of request",
+    },
+    Key {
+        name: "seed",
+        default: "0",
+        help: "Seed for the random requests, 0 to pick one at random",
+    },
+    Key {
+        name: "verify",
+        default: "false",
+        help: "Check the orders on the server are consistent afterwards",
+    },
+];
+
+/// Configuration for the `origin` binary
+#[derive(Debug, PartialEq, Clone)]
+pub struct OriginConfig {
+    pub bind: String,
+    pub db_path: PathBuf,
+    pub serve: ServeOptions,
+    pub retention: RetentionPolicy,
+
+    /// How often to apply `retention`, or `None` to never
+    pub retention_interval: Option<Duration>,
+}
+
+impl OriginConfig {
+    /// Build the configuration from command line arguments (without the program name), the
+    /// environment and an optional config file.
+    ///
+    /// Precedence, highest first: command line flags, `ASPIRIN_ORIGIN_*` environment variables,
+    /// the `[origin]` table of the config file, then built-in defaults.
+    pub fn load(args: &[String], env: &HashMap<String, String>) -> Result<Self, AspirinEatsError> {
+        let settings = Settings::load("origin", ORIGIN_KEYS, args, &[], env)?;
+
+        let db_path = PathBuf::from(settings.get::<String>("db_path")?);
+        if let Some(dir) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if !dir.is_dir() {
+                return Err(settings.invalid(
+                    "db_path",
+                    &format!("directory {} does not exist", dir.display()),
+                ));
+            }
+        }
+
+        let retention_interval = settings.get::<u64>("retention_interval_ms")?;
+
+        Ok(OriginConfig {
+            bind: settings.address("bind")?,
+            db_path,
+            serve: settings.serve_options()?,
+            retention: RetentionPolicy {
+                anonymize_after: settings.get("anonymize_after_days")?,
+                delete_after: settings.get("delete_after_days")?,
+            },
+            retention_interval: (retention_interval > 0)
+                .then(|| Duration::from_millis(retention_interval)),
+        })
+    }
+
+    pub fn usage() -> String {
+        usage("origin", "origin", "", ORIGIN_KEYS)
+    }
+}
+
+/// Configuration for the `proxy` binary
+#[derive(Debug, PartialEq, Clone)]
+pub struct ProxyConfig {
+    pub bind: String,
+    pub origin: String,
+    pub serve: ServeOptions,
+    pub upstream: UpstreamOptions,
+}
+
+impl ProxyConfig {
+    /// Build the configuration from command line arguments (without the program name), the
+    /// environment and an optional config file.
+    ///
+    /// For compatibility the listen and origin addresses may also be given positionally, as in
+    /// `proxy <proxy-from> <proxy-to>`. Precedence is the same as for `OriginConfig::load`, using
+    /// `ASPIRIN_PROXY_*` variables and the `[proxy]` table.
+    pub fn load(args: &[String], env: &HashMap<String, String>) -> Result<Self, AspirinEatsError> {
+        let settings = Settings::load("proxy", PROXY_KEYS, args, &["bind", "origin"], env)?;
+        Ok(ProxyConfig {
+            bind: settings.address("bind")?,
+            origin: settings.address("origin")?,
+            serve: settings.serve_options()?,
+            upstream: settings.upstream_options()?,
+        })
+    }
+
+    pub fn usage() -> String {
+        usage("proxy", "proxy", " [<proxy-from> [<proxy-to>]]", PROXY_KEYS)
+    }
+}
+
+/// Configuration for the `aspirin-load` binary
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoadConfig {
+    pub target: String,
+    pub connections: usize,
+    pub requests: usize,
+    pub mix: Mix,
+    pub seed: u64,
+    pub verify: bool,
+}
+
+impl LoadConfig {
+    /// Build the configuration in the same way as `OriginConfig::load`, using `ASPIRIN_LOAD_*`
+    /// variables and the `[load]` table.
+    pub fn load(args: &[String], env: &HashMap<String, String>) -> Result<Self, AspirinEatsError> {
+        let settings = Settings::load("load", LOAD_KEYS, args, &[], env)?;
+
+        let connections = settings.get::<usize>("connections")?;
+        if connections == 0 {
+            return Err(settings.invalid("connections", "must be at least 1"));
+        }
+        let mix = settings
+            .get::<Mix>("mix")
+            .map_err(|_| settings.invalid("mix", "expected e.g. get=70,post=20,delete=10"))?;
+
+        Ok(LoadConfig {
+            target: settings.address("target")?,
+            connections,
+            requests: settings.get("requests")?,
+            mix,
+            seed: settings.get("seed")?,
+            verify: settings.get("verify")?,
+        })
+    }
+
+    pub fn usage() -> String {
+        usage("aspirin-load", "load", "", LOAD_KEYS)
+    }
+}
+
+/// Raw configuration values merged from every source, along with where each one came from
+struct Settings {
+    section: &'static str,
+    values: BTreeMap<&'static str, (String, Source)>,
+}
+
+impl Settings {
+    fn load(
+        section: &'static str,
+        keys: &'static [Key],
+        args: &[String],
+        positional: &[&'static str],
+        env: &HashMap<String, String>,
+    ) -> Result<Self, AspirinEatsError> {
+        let mut values = keys
+            .iter()
+            .map(|key| (key.name, (key.default.to_string(), Source::Default)))
+            .collect::<BTreeMap<_, _>>();
+
+        let (config_path, cli) = parse_args(keys, args, positional)?;
+
+        // An explicitly requested config file must exist, the default one is optional
+        let config_path = config_path
+            .map(PathBuf::from)
+            .or_else(|| env.get("ASPIRIN_CONFIG").map(PathBuf::from))
+            .or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()));
+        if let Some(path) = config_path {
+            for (name, value) in read_file(&path, section, keys)? {
+                values.insert(name, (value, Source::File(path.clone())));
+            }
+        }
+
+        for key in keys {
+            let var = format!("ASPIRIN_{}_{}", section, key.name).to_uppercase();
+            if let Some(value) = env.get(&var) {
+                values.insert(key.name, (value.clone(), Source::Env(var)));
+            }
+        }
+
+        for (name, value, flag) in cli {
+            values.insert(name, (value, Source::Cli(flag)));
+        }
+
+        Ok(Settings { section, values })
+    }
+
+    /// Parse a value, turning a failure into an error that says where the bad value came from
+    fn get<T: FromStr>(&self, name: &str) -> Result<T, AspirinEatsError> {
+        let (value, _) = &self.values[name];
+        value
+            .parse()
+            .map_err(|_| self.invalid(name, &format!("expected a {}", type_hint::<T>())))
+    }
+
+    /// A `host:port` address that can be resolved
+    fn address(&self, name: &str) -> Result<String, AspirinEatsError> {
+        let (value, _) = &self.values[name];
+        if value.parse::<SocketAddr>().is_ok() {
+            return Ok(value.clone());
+        }
+        match value.to_socket_addrs().map(|mut addrs| addrs.next()) {
+            Ok(Some(_)) => Ok(value.clone()),
+            _ => Err(self.invalid(name, "expected host:port, e.g. 127.0.0.1:8080")),
+        }
+    }
+
+    fn serve_options(&self) -> Result<ServeOptions, AspirinEatsError> {
+        let workers = self.get::<usize>("workers")?;
+        if workers == 0 {
+            return Err(self.invalid("workers", "must be at least 1"));
+        }
+        let read_timeout = self.get::<u64>("read_timeout_ms")?;
+
+        Ok(ServeOptions {
+            workers,
+            read_timeout: (read_timeout > 0).then(|| Duration::from_millis(read_timeout)),
+            drain_timeout: Duration::from_millis(self.get("drain_timeout_ms")?),
+        })
+    }
+
+    fn upstream_options(&self) -> Result<UpstreamOptions, AspirinEatsError> {
+        let millis = |name| self.get::<u64>(name).map(Duration::from_millis);
+        let connect_timeout = millis("connect_timeout_ms")?;
+        if connect_timeout.is_zero() {
+            return Err(self.invalid("connect_timeout_ms", "must be at least 1"));
+        }
+        let timeout = millis("upstream_timeout_ms")?;
+        if timeout.is_zero() {
+            return Err(self.invalid("upstream_timeout_ms", "must be at least 1"));
+        }
+
+        Ok(UpstreamOptions {
+            connect_timeout,
+            timeout,
+            retries: self.get("retries")?,
+            retry_backoff: millis("retry_backoff_ms")?,
+            breaker_threshold: self.get("breaker_threshold")?,
+            breaker_cooldown: millis("breaker_cooldown_ms")?,
+        })
+    }
+
+    fn invalid(&self, name: &str, reason: &str) -> AspirinEatsError {
+        let (value, source) = &self.values[name];
+        AspirinEatsError::Config(format!(
+            "invalid {}.{} {:?} from {}: {}",
+            self.section, name, value, source, reason
+        ))
+    }
+}
+
+/// A short description of what a type parses from, for error messages
+fn type_hint<T>() -> &'static str {
+    match std::any::type_name::<T>() {
+        "usize" | "u32" | "u64" => "non-negative whole number",
+        "bool" => "true or false",
+        name if name.ends_with("AgeLimits") => "list of status=days, e.g. completed=365",
+        _ => "valid value",
+    }
+}
+
+type CliValues = Vec<(&'static str, String, String)>;
+
+/// Split command line arguments into an optional `--config` path and `(key, value, flag)` triples
+fn parse_args(
+    keys: &'static [Key],
+    args: &[String],
+    positional: &[&'static str],
+) -> Result<(Option<String>, CliValues), AspirinEatsError> {
+    let mut config = None;
+    let mut values = Vec::new();
+    let mut positional = positional.iter();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            let name = positional.next().ok_or_else(|| {
+                AspirinEatsError::Config(format!("unexpected argument {:?}", arg))
+            })?;
+            values.push((*name, arg.clone(), format!("<{}>", name)));
+            continue;
+        };
+
+        let (flag, inline) = match flag.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (flag, None),
+        };
+        let value = match inline.or_else(|| args.next().cloned()) {
+            Some(value) => value,
+            None => {
+                return Err(AspirinEatsError::Config(format!(
+                    "missing value for --{}",
+                    flag
+                )))
+            }
+        };
+
+        if flag == "config" {
+            config = Some(value);
+            continue;
+        }
+        let name = flag.replace('-', "_");
+        let key = keys.iter().find(|key| key.name == name).ok_or_else(|| {
+            AspirinEatsError::Config(format!(
+                "unknown flag --{}, expected one of: {}",
+                flag,
+                flag_names(keys)
+            ))
+        })?;
+        values.push((key.name, value, format!("--{}", flag)));
+    }
+
+    Ok((config, values))
+}
+
+/// Read the `[section]` table of a TOML config file
+fn read_file(
+    path: &Path,
+    section: &str,
+    keys: &'static [Key],
+) -> Result<Vec<(&'static str, String)>, AspirinEatsError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        AspirinEatsError::Config(format!(
+            "could not read config file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let table = toml::Table::from_str(&contents).map_err(|e| {
+        AspirinEatsError::Config(format!(
+            "config file {} is not valid TOML: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let Some(section_value) = table.get(section) else {
+        return Ok(Vec::new());
+    };
+    let section_table = section_value.as_table().ok_or_else(|| {
+        AspirinEatsError::Config(format!(
+            "[{}] in config file {} must be a table",
+            section,
+            path.display()
+        ))
+    })?;
+
+    section_table
+        .iter()
+        .map(|(name, value)| {
+            let key = keys.iter().find(|key| key.name == name).ok_or_else(|| {
+                AspirinEatsError::Config(format!(
+                    "unknown key {:?} in [{}] of config file {}, expected one of: {}",
+                    name,
+                    section,
+                    path.display(),
+                    keys.iter()
+                        .map(|key| key.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                other => {
+                    return Err(AspirinEatsError::Config(format!(
+                        "{}.{} in config file {} must be a string, integer or boolean, found {}",
+                        section,
+                        name,
+                        path.display(),
+                        other.type_str()
+                    )))
+                }
+            };
+            Ok((key.name, value))
+        })
+        .collect()
+}
+
+fn flag_names(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| format!("--{}", key.name.replace('_', "-")))
+        .chain(["--config".to_string()])
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn usage(binary: &str, section: &str, positional: &str, keys: &[Key]) -> String {
+    let mut usage = format!("Usage: {} [options]{}\n\nOptions:\n", binary, positional);
+    usage.push_str(&format!(
+        "    {:<26} TOML file with an [{}] table (env ASPIRIN_CONFIG, default {})\n",
+        "--config <file>", section, DEFAULT_CONFIG_FILE
+    ));
+    for key in keys {
+        usage.push_str(&format!(
+            "    {:<26} {} (env ASPIRIN_{}_{}, default {})\n",
+            format!("--{} <value>", key.name.replace('_', "-")),
+            key.help,
+            section.to_uppercase(),
+            key.name.to_uppercase(),
+            key.default
+        ));
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retention::AgeLimits;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Write a config file to a unique temporary path
+    fn config_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("aspirin-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = OriginConfig::load(&[], &env(&[])).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:8080");
+        assert_eq!(config.db_path, PathBuf::from("aspirin_eats.db"));
+        assert_eq!(config.serve.workers, 8);
+        assert_eq!(config.serve.read_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.serve.drain_timeout, Duration::from_secs(10));
+        assert!(config.retention.is_empty());
+        assert_eq!(config.retention_interval, Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_origin_retention() {
+        let config = OriginConfig::load(
+            &args(&["--delete-after-days", "completed=365"]),
+            &env(&[("ASPIRIN_ORIGIN_ANONYMIZE_AFTER_DAYS", "completed=90")]),
+        )
+        .unwrap();
+        assert_eq!(
+            config.retention.anonymize_after,
+            AgeLimits::from_str("completed=90").unwrap()
+        );
+        assert_eq!(
+            config.retention.delete_after,
+            AgeLimits::from_str("completed=365").unwrap()
+        );
+
+        let error = OriginConfig::load(&args(&["--delete-after-days", "forever"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("status=days"), "{}", error);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let path =
+            config_file("[origin]\nbind = \"127.0.0.1:1000\"\nworkers = 2\nread_timeout_ms = 0\n");
+        let config_arg = path.to_str().unwrap();
+
+        // File overrides defaults
+        let config = OriginConfig::load(&args(&["--config", config_arg]), &env(&[])).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:1000");
+        assert_eq!(config.serve.workers, 2);
+        assert_eq!(config.serve.read_timeout, None);
+
+        // Environment overrides the file
+        let vars = env(&[
+            ("ASPIRIN_CONFIG", config_arg),
+            ("ASPIRIN_ORIGIN_WORKERS", "3"),
+            ("ASPIRIN_ORIGIN_BIND", "127.0.0.1:2000"),
+        ]);
+        let config = OriginConfig::load(&[], &vars).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:2000");
+        assert_eq!(config.serve.workers, 3);
+
+        // Flags override the environment
+        let config = OriginConfig::load(&args(&["--workers=4"]), &vars).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:2000");
+        assert_eq!(config.serve.workers, 4);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_positional_args() {
+        let config = ProxyConfig::load(
+            &args(&["127.0.0.1:3000", "127.0.0.1:4000", "--workers", "1"]),
+            &env(&[]),
+        )
+        .unwrap();
+        assert_eq!(config.bind, "127.0.0.1:3000");
+        assert_eq!(config.origin, "127.0.0.1:4000");
+        assert_eq!(config.serve.workers, 1);
+        assert_eq!(config.upstream, UpstreamOptions::default());
+    }
+
+    #[test]
+    fn test_proxy_upstream_options() {
+        let config = ProxyConfig::load(
+            &args(&["--retries", "0", "--upstream-timeout-ms", "250"]),
+            &env(&[("ASPIRIN_PROXY_BREAKER_THRESHOLD", "0")]),
+        )
+        .unwrap();
+        assert_eq!(config.upstream.retries, 0);
+        assert_eq!(config.upstream.timeout, Duration::from_millis(250));
+        assert_eq!(config.upstream.breaker_threshold, 0);
+
+        let error = ProxyConfig::load(&args(&["--connect-timeout-ms", "0"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("proxy.connect_timeout_ms"), "{}", error);
+    }
+
+    #[test]
+    fn test_load_config() {
+        let config = LoadConfig::load(
+            &args(&["--mix", "post=1", "--verify=true"]),
+            &env(&[("ASPIRIN_LOAD_CONNECTIONS", "2")]),
+        )
+        .unwrap();
+        assert_eq!(config.target, "127.0.0.1:8000");
+        assert_eq!(config.connections, 2);
+        assert_eq!(config.mix.post, 1);
+        assert!(config.verify);
+
+        let error = LoadConfig::load(&args(&["--mix", "fetch=1"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("load.mix"), "{}", error);
+        assert!(error.contains("command line flag --mix"));
+
+        let path = config_file("[load]\nverify = true\nrequests = 50\n");
+        let config =
+            LoadConfig::load(&args(&["--config", path.to_str().unwrap()]), &env(&[])).unwrap();
+        assert!(config.verify);
+        assert_eq!(config.requests, 50);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_errors_name_their_source() {
+        let error = OriginConfig::load(&[], &env(&[("ASPIRIN_ORIGIN_WORKERS", "lots")]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("origin.workers"), "{}", error);
+        assert!(error.contains("environment variable ASPIRIN_ORIGIN_WORKERS"));
+
+        let error = OriginConfig::load(&args(&["--workers", "0"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("command line flag --workers"));
+        assert!(error.contains("at least 1"));
+
+        let error = ProxyConfig::load(&args(&["--bind", "nonsense"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("host:port"));
+
+        let error = OriginConfig::load(&args(&["--db-path", "/no/such/dir/x.db"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("/no/such/dir does not exist"));
+
+        let error = OriginConfig::load(&args(&["--port", "80"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown flag --port"));
+        assert!(error.contains("--bind"));
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let path = config_file("[origin]\nport = 8080\n");
+        let error = OriginConfig::load(&args(&["--config", path.to_str().unwrap()]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown key \"port\" in [origin]"));
+        std::fs::remove_file(path).unwrap();
+
+        let error = OriginConfig::load(&args(&["--config", "/no/such/file.toml"]), &env(&[]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("could not read config file /no/such/file.toml"));
+    }
+}
@@ -1,12 +1,30 @@
+use std::cell::RefCell;
 use std::path::Path;
-use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use rusqlite::{Connection, Result};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::types::Type;
+use rusqlite::{Connection, Result, Row};
+use serde::de::DeserializeOwned;
 
+use crate::clock::{Clock, SystemClock};
+use crate::dispatch::{Assignment, Driver};
+use crate::error::AspirinEatsError;
 use crate::food::*;
+use crate::retention::{AgeLimits, RetentionPolicy, RetentionReport, REDACTED_CUSTOMER};
+
+/// Actor recorded for changes made directly through `AspirinEatsDb` rather than the API
+pub const SYSTEM_ACTOR: &str = "system";
 
 pub struct AspirinEatsDb {
     conn: Connection,
+
+    /// Who changes are currently being made by, see `acting_as`
+    actor: RefCell<String>,
+
+    /// Where timestamps come from
+    clock: Box<dyn Clock>,
 }
 
 impl AspirinEatsDb {
@@ -18,6 +36,8 @@ impl AspirinEatsDb {
     {
         let db = Self {
             conn: Connection::open(db_path)?,
+            actor: RefCell::new(SYSTEM_ACTOR.to_string()),
+            clock: Box::new(SystemClock),
         };
         db.create_table()?;
         Ok(db)
@@ -27,12 +47,35 @@ impl AspirinEatsDb {
     pub fn in_memory() -> Result<Self> {
         let db = Self {
             conn: Connection::open_in_memory()?,
+            actor: RefCell::new(SYSTEM_ACTOR.to_string()),
+            clock: Box::new(SystemClock),
         };
         db.create_table()?;
         Ok(db)
     }
 
+    /// Take timestamps from `clock` instead of the system clock, so tests can control time
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Run `f`, recording every change it makes to an order as made by `actor`
+    pub fn acting_as<T>(&self, actor: &str, f: impl FnOnce() -> T) -> T {
+        let previous = self.actor.replace(actor.to_string());
+        let result = f();
+        self.actor.replace(previous);
+        result
+    }
+
+    /// Close the underlying database connection, flushing any pending writes
+    pub fn close(self) -> Result<()> {
+        self.conn.close().map_err(|(_, e)| e)
+    }
+
     fn create_table(&self) -> Result<()> {
+        // `deleted_at_ms` is set when an order is deleted, and such orders are left out of every
+        // query other than `restore_order` and the retention job
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS orders (
             id	        INTEGER NOT NULL,
@@ -40,48 +83,311 @@ impl AspirinEatsDb {
             food        TEXT NOT NULL,
             status	    TEXT NOT NULL,
             total       REAL NOT NULL,
+            status_changed_at_ms    INTEGER NOT NULL DEFAULT 0,
+            deleted_at_ms           INTEGER,
             PRIMARY KEY(id AUTOINCREMENT)
         )",
             [], // no params for this query
         )?;
+        // Databases created before orders were timestamped count their orders as having reached
+        // their status now, so the retention job doesn't purge them straight away
+        if self.add_column("orders", "status_changed_at_ms INTEGER NOT NULL DEFAULT 0")? {
+            self.conn.execute(
+                "UPDATE orders SET status_changed_at_ms = ?1",
+                [self.clock.now_ms()],
+            )?;
+        }
+        self.add_column("orders", "deleted_at_ms INTEGER")?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS inventory (
+            ingredient  TEXT NOT NULL,
+            quantity    INTEGER NOT NULL CHECK(quantity >= 0),
+            PRIMARY KEY(ingredient)
+        )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS drivers (
+            id          INTEGER NOT NULL,
+            name        TEXT NOT NULL,
+            PRIMARY KEY(id AUTOINCREMENT)
+        )",
+            [],
+        )?;
+        // Append-only history of every change to an order. `before` and `after` are the order as
+        // JSON, and are NULL when the order didn't exist on that side of the change
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_events (
+            id          INTEGER NOT NULL,
+            order_id    INTEGER NOT NULL,
+            kind        TEXT NOT NULL,
+            actor       TEXT NOT NULL,
+            at_ms       INTEGER NOT NULL,
+            before      TEXT,
+            after       TEXT,
+            PRIMARY KEY(id AUTOINCREMENT)
+        )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS order_events_by_order ON order_events (order_id)",
+            [],
+        )?;
+        // Orders the kitchen has finished preparing, in the order they became ready. `driver_id`
+        // is set once the order is handed to a driver, and the row is removed once it is delivered
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS deliveries (
+            seq         INTEGER NOT NULL,
+            order_id    INTEGER NOT NULL UNIQUE,
+            driver_id   INTEGER UNIQUE,
+            PRIMARY KEY(seq AUTOINCREMENT)
+        )",
+            [],
+        )?;
+        // Idempotency keys of placed orders, so a retried POST doesn't place the order twice
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS idempotency_keys (
+            key         TEXT NOT NULL,
+            order_id    INTEGER NOT NULL,
+            PRIMARY KEY(key)
+        )",
+            [],
+        )?;
         Ok(())
     }
+
+    /// Add a column to a table created by an older version, if it doesn't have it yet. Returns
+    /// whether the column was added
+    fn add_column(&self, table: &str, definition: &str) -> Result<bool> {
+        let name = definition.split_whitespace().next().unwrap_or_default();
+        let exists = self
+            .conn
+            .prepare(&format!(
+                "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+                table
+            ))?
+            .exists([name])?;
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {}", table, definition),
+                [],
+            )?;
+        }
+        Ok(!exists)
+    }
 }
 
 impl AspirinEatsDb {
     /// Insert a new Order into the database
     pub fn add_order(&self, order: Order) -> Result<i64> {
-        self.conn.execute(
-            "INSERT INTO orders (customer, food, status, total) VALUES (?1, ?2, ?3, ?4)",
-            [
-                order.customer,
-                serde_json::to_string(&order.food).expect("Failed to serialize food"),
-                serde_json::to_string(&order.status).expect("Failed to serialize status"),
-                order.total.to_string(),
-            ],
+        let tx = self.conn.unchecked_transaction()?;
+        let id = self.create_order(order)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Insert a new Order, taking the ingredients it needs out of stock
+    ///
+    /// Stock is checked and taken in the same transaction as the insert, so either the order is
+    /// placed and every ingredient is decremented, or nothing changes. Ingredients that have never
+    /// been restocked are not tracked and never run out.
+    ///
+    /// Errors:
+    /// - `OutOfStock` listing every tracked ingredient there is not enough of
+    pub fn place_order(&self, order: Order) -> std::result::Result<i64, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let missing = self.take_stock(&order.food)?;
+        if !missing.is_empty() {
+            // Dropping the transaction rolls back the stock already taken
+            return Err(AspirinEatsError::OutOfStock(missing));
+        }
+
+        let id = self.create_order(order)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Place an order as `place_order` does, unless an order has already been placed with the
+    /// same idempotency key. Returns the order as it was when it was placed, so a repeated
+    /// request gets the same answer as the first
+    ///
+    /// Errors:
+    /// - `OutOfStock` listing every tracked ingredient there is not enough of
+    pub fn place_order_once(
+        &self,
+        key: &str,
+        order: Order,
+    ) -> std::result::Result<Order, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let placed = tx.query_row(
+            "SELECT order_id FROM idempotency_keys WHERE key = ?1",
+            [key],
+            |row| row.get::<_, i64>(0),
+        );
+        let id = match placed {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                let missing = self.take_stock(&order.food)?;
+                if !missing.is_empty() {
+                    return Err(AspirinEatsError::OutOfStock(missing));
+                }
+                let id = self.create_order(order)?;
+                tx.execute(
+                    "INSERT INTO idempotency_keys (key, order_id) VALUES (?1, ?2)",
+                    (key, id),
+                )?;
+                id
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let created = self
+            .get_order_events(id)?
+            .into_iter()
+            .find(|event| event.kind == OrderEventKind::Created)
+            .and_then(|event| event.after)
+            .ok_or(AspirinEatsError::NotFound)?;
+        tx.commit()?;
+        Ok(created)
+    }
+
+    /// Replace the items of a pending order, recomputing its total and recording the change
+    ///
+    /// The ingredients of the old items go back into stock and those of the new items are taken
+    /// out, all in one transaction.
+    ///
+    /// Errors:
+    /// - `NotFound` if there is no such order
+    /// - `NotPending` if the kitchen has already started on the order
+    /// - `InvalidRequest` if the order would be left empty
+    /// - `OutOfStock` listing every tracked ingredient there is not enough of
+    pub fn modify_items(
+        &self,
+        id: i64,
+        food: Vec<MenuItem>,
+    ) -> std::result::Result<Order, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let before = self.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+        if before.status != OrderStatus::Pending {
+            return Err(AspirinEatsError::NotPending);
+        }
+        if food.is_empty() {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+
+        self.return_stock(&before.food)?;
+        let missing = self.take_stock(&food)?;
+        if !missing.is_empty() {
+            return Err(AspirinEatsError::OutOfStock(missing));
+        }
+
+        let after = Order {
+            total: total_price(&food),
+            food,
+            ..before.clone()
+        };
+        tx.execute(
+            "UPDATE orders SET food = ?1, total = ?2 WHERE id = ?3",
+            (
+                serde_json::to_string(&after.food).expect("Failed to serialize food"),
+                after.total,
+                id,
+            ),
         )?;
-        Ok(self.conn.last_insert_rowid())
+        self.record_event(
+            id,
+            OrderEventKind::ItemsChanged,
+            Some(&before),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(after)
+    }
+
+    /// Get every change made to the items of an order, oldest first
+    pub fn get_item_changes(&self, id: i64) -> Result<Vec<ItemsChangeRecord>> {
+        let changes = self
+            .get_order_events(id)?
+            .into_iter()
+            .filter(|event| event.kind == OrderEventKind::ItemsChanged)
+            .filter_map(|event| {
+                let (before, after) = (event.before?, event.after?);
+                Some(ItemsChangeRecord {
+                    changed_at_ms: event.at_ms,
+                    before: before.food,
+                    after: after.food,
+                    total_before: before.total,
+                    total_after: after.total,
+                })
+            });
+        Ok(changes.collect())
+    }
+
+    /// Get the history of an order, oldest event first. The history outlives the order, so this
+    /// still works after it has been deleted
+    pub fn get_order_events(&self, id: i64) -> Result<Vec<OrderEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, order_id, kind, actor, at_ms, before, after
+            FROM order_events WHERE order_id = ?1 ORDER BY id",
+        )?;
+        let events = stmt.query_map([id], |row| {
+            Ok(OrderEvent {
+                id: row.get(0)?,
+                order_id: row.get(1)?,
+                kind: json_column(row, 2)?,
+                actor: row.get(3)?,
+                at_ms: row.get(4)?,
+                before: optional_json_column(row, 5)?,
+                after: optional_json_column(row, 6)?,
+            })
+        })?;
+        events.collect()
+    }
+
+    /// Bring back a deleted order exactly as it was when it was deleted. An order that was not
+    /// finished takes its ingredients out of stock again, since deleting it put them back
+    ///
+    /// Errors:
+    /// - `NotDeleted` if the order still exists
+    /// - `NotFound` if there is no deleted order with this ID, including if the retention job has
+    ///   removed it for good
+    /// - `OutOfStock` if its ingredients have been used since, in which case it stays deleted
+    pub fn restore_order(&self, id: i64) -> std::result::Result<Order, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let restored = tx.execute(
+            "UPDATE orders SET deleted_at_ms = NULL WHERE id = ?1 AND deleted_at_ms IS NOT NULL",
+            [id],
+        )?;
+        let order = self.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+        if restored == 0 {
+            return Err(AspirinEatsError::NotDeleted);
+        }
+        if holds_stock(&order) {
+            let missing = self.take_stock(&order.food)?;
+            if !missing.is_empty() {
+                return Err(AspirinEatsError::OutOfStock(missing));
+            }
+        }
+
+        self.record_event(id, OrderEventKind::Restored, None, Some(&order))?;
+        tx.commit()?;
+        Ok(order)
     }
 
     /// Get an order by ID from the database
     pub fn get_order(&self, id: i64) -> Result<Option<Order>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT customer, food, status, total FROM orders WHERE id = ?1")?;
+        let mut stmt = self.conn.prepare(
+            "SELECT customer, food, status, total FROM orders
+                WHERE id = ?1 AND deleted_at_ms IS NULL",
+        )?;
         let mut rows = stmt.query([&id])?;
 
         if let Some(row) = rows.next()? {
             Ok(Some(Order {
                 id: Some(id),
                 customer: row.get(0)?,
-                food: {
-                    let food_str: String = row.get(1)?;
-                    serde_json::from_str(&food_str).expect("db should contain valid json")
-                },
-                status: {
-                    let status: String = row.get(2)?;
-                    OrderStatus::from_str(&status).expect("db should contain valid status")
-                },
+                food: json_column(row, 1)?,
+                status: json_column(row, 2)?,
                 total: row.get(3)?,
             }))
         } else {
@@ -89,52 +395,621 @@ impl AspirinEatsDb {
         }
     }
 
-    /// Remove an order by ID from the database
-    pub fn remove_order(&self, id: i64) -> Result<()> {
-        self.conn
-            .execute("DELETE FROM orders WHERE id = ?1", [&id])?;
+    /// Set the status of an order by ID. Returns `false` if there is no such order
+    ///
+    /// Cancelling an order puts its ingredients back in stock. Completed and cancelled orders are
+    /// finished: their status can't change any more, so their stock is never returned or needed
+    /// twice. Setting a finished order to the status it already has changes nothing.
+    ///
+    /// Errors:
+    /// - `OrderFinished` if the order has been completed or cancelled
+    pub fn update_status(
+        &self,
+        id: i64,
+        status: OrderStatus,
+    ) -> std::result::Result<bool, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let Some(order) = self.get_order(id)? else {
+            return Ok(false);
+        };
+
+        if matches!(
+            order.status,
+            OrderStatus::Cancelled | OrderStatus::Completed
+        ) {
+            return match status == order.status {
+                true => Ok(true),
+                false => Err(AspirinEatsError::OrderFinished),
+            };
+        }
+        if status == OrderStatus::Cancelled {
+            self.return_stock(&order.food)?;
+        }
+        tx.execute(
+            "UPDATE orders SET status = ?1, status_changed_at_ms = ?2 WHERE id = ?3",
+            (
+                serde_json::to_string(&status).expect("Failed to serialize status"),
+                self.clock.now_ms(),
+                id,
+            ),
+        )?;
+        let after = Order {
+            status,
+            ..order.clone()
+        };
+        self.record_event(
+            id,
+            OrderEventKind::StatusChanged,
+            Some(&order),
+            Some(&after),
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Add to the stock of each ingredient, starting to track any that were not tracked before
+    pub fn restock(&self, levels: &[StockLevel]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for level in levels {
+            tx.execute(
+                "INSERT INTO inventory (ingredient, quantity) VALUES (?1, ?2)
+                ON CONFLICT(ingredient) DO UPDATE SET quantity = quantity + excluded.quantity",
+                (level.ingredient.to_string(), level.quantity),
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Get the stock of every tracked ingredient
+    pub fn get_inventory(&self) -> Result<Vec<StockLevel>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT ingredient, quantity FROM inventory ORDER BY ingredient")?;
+        let levels = stmt.query_map([], |row| {
+            Ok(StockLevel {
+                ingredient: json_column(row, 0)?,
+                quantity: row.get(1)?,
+            })
+        })?;
+        levels.collect()
+    }
+
+    /// Take the ingredients needed for `food` out of stock, returning every tracked ingredient
+    /// there is not enough of. Only meant to be called inside a transaction, which must be rolled
+    /// back if anything is missing
+    fn take_stock(&self, food: &[MenuItem]) -> Result<Vec<Ingredient>> {
+        let mut missing = Vec::new();
+        for (ingredient, quantity) in required_ingredients(food) {
+            let key = ingredient.to_string();
+            let taken = self.conn.execute(
+                "UPDATE inventory SET quantity = quantity - ?2 WHERE ingredient = ?1 AND quantity >= ?2",
+                (&key, quantity),
+            )?;
+            if taken == 0 && self.stock_of(&key)?.is_some() {
+                missing.push(ingredient);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Put the ingredients needed for `food` back into stock
+    fn return_stock(&self, food: &[MenuItem]) -> Result<()> {
+        for (ingredient, quantity) in required_ingredients(food) {
+            self.conn.execute(
+                "UPDATE inventory SET quantity = quantity + ?2 WHERE ingredient = ?1",
+                (ingredient.to_string(), quantity),
+            )?;
+        }
         Ok(())
     }
 
-    /// Remove all orders from the database
+    /// Current stock of an ingredient, or `None` if it is not tracked
+    fn stock_of(&self, ingredient: &str) -> Result<Option<u32>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT quantity FROM inventory WHERE ingredient = ?1")?;
+        let mut rows = stmt.query([ingredient])?;
+        rows.next()?.map(|row| row.get(0)).transpose()
+    }
+
+    /// Delete an order by ID. It is only marked as deleted, so it can be brought back with
+    /// `restore_order` until the retention job removes it for good. It leaves the kitchen and
+    /// delivery queues straight away, freeing any driver carrying it, and an order that was not
+    /// finished puts its ingredients back in stock as if it had been cancelled
+    pub fn remove_order(&self, id: i64) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if let Some(order) = self.get_order(id)? {
+            if holds_stock(&order) {
+                self.return_stock(&order.food)?;
+            }
+            tx.execute("DELETE FROM deliveries WHERE order_id = ?1", [id])?;
+            tx.execute(
+                "UPDATE orders SET deleted_at_ms = ?1 WHERE id = ?2",
+                (self.clock.now_ms(), id),
+            )?;
+            self.record_event(id, OrderEventKind::Deleted, Some(&order), None)?;
+        }
+        tx.commit()
+    }
+
+    /// Delete every order, in the same way as `remove_order`. IDs are never handed out again, so
+    /// the history of each order stays unambiguous
     pub fn reset_orders(&self) -> Result<()> {
-        self.conn.execute("DELETE FROM orders", [])?;
-        self.conn.execute(
-            "UPDATE SQLITE_SEQUENCE SET SEQ='0' WHERE NAME='orders';",
-            [],
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM deliveries", [])?;
+        for order in self.get_all_orders()? {
+            let id = order.id.expect("orders from the db have an id");
+            if holds_stock(&order) {
+                self.return_stock(&order.food)?;
+            }
+            self.record_event(id, OrderEventKind::Deleted, Some(&order), None)?;
+        }
+        tx.execute(
+            "UPDATE orders SET deleted_at_ms = ?1 WHERE deleted_at_ms IS NULL",
+            [self.clock.now_ms()],
         )?;
-        Ok(())
+        tx.commit()
+    }
+
+    /// Anonymize and permanently delete old orders according to `policy`, including orders that
+    /// have already been deleted. Anonymizing an order also scrubs the customer's name from its
+    /// history, and permanently deleting one removes its history too
+    pub fn apply_retention(&self, policy: &RetentionPolicy) -> Result<RetentionReport> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut report = RetentionReport::default();
+
+        for id in self.orders_older_than(&policy.anonymize_after)? {
+            let anonymized = tx.execute(
+                "UPDATE orders SET customer = ?1 WHERE id = ?2 AND customer != ?1",
+                (REDACTED_CUSTOMER, id),
+            )?;
+            if anonymized > 0 {
+                self.redact_history(id)?;
+                report.anonymized += 1;
+            }
+        }
+
+        for id in self.orders_older_than(&policy.delete_after)? {
+            tx.execute("DELETE FROM deliveries WHERE order_id = ?1", [id])?;
+            tx.execute("DELETE FROM order_events WHERE order_id = ?1", [id])?;
+            tx.execute("DELETE FROM idempotency_keys WHERE order_id = ?1", [id])?;
+            report.deleted += tx.execute("DELETE FROM orders WHERE id = ?1", [id])?;
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Insert a batch of orders in a single transaction, keeping their IDs
+    ///
+    /// Orders without an ID are given a new one. If any order cannot be inserted (for example
+    /// because its ID is already taken) none of them are.
+    pub fn import_orders(&mut self, orders: &[Order]) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        for order in orders {
+            let id = self.insert_order(order)?;
+            let order = Order {
+                id: Some(id),
+                ..order.clone()
+            };
+            self.record_event(id, OrderEventKind::Created, None, Some(&order))?;
+        }
+        tx.commit()?;
+        Ok(orders.len())
+    }
+
+    /// Copy a consistent snapshot of the database to `path` using SQLite's online backup API.
+    /// Safe to call while other connections are using the database
+    pub fn backup_to<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut dest = Connection::open(path)?;
+        let backup = Backup::new(&self.conn, &mut dest)?;
+        loop {
+            // Copy every page in one step so the snapshot can't interleave with concurrent writes
+            match backup.step(-1)? {
+                StepResult::Done => return Ok(()),
+                _ => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    /// Get every order with the given status, oldest first
+    pub fn get_orders_by_status(&self, status: OrderStatus) -> Result<Vec<Order>> {
+        self.query_orders(
+            "SELECT id, customer, food, status, total FROM orders
+            WHERE status = ?1 AND deleted_at_ms IS NULL ORDER BY id",
+            [status_json(&status)],
+        )
+    }
+
+    /// Take the oldest pending order off the kitchen queue and start preparing it. Returns `None`
+    /// if the queue is empty
+    pub fn claim_next_order(&self) -> Result<Option<Order>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let Some(mut order) = self
+            .get_orders_by_status(OrderStatus::Pending)?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+
+        let before = order.clone();
+        order.status = OrderStatus::Preparing;
+        tx.execute(
+            "UPDATE orders SET status = ?1, status_changed_at_ms = ?2 WHERE id = ?3",
+            (status_json(&order.status), self.clock.now_ms(), order.id),
+        )?;
+        self.record_event(
+            order.id.expect("orders from the db have an id"),
+            OrderEventKind::StatusChanged,
+            Some(&before),
+            Some(&order),
+        )?;
+        tx.commit()?;
+        Ok(Some(order))
+    }
+
+    /// Mark an order the kitchen is preparing as ready to be picked up by a driver
+    ///
+    /// Errors:
+    /// - `NotFound` if there is no such order
+    /// - `InvalidRequest` if the order is not being prepared, or is already ready
+    pub fn mark_ready(&self, id: i64) -> std::result::Result<Order, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let order = self.get_order(id)?.ok_or(AspirinEatsError::NotFound)?;
+        if order.status != OrderStatus::Preparing {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO deliveries (order_id) VALUES (?1)",
+            [id],
+        )?;
+        if inserted == 0 {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+        tx.commit()?;
+        Ok(order)
+    }
+
+    /// Get every prepared order that is waiting for a driver, in the order they became ready
+    pub fn get_ready_orders(&self) -> Result<Vec<Order>> {
+        self.query_orders(
+            "SELECT o.id, o.customer, o.food, o.status, o.total
+            FROM deliveries d JOIN orders o ON o.id = d.order_id
+            WHERE d.driver_id IS NULL AND o.status = ?1 AND o.deleted_at_ms IS NULL
+            ORDER BY d.seq",
+            [status_json(&OrderStatus::Preparing)],
+        )
+    }
+
+    /// Add a new driver, who starts out available
+    pub fn add_driver(&self, name: &str) -> Result<i64> {
+        self.conn
+            .execute("INSERT INTO drivers (name) VALUES (?1)", [name])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Get a driver by ID, along with the order they are delivering
+    pub fn get_driver(&self, id: i64) -> Result<Option<Driver>> {
+        Ok(self
+            .get_drivers()?
+            .into_iter()
+            .find(|driver| driver.id == id))
+    }
+
+    /// Get every driver, along with the order each of them is delivering
+    pub fn get_drivers(&self) -> Result<Vec<Driver>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.id, r.name, d.order_id
+            FROM drivers r LEFT JOIN deliveries d ON d.driver_id = r.id
+            ORDER BY r.id",
+        )?;
+        let drivers = stmt.query_map([], |row| {
+            Ok(Driver {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                order: row.get(2)?,
+            })
+        })?;
+        drivers.collect()
+    }
+
+    /// Hand ready orders to available drivers, oldest order to the longest-serving driver first,
+    /// and mark them as being transported
+    pub fn dispatch(&self) -> Result<Vec<Assignment>> {
+        let tx = self.conn.unchecked_transaction()?;
+        // Orders cancelled or removed while waiting for a driver no longer need delivering
+        tx.execute(
+            "DELETE FROM deliveries WHERE driver_id IS NULL
+            AND order_id NOT IN
+                (SELECT id FROM orders WHERE status = ?1 AND deleted_at_ms IS NULL)",
+            [status_json(&OrderStatus::Preparing)],
+        )?;
+
+        let orders = self.get_ready_orders()?;
+        let drivers = self
+            .get_drivers()?
+            .into_iter()
+            .filter(|driver| driver.order.is_none());
+
+        let mut assignments = Vec::new();
+        for (before, driver) in orders.into_iter().zip(drivers) {
+            let order = before.id.expect("orders from the db have an id");
+            tx.execute(
+                "UPDATE deliveries SET driver_id = ?1 WHERE order_id = ?2",
+                [driver.id, order],
+            )?;
+            tx.execute(
+                "UPDATE orders SET status = ?1, status_changed_at_ms = ?2 WHERE id = ?3",
+                (
+                    status_json(&OrderStatus::Transporting),
+                    self.clock.now_ms(),
+                    order,
+                ),
+            )?;
+            let after = Order {
+                status: OrderStatus::Transporting,
+                ..before.clone()
+            };
+            self.record_event(
+                order,
+                OrderEventKind::StatusChanged,
+                Some(&before),
+                Some(&after),
+            )?;
+            assignments.push(Assignment {
+                order,
+                driver: driver.id,
+            });
+        }
+        tx.commit()?;
+        Ok(assignments)
+    }
+
+    /// Record that a driver has delivered their order, completing it and making them available
+    /// again. Returns the delivered order, or `None` if it has been deleted since it was
+    /// dispatched, in which case there is nothing to complete but the driver is still freed
+    ///
+    /// Errors:
+    /// - `NotFound` if there is no such driver
+    /// - `InvalidRequest` if the driver is not delivering anything
+    pub fn complete_delivery(
+        &self,
+        driver_id: i64,
+    ) -> std::result::Result<Option<Order>, AspirinEatsError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let driver = self
+            .get_driver(driver_id)?
+            .ok_or(AspirinEatsError::NotFound)?;
+        let order_id = driver.order.ok_or(AspirinEatsError::InvalidRequest)?;
+
+        tx.execute("DELETE FROM deliveries WHERE driver_id = ?1", [driver_id])?;
+        let Some(before) = self.get_order(order_id)? else {
+            tx.commit()?;
+            return Ok(None);
+        };
+
+        // An order cancelled while on the road stays cancelled
+        let completed = tx.execute(
+            "UPDATE orders SET status = ?1, status_changed_at_ms = ?2 WHERE id = ?3 AND status = ?4",
+            (
+                status_json(&OrderStatus::Completed),
+                self.clock.now_ms(),
+                order_id,
+                status_json(&OrderStatus::Transporting),
+            ),
+        )?;
+        let order = self
+            .get_order(order_id)?
+            .ok_or(AspirinEatsError::NotFound)?;
+        if completed > 0 {
+            self.record_event(
+                order_id,
+                OrderEventKind::StatusChanged,
+                Some(&before),
+                Some(&order),
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(order))
     }
 
     /// Get all orders from the database
     pub fn get_all_orders(&self) -> Result<Vec<Order>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, customer, food, status, total FROM orders")?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, customer, food, status, total FROM orders WHERE deleted_at_ms IS NULL",
+        )?;
 
         let order_iter = stmt.query_map([], |row| {
             Ok(Order {
                 id: row.get(0)?,
                 customer: row.get(1)?,
-                food: {
-                    let food_str: String = row.get(2)?;
-                    serde_json::from_str(&food_str).expect("db should contain valid json")
-                },
-                status: {
-                    let status: String = row.get(3)?;
-                    OrderStatus::from_str(&status).expect("db should contain valid status")
-                },
+                food: json_column(row, 2)?,
+                status: json_column(row, 3)?,
                 total: row.get(4)?,
             })
         })?;
 
-        Ok(order_iter.map(Result::unwrap).collect())
+        order_iter.collect()
     }
 }
 
+/// Decode a column holding JSON. A value that doesn't decode fails the query rather than
+/// panicking, so a corrupt row can't take down the thread serving it
+fn json_column<T: DeserializeOwned>(row: &Row, idx: usize) -> Result<T> {
+    let json: String = row.get(idx)?;
+    decode_json(idx, &json)
+}
+
+/// Decode a nullable column holding JSON, as `json_column` does
+fn optional_json_column<T: DeserializeOwned>(row: &Row, idx: usize) -> Result<Option<T>> {
+    let json: Option<String> = row.get(idx)?;
+    json.map(|json| decode_json(idx, &json)).transpose()
+}
+
+fn decode_json<T: DeserializeOwned>(idx: usize, json: &str) -> Result<T> {
+    serde_json::from_str(json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+impl AspirinEatsDb {
+    /// Insert a new order, ignoring any ID it has, and record its creation. Only meant to be
+    /// called inside a transaction
+    fn create_order(&self, order: Order) -> Result<i64> {
+        let id = self.insert_order(&Order { id: None, ..order })?;
+        let order = self.get_order(id)?;
+        self.record_event(id, OrderEventKind::Created, None, order.as_ref())?;
+        Ok(id)
+    }
+
+    /// Insert an order as is, keeping its ID if it has one, and return its ID
+    fn insert_order(&self, order: &Order) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO orders (id, customer, food, status, total, status_changed_at_ms)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                order.id,
+                &order.customer,
+                serde_json::to_string(&order.food).expect("Failed to serialize food"),
+                serde_json::to_string(&order.status).expect("Failed to serialize status"),
+                order.total,
+                self.clock.now_ms(),
+            ),
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Append an event to the history of an order, made by the current actor
+    fn record_event(
+        &self,
+        order_id: i64,
+        kind: OrderEventKind,
+        before: Option<&Order>,
+        after: Option<&Order>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO order_events (order_id, kind, actor, at_ms, before, after)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                order_id,
+                kind.to_string(),
+                self.actor.borrow().as_str(),
+                self.clock.now_ms(),
+                before.map(Order::to_string),
+                after.map(Order::to_string),
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// IDs of the orders, deleted or not, that have had one of the statuses in `limits` for
+    /// longer than its limit, along with deleted orders that were deleted longer ago than the
+    /// longest limit
+    ///
+    /// A deleted order can never reach another status, so without the second rule one deleted
+    /// while its status isn't listed (say, still pending) would keep the customer's name forever.
+    /// An order with a listed status is always caught by its own limit first.
+    fn orders_older_than(&self, limits: &AgeLimits) -> Result<Vec<i64>> {
+        let now = self.clock.now_ms();
+        let cutoff = |age: &Duration| now.saturating_sub(age.as_millis() as u64);
+        let mut ids = Vec::new();
+        for (status, age) in &limits.0 {
+            let mut stmt = self.conn.prepare(
+                "SELECT id FROM orders WHERE status = ?1 AND status_changed_at_ms <= ?2",
+            )?;
+            let matching = stmt.query_map((status_json(status), cutoff(age)), |row| row.get(0))?;
+            for id in matching {
+                ids.push(id?);
+            }
+        }
+
+        if let Some(longest) = limits.0.iter().map(|(_, age)| age).max() {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT id FROM orders WHERE deleted_at_ms <= ?1")?;
+            for id in stmt.query_map([cutoff(longest)], |row| row.get(0))? {
+                ids.push(id?);
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Replace the customer's name with `REDACTED_CUSTOMER` everywhere in an order's history
+    fn redact_history(&self, id: i64) -> Result<()> {
+        let redact = |order: Option<Order>| {
+            order.map(|order| {
+                Order {
+                    customer: REDACTED_CUSTOMER.to_string(),
+                    ..order
+                }
+                .to_string()
+            })
+        };
+        for event in self.get_order_events(id)? {
+            self.conn.execute(
+                "UPDATE order_events SET before = ?1, after = ?2 WHERE id = ?3",
+                (redact(event.before), redact(event.after), event.id),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run a query selecting `id, customer, food, status, total` and collect the orders it returns
+    fn query_orders<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<Order>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let orders = stmt.query_map(params, |row| {
+            Ok(Order {
+                id: row.get(0)?,
+                customer: row.get(1)?,
+                food: json_column(row, 2)?,
+                status: json_column(row, 3)?,
+                total: row.get(4)?,
+            })
+        })?;
+        orders.collect()
+    }
+}
+
+/// How an order status is stored in the `status` column
+fn status_json(status: &OrderStatus) -> String {
+    serde_json::to_string(status).expect("Failed to serialize status")
+}
+
+/// Total amount of each ingredient needed to make `food`, in the order they are first needed
+fn required_ingredients(food: &[MenuItem]) -> Vec<(Ingredient, u32)> {
+    let mut required: Vec<(Ingredient, u32)> = Vec::new();
+    for ingredient in food.iter().flat_map(MenuItem::ingredients) {
+        match required
+            .iter_mut()
+            .find(|(needed, _)| *needed == ingredient)
+        {
+            Some((_, quantity)) => *quantity += 1,
+            None => required.push((ingredient, 1)),
+        }
+    }
+    required
+}
+
+/// Whether an order's ingredients are set aside for it but not yet used up, so deleting it
+/// should put them back in stock
+fn holds_stock(order: &Order) -> bool {
+    !matches!(
+        order.status,
+        OrderStatus::Completed | OrderStatus::Cancelled
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
+    use crate::clock::ManualClock;
 
     fn get_test_order() -> Order {
         Order {
@@ -182,6 +1057,602 @@ mod tests {
         assert_eq!(got, None);
     }
 
+    #[test]
+    fn test_update_status() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+
+        assert!(db.update_status(id, OrderStatus::Transporting).unwrap());
+        let got = db.get_order(id).unwrap().unwrap();
+        assert_eq!(got.status, OrderStatus::Transporting);
+
+        assert!(!db.update_status(id + 1, OrderStatus::Completed).unwrap());
+    }
+
+    #[test]
+    fn test_import_orders_keeps_ids() {
+        let mut db = AspirinEatsDb::in_memory().unwrap();
+        let mut order1 = get_test_order();
+        order1.id = Some(7);
+        let mut order2 = get_test_order();
+        order2.id = Some(3);
+        order2.status = OrderStatus::Completed;
+
+        db.import_orders(&[order1.clone(), order2.clone()]).unwrap();
+        assert_eq!(db.get_order(7).unwrap(), Some(order1));
+        assert_eq!(db.get_order(3).unwrap(), Some(order2));
+
+        // New orders continue after the highest imported id
+        assert_eq!(db.add_order(get_test_order()).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_import_orders_is_atomic() {
+        let mut db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+
+        let mut fresh = get_test_order();
+        fresh.id = Some(id + 1);
+        let mut duplicate = get_test_order();
+        duplicate.id = Some(id);
+
+        assert!(db.import_orders(&[fresh, duplicate]).is_err());
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_backup_to() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        db.add_order(get_test_order()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("aspirin-backup-{}.db", uuid::Uuid::new_v4()));
+        db.backup_to(&path).unwrap();
+
+        let copy = AspirinEatsDb::from_path(&path).unwrap();
+        assert_eq!(copy.get_all_orders().unwrap(), db.get_all_orders().unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_opening_an_old_database_adds_columns() {
+        let path = std::env::temp_dir().join(format!("aspirin-old-{}.db", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute(
+            "CREATE TABLE orders (
+            id INTEGER NOT NULL, customer TEXT NOT NULL, food TEXT NOT NULL,
+            status TEXT NOT NULL, total REAL NOT NULL, PRIMARY KEY(id AUTOINCREMENT))",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO orders (customer, food, status, total)
+            VALUES ('Amit', '[\"Fries\"]', '\"Completed\"', 5.0)",
+            [],
+        )
+        .unwrap();
+        conn.close().unwrap();
+
+        // The order counts as having just been completed, so a year-long limit doesn't touch it
+        let db = AspirinEatsDb::from_path(&path).unwrap();
+        let policy = RetentionPolicy {
+            delete_after: AgeLimits::from_str("completed=365").unwrap(),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(db.apply_retention(&policy).unwrap().deleted, 0);
+        db.remove_order(1).unwrap();
+        assert_eq!(db.restore_order(1).unwrap().customer, "Amit");
+
+        db.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_removed_orders_are_hidden() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        let other = db.add_order(get_test_order()).unwrap();
+        db.remove_order(id).unwrap();
+
+        assert_eq!(db.get_order(id).unwrap(), None);
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+        assert_eq!(
+            db.get_orders_by_status(OrderStatus::Pending).unwrap()[0].id,
+            Some(other)
+        );
+        assert_eq!(db.claim_next_order().unwrap().unwrap().id, Some(other));
+        assert!(!db.update_status(id, OrderStatus::Cancelled).unwrap());
+    }
+
+    #[test]
+    fn test_retention() {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        let clock = ManualClock::new(1_700_000_000_000);
+        let db = AspirinEatsDb::in_memory()
+            .unwrap()
+            .with_clock(clock.clone());
+        let policy = RetentionPolicy {
+            anonymize_after: AgeLimits::from_str("completed=90,cancelled=90").unwrap(),
+            delete_after: AgeLimits::from_str("completed=365,cancelled=30").unwrap(),
+        };
+
+        let completed = db.add_order(get_test_order()).unwrap();
+        let cancelled = db.add_order(get_test_order()).unwrap();
+        let pending = db.add_order(get_test_order()).unwrap();
+        clock.advance(DAY * 10);
+        db.update_status(completed, OrderStatus::Completed).unwrap();
+        db.update_status(cancelled, OrderStatus::Cancelled).unwrap();
+        db.remove_order(completed).unwrap();
+
+        // Ages count from when the order reached its status, not when it was placed
+        clock.advance(DAY * 29);
+        assert_eq!(
+            db.apply_retention(&policy).unwrap(),
+            RetentionReport::default()
+        );
+
+        clock.advance(DAY);
+        let report = db.apply_retention(&policy).unwrap();
+        assert_eq!(report.deleted, 1);
+        assert_eq!(db.get_order(cancelled).unwrap(), None);
+        assert!(db.get_order_events(cancelled).unwrap().is_empty());
+
+        clock.advance(DAY * 60);
+        assert_eq!(db.apply_retention(&policy).unwrap().anonymized, 1);
+        assert_eq!(db.apply_retention(&policy).unwrap().anonymized, 0);
+        let events = db.get_order_events(completed).unwrap();
+        assert!(events
+            .iter()
+            .flat_map(|event| event.before.iter().chain(event.after.iter()))
+            .all(|order| order.customer == REDACTED_CUSTOMER));
+        let restored = db.restore_order(completed).unwrap();
+        assert_eq!(restored.customer, REDACTED_CUSTOMER);
+        assert_eq!(db.get_order(pending).unwrap().unwrap().customer, "Amit");
+
+        clock.advance(DAY * 275);
+        assert_eq!(db.apply_retention(&policy).unwrap().deleted, 1);
+        assert!(matches!(
+            db.restore_order(completed),
+            Err(AspirinEatsError::NotFound)
+        ));
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_retention_ages_deleted_orders_from_deletion() {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        let clock = ManualClock::new(1_700_000_000_000);
+        let db = AspirinEatsDb::in_memory()
+            .unwrap()
+            .with_clock(clock.clone());
+        let policy = RetentionPolicy {
+            anonymize_after: AgeLimits::from_str("completed=30,cancelled=90").unwrap(),
+            delete_after: AgeLimits::from_str("completed=365").unwrap(),
+        };
+
+        let deleted = db.add_order(get_test_order()).unwrap();
+        let kept = db.add_order(get_test_order()).unwrap();
+        clock.advance(DAY * 10);
+        db.remove_order(deleted).unwrap();
+
+        // Pending orders aren't listed, so the deleted one goes by the longest limit instead
+        clock.advance(DAY * 89);
+        assert_eq!(
+            db.apply_retention(&policy).unwrap(),
+            RetentionReport::default()
+        );
+
+        clock.advance(DAY);
+        assert_eq!(db.apply_retention(&policy).unwrap().anonymized, 1);
+        let events = db.get_order_events(deleted).unwrap();
+        assert!(events
+            .iter()
+            .flat_map(|event| event.before.iter().chain(event.after.iter()))
+            .all(|order| order.customer == REDACTED_CUSTOMER));
+        assert_eq!(db.get_order(kept).unwrap().unwrap().customer, "Amit");
+
+        clock.advance(DAY * 275);
+        assert_eq!(db.apply_retention(&policy).unwrap().deleted, 1);
+        assert!(db.get_order_events(deleted).unwrap().is_empty());
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+    }
+
+    fn veggie_burger_order() -> Order {
+        OrderRequest {
+            customer: "Amit".to_string(),
+            food: vec![MenuItem::Burger(Burger::new(
+                Bun::Plain,
+                Patty::Veggie,
+                vec![Topping::Cheese],
+            ))],
+        }
+        .into()
+    }
+
+    fn stock(ingredient: Ingredient, quantity: u32) -> StockLevel {
+        StockLevel {
+            ingredient,
+            quantity,
+        }
+    }
+
+    fn quantity_of(db: &AspirinEatsDb, ingredient: &Ingredient) -> u32 {
+        db.get_inventory()
+            .unwrap()
+            .into_iter()
+            .find(|level| level.ingredient == *ingredient)
+            .map(|level| level.quantity)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_corrupt_rows_fail_the_query() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        db.conn
+            .execute("UPDATE orders SET status = 'Lost' WHERE id = ?1", [id])
+            .unwrap();
+
+        assert!(matches!(
+            db.get_order(id),
+            Err(rusqlite::Error::FromSqlConversionFailure(2, Type::Text, _))
+        ));
+        assert!(matches!(
+            db.get_all_orders(),
+            Err(rusqlite::Error::FromSqlConversionFailure(3, Type::Text, _))
+        ));
+    }
+
+    #[test]
+    fn test_place_order_takes_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 2)]).unwrap();
+
+        // Untracked ingredients (the bun and cheese) never run out
+        db.place_order(veggie_burger_order()).unwrap();
+        db.place_order(veggie_burger_order()).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 0);
+
+        match db.place_order(veggie_burger_order()) {
+            Err(AspirinEatsError::OutOfStock(missing)) => assert_eq!(missing, vec![patty]),
+            other => panic!("expected OutOfStock, got {:?}", other),
+        }
+        assert_eq!(db.get_all_orders().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_place_order_is_atomic() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let bun = Ingredient::Bun(Bun::Plain);
+        let patty = Ingredient::Patty(Patty::Veggie);
+        let cheese = Ingredient::Topping(Topping::Cheese);
+        db.restock(&[
+            stock(bun.clone(), 5),
+            stock(patty.clone(), 0),
+            stock(cheese.clone(), 0),
+        ])
+        .unwrap();
+
+        match db.place_order(veggie_burger_order()) {
+            Err(AspirinEatsError::OutOfStock(missing)) => {
+                assert_eq!(missing, vec![patty, cheese])
+            }
+            other => panic!("expected OutOfStock, got {:?}", other),
+        }
+        // The bun taken before the shortage was found is put back
+        assert_eq!(quantity_of(&db, &bun), 5);
+        assert!(db.get_all_orders().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_place_order_once_per_key() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 2)]).unwrap();
+
+        let first = db.place_order_once("abc", veggie_burger_order()).unwrap();
+        db.update_status(first.id.unwrap(), OrderStatus::Preparing)
+            .unwrap();
+        let repeat = db.place_order_once("abc", veggie_burger_order()).unwrap();
+        assert_eq!(repeat, first);
+        assert_eq!(repeat.status, OrderStatus::Pending);
+        assert_eq!(db.get_all_orders().unwrap().len(), 1);
+        assert_eq!(quantity_of(&db, &patty), 1);
+
+        let other = db.place_order_once("def", veggie_burger_order()).unwrap();
+        assert_ne!(other.id, first.id);
+        assert_eq!(quantity_of(&db, &patty), 0);
+
+        // A key isn't used up by an order that couldn't be placed
+        assert!(matches!(
+            db.place_order_once("ghi", veggie_burger_order()),
+            Err(AspirinEatsError::OutOfStock(_))
+        ));
+        db.restock(&[stock(patty.clone(), 1)]).unwrap();
+        assert!(db.place_order_once("ghi", veggie_burger_order()).is_ok());
+    }
+
+    #[test]
+    fn test_cancel_restores_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 1)]).unwrap();
+
+        let id = db.place_order(veggie_burger_order()).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 0);
+
+        db.update_status(id, OrderStatus::Cancelled).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 1);
+
+        // Cancelling twice does not restock twice
+        db.update_status(id, OrderStatus::Cancelled).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 1);
+
+        // Completed orders have used their ingredients up, and can't be cancelled
+        let id = db.place_order(veggie_burger_order()).unwrap();
+        db.update_status(id, OrderStatus::Completed).unwrap();
+        assert!(matches!(
+            db.update_status(id, OrderStatus::Cancelled),
+            Err(AspirinEatsError::OrderFinished)
+        ));
+        assert_eq!(quantity_of(&db, &patty), 0);
+    }
+
+    #[test]
+    fn test_delete_and_restore_move_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 1)]).unwrap();
+
+        let id = db.place_order(veggie_burger_order()).unwrap();
+        db.claim_next_order().unwrap();
+        db.remove_order(id).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 1);
+
+        db.restore_order(id).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 0);
+
+        // Restoring fails if the ingredients have gone to another order in the meantime
+        db.remove_order(id).unwrap();
+        let other = db.place_order(veggie_burger_order()).unwrap();
+        match db.restore_order(id) {
+            Err(AspirinEatsError::OutOfStock(missing)) => assert_eq!(missing, vec![patty.clone()]),
+            other => panic!("expected OutOfStock, got {:?}", other),
+        }
+        assert!(db.get_order(id).unwrap().is_none());
+        assert_eq!(quantity_of(&db, &patty), 0);
+
+        // Finished orders have nothing to give back
+        db.update_status(other, OrderStatus::Completed).unwrap();
+        db.reset_orders().unwrap();
+        assert_eq!(quantity_of(&db, &patty), 0);
+        db.restore_order(other).unwrap();
+        assert_eq!(quantity_of(&db, &patty), 0);
+    }
+
+    #[test]
+    fn test_reset_orders_returns_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 2)]).unwrap();
+
+        db.place_order(veggie_burger_order()).unwrap();
+        db.place_order(veggie_burger_order()).unwrap();
+        db.reset_orders().unwrap();
+        assert_eq!(quantity_of(&db, &patty), 2);
+    }
+
+    #[test]
+    fn test_cancelled_orders_stay_cancelled() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Veggie);
+        db.restock(&[stock(patty.clone(), 1)]).unwrap();
+
+        let id = db.place_order(veggie_burger_order()).unwrap();
+        db.update_status(id, OrderStatus::Cancelled).unwrap();
+        assert!(matches!(
+            db.update_status(id, OrderStatus::Pending),
+            Err(AspirinEatsError::OrderFinished)
+        ));
+        assert!(matches!(
+            db.modify_items(id, vec![MenuItem::Fries]),
+            Err(AspirinEatsError::NotPending)
+        ));
+        db.update_status(id, OrderStatus::Cancelled).unwrap();
+
+        // Reviving the order would have let its stock be returned a second time
+        assert_eq!(quantity_of(&db, &patty), 1);
+        assert_eq!(
+            db.get_order(id).unwrap().unwrap().status,
+            OrderStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_restock_adds_to_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let patty = Ingredient::Patty(Patty::Beef);
+        db.restock(&[stock(patty.clone(), 3)]).unwrap();
+        db.restock(&[stock(patty.clone(), 4)]).unwrap();
+        assert_eq!(db.get_inventory().unwrap(), vec![stock(patty, 7)]);
+    }
+
+    #[test]
+    fn test_kitchen_queue_is_fifo() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let first = db.add_order(get_test_order()).unwrap();
+        let second = db.add_order(get_test_order()).unwrap();
+
+        assert_eq!(db.claim_next_order().unwrap().unwrap().id, Some(first));
+        assert_eq!(db.claim_next_order().unwrap().unwrap().id, Some(second));
+        assert_eq!(db.claim_next_order().unwrap(), None);
+        assert_eq!(
+            db.get_orders_by_status(OrderStatus::Preparing)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_dispatch_assigns_ready_orders_to_available_drivers() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let first = db.add_order(get_test_order()).unwrap();
+        let second = db.add_order(get_test_order()).unwrap();
+        let driver = db.add_driver("Dana").unwrap();
+        db.claim_next_order().unwrap();
+        db.claim_next_order().unwrap();
+
+        // The second order was ready first, so it goes out first
+        db.mark_ready(second).unwrap();
+        db.mark_ready(first).unwrap();
+        assert_eq!(
+            db.dispatch().unwrap(),
+            vec![Assignment {
+                order: second,
+                driver
+            }]
+        );
+        assert_eq!(db.get_driver(driver).unwrap().unwrap().order, Some(second));
+
+        // The only driver is busy
+        assert!(db.dispatch().unwrap().is_empty());
+
+        db.complete_delivery(driver).unwrap();
+        assert_eq!(
+            db.get_order(second).unwrap().unwrap().status,
+            OrderStatus::Completed
+        );
+        assert_eq!(
+            db.dispatch().unwrap(),
+            vec![Assignment {
+                order: first,
+                driver
+            }]
+        );
+    }
+
+    #[test]
+    fn test_completing_a_deleted_delivery_frees_the_driver() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        let driver = db.add_driver("Dana").unwrap();
+        db.claim_next_order().unwrap();
+        db.mark_ready(id).unwrap();
+        db.dispatch().unwrap();
+
+        // Deleted the way older versions did, leaving the delivery behind
+        db.conn
+            .execute("UPDATE orders SET deleted_at_ms = 1 WHERE id = ?1", [id])
+            .unwrap();
+        assert_eq!(db.complete_delivery(driver).unwrap(), None);
+        assert_eq!(db.get_driver(driver).unwrap().unwrap().order, None);
+        assert!(db.get_order(id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_deleting_an_order_frees_its_driver() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        let driver = db.add_driver("Dana").unwrap();
+        db.claim_next_order().unwrap();
+        db.mark_ready(id).unwrap();
+        db.dispatch().unwrap();
+
+        db.remove_order(id).unwrap();
+        assert_eq!(db.get_driver(driver).unwrap().unwrap().order, None);
+        assert!(matches!(
+            db.complete_delivery(driver),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+        assert!(db.get_ready_orders().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_dispatch_skips_cancelled_orders() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        db.add_driver("Dana").unwrap();
+        db.claim_next_order().unwrap();
+        db.mark_ready(id).unwrap();
+
+        db.update_status(id, OrderStatus::Cancelled).unwrap();
+        assert!(db.dispatch().unwrap().is_empty());
+        assert!(db.get_ready_orders().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_modify_items() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+
+        let order = db.modify_items(id, vec![MenuItem::Drink]).unwrap();
+        assert_eq!(order.food, vec![MenuItem::Drink]);
+        assert_eq!(order.total, 3.0);
+        assert_eq!(db.get_order(id).unwrap(), Some(order));
+
+        let changes = db.get_item_changes(id).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].before, vec![MenuItem::Fries, MenuItem::Drink]);
+        assert_eq!(changes[0].after, vec![MenuItem::Drink]);
+        assert_eq!(
+            (changes[0].total_before, changes[0].total_after),
+            (8.0, 3.0)
+        );
+
+        assert!(matches!(
+            db.modify_items(id, Vec::new()),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+        assert!(matches!(
+            db.modify_items(id + 1, vec![MenuItem::Drink]),
+            Err(AspirinEatsError::NotFound)
+        ));
+
+        db.update_status(id, OrderStatus::Preparing).unwrap();
+        assert!(matches!(
+            db.modify_items(id, vec![MenuItem::Fries]),
+            Err(AspirinEatsError::NotPending)
+        ));
+        assert_eq!(db.get_item_changes(id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_modify_items_moves_stock() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let veggie = Ingredient::Patty(Patty::Veggie);
+        let cheese = Ingredient::Topping(Topping::Cheese);
+        db.restock(&[stock(veggie.clone(), 1), stock(cheese.clone(), 0)])
+            .unwrap();
+        let id = db
+            .place_order(Order::from(OrderRequest {
+                customer: "Amit".to_string(),
+                food: vec![MenuItem::Burger(Burger::new(
+                    Bun::Plain,
+                    Patty::Veggie,
+                    Vec::new(),
+                ))],
+            }))
+            .unwrap();
+        assert_eq!(quantity_of(&db, &veggie), 0);
+
+        // Adding cheese there is none of fails without touching the order or the stock
+        let with_cheese = MenuItem::Burger(Burger::new(
+            Bun::Plain,
+            Patty::Veggie,
+            vec![Topping::Cheese],
+        ));
+        assert!(matches!(
+            db.modify_items(id, vec![with_cheese]),
+            Err(AspirinEatsError::OutOfStock(missing)) if missing == vec![cheese]
+        ));
+        assert_eq!(quantity_of(&db, &veggie), 0);
+
+        // Dropping the burger frees its patty
+        db.modify_items(id, vec![MenuItem::Fries]).unwrap();
+        assert_eq!(quantity_of(&db, &veggie), 1);
+    }
+
     #[test]
     fn test_reset_orders() {
         let db = AspirinEatsDb::in_memory().unwrap();
@@ -192,5 +1663,69 @@ mod tests {
         db.reset_orders().unwrap();
         let orders = db.get_all_orders().unwrap();
         assert_eq!(orders.len(), 0);
+
+        // IDs are not reused, so the history of the removed orders stays separate
+        assert_eq!(db.add_order(get_test_order()).unwrap(), 6);
+        assert_eq!(db.get_order_events(1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_order_events() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        db.acting_as("kitchen", || db.update_status(id, OrderStatus::Preparing))
+            .unwrap();
+        db.acting_as("amit", || db.remove_order(id)).unwrap();
+
+        let events = db.get_order_events(id).unwrap();
+        let kinds = events.iter().map(|e| e.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                OrderEventKind::Created,
+                OrderEventKind::StatusChanged,
+                OrderEventKind::Deleted
+            ]
+        );
+        let actors = events.iter().map(|e| e.actor.as_str()).collect::<Vec<_>>();
+        assert_eq!(actors, vec![SYSTEM_ACTOR, "kitchen", "amit"]);
+
+        assert_eq!(events[0].before, None);
+        assert_eq!(
+            events[1].before.as_ref().unwrap().status,
+            OrderStatus::Pending
+        );
+        assert_eq!(
+            events[1].after.as_ref().unwrap().status,
+            OrderStatus::Preparing
+        );
+        assert_eq!(events[2].before, events[1].after);
+        assert_eq!(events[2].after, None);
+        assert!(events.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+    }
+
+    #[test]
+    fn test_restore_order() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let id = db.add_order(get_test_order()).unwrap();
+        db.update_status(id, OrderStatus::Preparing).unwrap();
+        let order = db.get_order(id).unwrap().unwrap();
+
+        assert!(matches!(
+            db.restore_order(id),
+            Err(AspirinEatsError::NotDeleted)
+        ));
+        assert!(matches!(
+            db.restore_order(id + 1),
+            Err(AspirinEatsError::NotFound)
+        ));
+
+        db.reset_orders().unwrap();
+        assert_eq!(db.restore_order(id).unwrap(), order);
+        assert_eq!(db.get_order(id).unwrap(), Some(order));
+        assert_eq!(
+            db.get_order_events(id).unwrap().last().unwrap().kind,
+            OrderEventKind::Restored
+        );
     }
 }
@@ -0,0 +1,206 @@
+use display_json::{DisplayAsJson, FromStrAsJson};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::AspirinEatsDb,
+    error::AspirinEatsError,
+    food::{Order, OrderStatus},
+};
+
+/// Struct that represents a delivery driver
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct Driver {
+    /// Driver ID (unique). Generated by the SQL database
+    pub id: i64,
+
+    /// Driver Name
+    pub name: String,
+
+    /// ID of the order the driver is delivering, or `None` if they are available
+    pub order: Option<i64>,
+}
+
+/// Struct that represents an incoming request to add a driver
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct DriverRequest {
+    /// Driver Name
+    pub name: String,
+}
+
+/// Struct that represents an order being handed to a driver
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct Assignment {
+    pub order: i64,
+    pub driver: i64,
+}
+
+/// What happened during one `Simulation::tick`
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Tick {
+    /// Orders a driver finished delivering
+    pub delivered: Vec<i64>,
+
+    /// Orders the kitchen finished preparing
+    pub prepared: Vec<i64>,
+
+    /// Orders cooks took off the kitchen queue
+    pub claimed: Vec<i64>,
+
+    /// Orders handed to drivers
+    pub dispatched: Vec<Assignment>,
+}
+
+impl Tick {
+    /// Whether nothing happened, meaning the simulation has nothing left to do
+    pub fn is_idle(&self) -> bool {
+        *self == Tick::default()
+    }
+}
+
+/// Drives the kitchen and the drivers through the dispatch workflow without any staff, so the whole
+/// lifecycle of an order can be exercised in tests
+///
+/// Every tick, drivers finish their deliveries, cooks finish the orders they are preparing and
+/// claim new ones from the queue, and prepared orders are handed to available drivers. An order
+/// therefore goes `Pending` → `Preparing` → `Transporting` → `Completed` over three ticks, or
+/// longer if it has to wait for a cook or a driver.
+pub struct Simulation {
+    cooks: usize,
+}
+
+impl Simulation {
+    /// Create a simulation where the kitchen prepares up to `cooks` orders at once
+    pub fn new(cooks: usize) -> Self {
+        Simulation { cooks }
+    }
+
+    /// Advance the simulation by one step
+    pub fn tick(&self, db: &AspirinEatsDb) -> Result<Tick, AspirinEatsError> {
+        let mut tick = Tick::default();
+
+        for driver in db.get_drivers()? {
+            if let Some(order) = driver.order {
+                db.complete_delivery(driver.id)?;
+                tick.delivered.push(order);
+            }
+        }
+
+        let ready = db.get_ready_orders()?;
+        for order in db.get_orders_by_status(OrderStatus::Preparing)? {
+            if !ready.contains(&order) {
+                let id = order.id.expect("orders from the db have an id");
+                db.mark_ready(id)?;
+                tick.prepared.push(id);
+            }
+        }
+
+        for _ in 0..self.cooks {
+            match db.claim_next_order()? {
+                Some(Order { id: Some(id), .. }) => tick.claimed.push(id),
+                _ => break,
+            }
+        }
+
+        tick.dispatched = db.dispatch()?;
+        Ok(tick)
+    }
+
+    /// Tick until nothing happens any more, returning how many ticks did something
+    ///
+    /// Orders can be left behind if there is nobody to handle them, for example prepared orders
+    /// when there are no drivers.
+    pub fn run_until_idle(&self, db: &AspirinEatsDb) -> Result<usize, AspirinEatsError> {
+        let mut ticks = 0;
+        while !self.tick(db)?.is_idle() {
+            ticks += 1;
+        }
+        Ok(ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::{MenuItem, OrderRequest};
+
+    fn place_orders(db: &AspirinEatsDb, count: usize) -> Vec<i64> {
+        (0..count)
+            .map(|_| {
+                let request = OrderRequest {
+                    customer: "Amit".to_string(),
+                    food: vec![MenuItem::Fries],
+                };
+                db.place_order(request.into()).unwrap()
+            })
+            .collect()
+    }
+
+    fn status(db: &AspirinEatsDb, id: i64) -> OrderStatus {
+        db.get_order(id).unwrap().unwrap().status
+    }
+
+    #[test]
+    fn test_order_lifecycle() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let driver = db.add_driver("Dana").unwrap();
+        let [id] = place_orders(&db, 1)[..] else {
+            unreachable!()
+        };
+        let simulation = Simulation::new(1);
+
+        let tick = simulation.tick(&db).unwrap();
+        assert_eq!(tick.claimed, vec![id]);
+        assert_eq!(status(&db, id), OrderStatus::Preparing);
+
+        let tick = simulation.tick(&db).unwrap();
+        assert_eq!(tick.prepared, vec![id]);
+        assert_eq!(tick.dispatched, vec![Assignment { order: id, driver }]);
+        assert_eq!(status(&db, id), OrderStatus::Transporting);
+
+        let tick = simulation.tick(&db).unwrap();
+        assert_eq!(tick.delivered, vec![id]);
+        assert_eq!(status(&db, id), OrderStatus::Completed);
+
+        assert!(simulation.tick(&db).unwrap().is_idle());
+    }
+
+    #[test]
+    fn test_run_until_idle_completes_every_order() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        db.add_driver("Dana").unwrap();
+        db.add_driver("Sam").unwrap();
+        let ids = place_orders(&db, 5);
+
+        // Batches of two overlap, so the third batch is claimed on the third tick and delivered on
+        // the fifth
+        assert_eq!(Simulation::new(2).run_until_idle(&db).unwrap(), 5);
+        for id in ids {
+            assert_eq!(status(&db, id), OrderStatus::Completed);
+        }
+        assert!(db.get_drivers().unwrap().iter().all(|d| d.order.is_none()));
+    }
+
+    #[test]
+    fn test_stalls_without_drivers() {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let ids = place_orders(&db, 2);
+
+        Simulation::new(1).run_until_idle(&db).unwrap();
+        assert_eq!(db.get_ready_orders().unwrap().len(), 2);
+        for id in ids {
+            assert_eq!(status(&db, id), OrderStatus::Preparing);
+        }
+
+        // Once a driver turns up the backlog clears
+        db.add_driver("Dana").unwrap();
+        Simulation::new(1).run_until_idle(&db).unwrap();
+        assert!(db.get_ready_orders().unwrap().is_empty());
+    }
+}
@@ -1,5 +1,7 @@
 use thiserror;
 
+use crate::food::Ingredient;
+
 #[derive(thiserror::Error, Debug)]
 pub enum AspirinEatsError {
     /// Error when trying to parse a JSON string
@@ -18,6 +20,10 @@ pub enum AspirinEatsError {
     #[error("Invalid Request")]
     InvalidRequest,
 
+    /// Error when a request body is larger than the server will read
+    #[error("Payload too large")]
+    PayloadTooLarge,
+
     /// Error when receiving request for resource that does not exist
     #[error("Resource not found")]
     NotFound,
@@ -25,4 +31,69 @@ pub enum AspirinEatsError {
     /// Error when request is for an HTTP method not supported on that path
     #[error("Method not allowed")]
     MethodNotAllowed,
+
+    /// Error when the client accepts none of the formats a resource is available in
+    #[error("Not acceptable")]
+    NotAcceptable,
+
+    /// Error when trying to change an order the kitchen has already started on
+    #[error("Order can only be modified while pending")]
+    NotPending,
+
+    /// Error when trying to change the status of an order that has been completed or cancelled
+    #[error("Order has already been completed or cancelled")]
+    OrderFinished,
+
+    /// Error when trying to restore an order that still exists
+    #[error("Order has not been deleted")]
+    NotDeleted,
+
+    /// Error when an order needs ingredients the kitchen does not have enough of
+    #[error("Out of stock: {}", serde_json::to_string(.0).unwrap_or_default())]
+    OutOfStock(Vec<Ingredient>),
+
+    /// Error when the proxy cannot get a response from the origin server
+    #[error("Bad gateway")]
+    BadGateway,
+
+    /// Error when the proxy has stopped sending requests to a failing origin server
+    #[error("Service unavailable")]
+    ServiceUnavailable,
+
+    /// Error when the origin server does not respond to the proxy in time
+    #[error("Gateway timeout")]
+    GatewayTimeout,
+
+    /// Error when a server responds with a status the client does not know how to interpret
+    #[error("Unexpected response status {0}")]
+    UnexpectedStatus(u16),
+
+    /// Error when the configuration given to one of the binaries is invalid
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+}
+
+impl AspirinEatsError {
+    /// Name of the error variant, used to label error metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AspirinEatsError::ParseError(_) => "ParseError",
+            AspirinEatsError::Database(_) => "Database",
+            AspirinEatsError::Io(_) => "Io",
+            AspirinEatsError::InvalidRequest => "InvalidRequest",
+            AspirinEatsError::PayloadTooLarge => "PayloadTooLarge",
+            AspirinEatsError::NotFound => "NotFound",
+            AspirinEatsError::MethodNotAllowed => "MethodNotAllowed",
+            AspirinEatsError::NotAcceptable => "NotAcceptable",
+            AspirinEatsError::NotPending => "NotPending",
+            AspirinEatsError::OrderFinished => "OrderFinished",
+            AspirinEatsError::NotDeleted => "NotDeleted",
+            AspirinEatsError::OutOfStock(_) => "OutOfStock",
+            AspirinEatsError::BadGateway => "BadGateway",
+            AspirinEatsError::ServiceUnavailable => "ServiceUnavailable",
+            AspirinEatsError::GatewayTimeout => "GatewayTimeout",
+            AspirinEatsError::UnexpectedStatus(_) => "UnexpectedStatus",
+            AspirinEatsError::Config(_) => "Config",
+        }
+    }
 }
@@ -0,0 +1,189 @@
+use std::{
+    io::{BufRead, Read, Write},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::AspirinEatsError,
+    food::{Order, OrderStatus},
+};
+
+/// File formats orders can be exported to and imported from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    /// One JSON `Order` per line
+    JsonLines,
+
+    /// One row per order, with the food column holding the JSON list of menu items
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = AspirinEatsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json-lines" | "jsonlines" => Ok(Format::JsonLines),
+            "csv" => Ok(Format::Csv),
+            _ => Err(AspirinEatsError::InvalidRequest),
+        }
+    }
+}
+
+/// A flattened `Order`, since CSV cells cannot hold nested values
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    id: Option<i64>,
+    customer: String,
+    food: String,
+    status: OrderStatus,
+    total: f64,
+}
+
+/// Write orders to `writer` in the given format
+pub fn export_orders<W: Write>(
+    orders: &[Order],
+    format: Format,
+    mut writer: W,
+) -> Result<(), AspirinEatsError> {
+    match format {
+        Format::JsonLines => {
+            for order in orders {
+                writeln!(writer, "{}", order)?;
+            }
+        }
+        Format::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for order in orders {
+                csv.serialize(CsvRow {
+                    id: order.id,
+                    customer: order.customer.clone(),
+                    food: serde_json::to_string(&order.food)?,
+                    status: order.status.clone(),
+                    total: order.total,
+                })
+                .map_err(csv_error)?;
+            }
+            csv.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Read orders previously written by `export_orders`
+pub fn import_orders<R: BufRead>(
+    reader: R,
+    format: Format,
+) -> Result<Vec<Order>, AspirinEatsError> {
+    match format {
+        Format::JsonLines => reader
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(Order::from_str(&line?)?))
+            .collect(),
+        Format::Csv => read_csv(reader),
+    }
+}
+
+fn read_csv<R: Read>(reader: R) -> Result<Vec<Order>, AspirinEatsError> {
+    csv::Reader::from_reader(reader)
+        .deserialize()
+        .map(|row| {
+            let row: CsvRow = row.map_err(csv_error)?;
+            Ok(Order {
+                id: row.id,
+                customer: row.customer,
+                food: serde_json::from_str(&row.food)?,
+                status: row.status,
+                total: row.total,
+            })
+        })
+        .collect()
+}
+
+/// CSV errors are either I/O failures or malformed rows
+fn csv_error(error: csv::Error) -> AspirinEatsError {
+    if error.is_io_error() {
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => AspirinEatsError::Io(error),
+            _ => AspirinEatsError::InvalidRequest,
+        }
+    } else {
+        AspirinEatsError::InvalidRequest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::AspirinEatsDb,
+        food::{Bun, Burger, MenuItem, OrderRequest, Patty, Topping},
+    };
+
+    /// Database with a few varied orders, including gaps in the ids
+    fn seeded_db() -> AspirinEatsDb {
+        let db = AspirinEatsDb::in_memory().unwrap();
+        let requests = [
+            OrderRequest {
+                customer: "Amit".to_string(),
+                food: vec![MenuItem::Fries, MenuItem::Drink],
+            },
+            OrderRequest {
+                customer: "O'Brien, \"Pat\"".to_string(),
+                food: vec![MenuItem::Burger(Burger::new(
+                    Bun::GlutenFree,
+                    Patty::Veggie,
+                    vec![Topping::Cheese, Topping::Pickle],
+                ))],
+            },
+            OrderRequest {
+                customer: "Alice".to_string(),
+                food: vec![MenuItem::Drink],
+            },
+        ];
+        for request in requests {
+            db.add_order(request.into()).unwrap();
+        }
+        db.update_status(2, OrderStatus::Transporting).unwrap();
+        db.remove_order(1).unwrap();
+        db
+    }
+
+    fn round_trip(format: Format) {
+        let source = seeded_db();
+        let mut exported = Vec::new();
+        export_orders(&source.get_all_orders().unwrap(), format, &mut exported).unwrap();
+
+        let mut dest = AspirinEatsDb::in_memory().unwrap();
+        let orders = import_orders(exported.as_slice(), format).unwrap();
+        dest.import_orders(&orders).unwrap();
+
+        assert_eq!(
+            dest.get_all_orders().unwrap(),
+            source.get_all_orders().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() {
+        round_trip(Format::JsonLines);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        round_trip(Format::Csv);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert!(import_orders("not json\n".as_bytes(), Format::JsonLines).is_err());
+        assert!(import_orders(
+            "id,customer,food,status,total\n1,a,[],Bogus,1.0\n".as_bytes(),
+            Format::Csv
+        )
+        .is_err());
+    }
+}
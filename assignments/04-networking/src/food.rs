@@ -1,8 +1,11 @@
 use display_json::{DisplayAsJson, FromStrAsJson};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Struct that represents an order
-#[derive(Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone)]
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
 pub struct Order {
     /// Order ID (unique). Should be generated by the SQL database
     pub id: Option<i64>,
@@ -22,7 +25,9 @@ pub struct Order {
 
 /// Struct that represents an incoming order request to be added to the database. Separate from the
 /// Order struct because many of the fields will be generated for new orders
-#[derive(Deserialize, FromStrAsJson)]
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
 pub struct OrderRequest {
     /// Customer Name
     pub customer: String,
@@ -38,14 +43,114 @@ impl From<OrderRequest> for Order {
             id: None,
             customer: order_request.customer,
             status: OrderStatus::Pending,
-            total: order_request.food.iter().map(|item| item.price()).sum(),
+            total: total_price(&order_request.food),
             food: order_request.food,
         }
     }
 }
 
+/// Total price of a list of menu items
+pub fn total_price(food: &[MenuItem]) -> f64 {
+    food.iter().map(|item| item.price()).sum()
+}
+
+/// Enum that represents a single change to the items of an order
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub enum ItemChange {
+    /// Add an item to the end of the order
+    Add(MenuItem),
+
+    /// Remove the item at this position
+    Remove(usize),
+
+    /// Swap the item at `index` for another
+    Replace { index: usize, item: MenuItem },
+}
+
+impl ItemChange {
+    /// Apply the change to a list of items. Returns `false`, leaving the list untouched, if it
+    /// refers to a position that doesn't exist
+    pub fn apply(&self, food: &mut Vec<MenuItem>) -> bool {
+        match self {
+            ItemChange::Add(item) => food.push(item.clone()),
+            ItemChange::Remove(index) if *index < food.len() => {
+                food.remove(*index);
+            }
+            ItemChange::Replace { index, item } if *index < food.len() => {
+                food[*index] = item.clone()
+            }
+            ItemChange::Remove(_) | ItemChange::Replace { .. } => return false,
+        }
+        true
+    }
+}
+
+/// Struct that represents a recorded change to the items of an order
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct ItemsChangeRecord {
+    /// When the change was made, in milliseconds since the Unix epoch
+    pub changed_at_ms: u64,
+
+    /// Items before the change
+    pub before: Vec<MenuItem>,
+
+    /// Items after the change
+    pub after: Vec<MenuItem>,
+
+    /// Total price before the change
+    pub total_before: f64,
+
+    /// Total price after the change
+    pub total_after: f64,
+}
+
+/// Enum that represents what kind of change an `OrderEvent` records
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub enum OrderEventKind {
+    Created,
+    StatusChanged,
+    ItemsChanged,
+    Deleted,
+    Restored,
+}
+
+/// Struct that represents one entry in the append-only history of an order
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct OrderEvent {
+    /// Event ID (unique, increasing). Generated by the SQL database
+    pub id: i64,
+
+    /// ID of the order that changed
+    pub order_id: i64,
+
+    /// What kind of change this was
+    pub kind: OrderEventKind,
+
+    /// Who made the change. Taken from the `X-Actor` header for changes made through the API
+    pub actor: String,
+
+    /// When the change was made, in milliseconds since the Unix epoch
+    pub at_ms: u64,
+
+    /// The order before the change, or `None` if it didn't exist yet
+    pub before: Option<Order>,
+
+    /// The order after the change, or `None` if it was deleted
+    pub after: Option<Order>,
+}
+
 /// Enum that represents the status of an order
-#[derive(Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone)]
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
 pub enum OrderStatus {
     Pending,
     Preparing,
@@ -55,7 +160,7 @@ pub enum OrderStatus {
 }
 
 /// Enum that represents a particular menu item
-#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone, JsonSchema)]
 pub enum MenuItem {
     Burger(Burger),
     Fries,
@@ -63,7 +168,15 @@ pub enum MenuItem {
 }
 
 impl MenuItem {
-    fn price(&self) -> f64 {
+    /// Ingredients the kitchen uses up to make this item. Fries and drinks are not tracked
+    pub fn ingredients(&self) -> Vec<Ingredient> {
+        match self {
+            MenuItem::Burger(burger) => burger.ingredients(),
+            MenuItem::Fries | MenuItem::Drink => Vec::new(),
+        }
+    }
+
+    pub fn price(&self) -> f64 {
         match self {
             MenuItem::Burger(burger) => burger.price(),
             MenuItem::Fries => 5.0,
@@ -73,7 +186,7 @@ impl MenuItem {
 }
 
 /// Struct that represents a burger
-#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone, JsonSchema)]
 pub struct Burger {
     bun: Bun,
     patty: Patty,
@@ -89,7 +202,28 @@ impl Burger {
         }
     }
 
-    fn price(&self) -> f64 {
+    pub fn bun(&self) -> &Bun {
+        &self.bun
+    }
+
+    pub fn patty(&self) -> &Patty {
+        &self.patty
+    }
+
+    pub fn toppings(&self) -> &[Topping] {
+        &self.toppings
+    }
+
+    fn ingredients(&self) -> Vec<Ingredient> {
+        let mut ingredients = vec![
+            Ingredient::Bun(self.bun.clone()),
+            Ingredient::Patty(self.patty.clone()),
+        ];
+        ingredients.extend(self.toppings.iter().cloned().map(Ingredient::Topping));
+        ingredients
+    }
+
+    pub fn price(&self) -> f64 {
         self.bun.price()
             + self.patty.price()
             + self
@@ -101,7 +235,7 @@ impl Burger {
 }
 
 /// Enum that represents a type of bun
-#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone, JsonSchema)]
 pub enum Bun {
     Sesame,
     Plain,
@@ -109,7 +243,7 @@ pub enum Bun {
 }
 
 impl Bun {
-    fn price(&self) -> f64 {
+    pub fn price(&self) -> f64 {
         match self {
             Bun::Sesame => 1.0,
             Bun::Plain => 0.0,
@@ -119,7 +253,7 @@ impl Bun {
 }
 
 /// Enum that represents a type of patty
-#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone, JsonSchema)]
 pub enum Patty {
     Beef,
     Chicken,
@@ -127,7 +261,7 @@ pub enum Patty {
 }
 
 impl Patty {
-    fn price(&self) -> f64 {
+    pub fn price(&self) -> f64 {
         match self {
             Patty::Beef => 8.0,
             Patty::Chicken => 7.0,
@@ -137,7 +271,7 @@ impl Patty {
 }
 
 /// Enum that represents a type of topping
-#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone, JsonSchema)]
 pub enum Topping {
     Lettuce,
     Tomato,
@@ -148,7 +282,7 @@ pub enum Topping {
 }
 
 impl Topping {
-    fn price(&self) -> f64 {
+    pub fn price(&self) -> f64 {
         match self {
             Topping::Lettuce => 0.0,
             Topping::Tomato => 0.0,
@@ -160,10 +294,106 @@ impl Topping {
     }
 }
 
+/// Enum that represents a single ingredient the kitchen keeps in stock
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub enum Ingredient {
+    Bun(Bun),
+    Patty(Patty),
+    Topping(Topping),
+}
+
+/// Struct that represents how much of an ingredient is in stock
+#[derive(
+    Serialize, Deserialize, DisplayAsJson, FromStrAsJson, Debug, PartialEq, Clone, JsonSchema,
+)]
+pub struct StockLevel {
+    pub ingredient: Ingredient,
+    pub quantity: u32,
+}
+
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
+    use proptest::{collection::vec, option, prelude::*};
+
     use super::*;
 
+    fn bun() -> impl Strategy<Value = Bun> {
+        prop_oneof![Just(Bun::Sesame), Just(Bun::Plain), Just(Bun::GlutenFree)]
+    }
+
+    fn patty() -> impl Strategy<Value = Patty> {
+        prop_oneof![Just(Patty::Beef), Just(Patty::Chicken), Just(Patty::Veggie)]
+    }
+
+    fn topping() -> impl Strategy<Value = Topping> {
+        prop_oneof![
+            Just(Topping::Lettuce),
+            Just(Topping::Tomato),
+            Just(Topping::Onion),
+            Just(Topping::Pickle),
+            Just(Topping::Cheese),
+            Just(Topping::Bacon),
+        ]
+    }
+
+    fn menu_item() -> impl Strategy<Value = MenuItem> {
+        prop_oneof![
+            (bun(), patty(), vec(topping(), 0..6)).prop_map(|(bun, patty, toppings)| {
+                MenuItem::Burger(Burger::new(bun, patty, toppings))
+            }),
+            Just(MenuItem::Fries),
+            Just(MenuItem::Drink),
+        ]
+    }
+
+    fn order_status() -> impl Strategy<Value = OrderStatus> {
+        prop_oneof![
+            Just(OrderStatus::Pending),
+            Just(OrderStatus::Preparing),
+            Just(OrderStatus::Transporting),
+            Just(OrderStatus::Completed),
+            Just(OrderStatus::Cancelled),
+        ]
+    }
+
+    fn order() -> impl Strategy<Value = Order> {
+        (
+            option::of(any::<i64>()),
+            any::<String>(),
+            vec(menu_item(), 0..8),
+            order_status(),
+            // JSON has no representation for NaN or the infinities
+            any::<f64>().prop_filter("total must be finite", |total| total.is_finite()),
+        )
+            .prop_map(|(id, customer, food, status, total)| Order {
+                id,
+                customer,
+                food,
+                status,
+                total,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_order_json_round_trip(order in order()) {
+            prop_assert_eq!(Order::from_str(&order.to_string()).unwrap(), order);
+        }
+
+        #[test]
+        fn prop_order_request_json_round_trip(
+            customer in any::<String>(),
+            food in vec(menu_item(), 0..8),
+        ) {
+            let request = OrderRequest { customer, food };
+            prop_assert_eq!(OrderRequest::from_str(&request.to_string()).unwrap(), request);
+        }
+    }
+
     #[test]
     fn test_order_from_order_request() {
         let food = vec![
@@ -200,4 +430,46 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_item_change_apply() {
+        let mut food = vec![MenuItem::Fries, MenuItem::Drink];
+
+        assert!(ItemChange::Remove(0).apply(&mut food));
+        assert!(ItemChange::Add(MenuItem::Fries).apply(&mut food));
+        assert!(ItemChange::Replace {
+            index: 0,
+            item: MenuItem::Fries
+        }
+        .apply(&mut food));
+        assert_eq!(food, vec![MenuItem::Fries, MenuItem::Fries]);
+
+        assert!(!ItemChange::Remove(2).apply(&mut food));
+        assert!(!ItemChange::Replace {
+            index: 5,
+            item: MenuItem::Drink
+        }
+        .apply(&mut food));
+        assert_eq!(food.len(), 2);
+        assert_eq!(total_price(&food), 10.0);
+    }
+
+    #[test]
+    fn test_menu_item_ingredients() {
+        let burger = MenuItem::Burger(Burger::new(
+            Bun::Plain,
+            Patty::Veggie,
+            vec![Topping::Cheese, Topping::Cheese],
+        ));
+        assert_eq!(
+            burger.ingredients(),
+            vec![
+                Ingredient::Bun(Bun::Plain),
+                Ingredient::Patty(Patty::Veggie),
+                Ingredient::Topping(Topping::Cheese),
+                Ingredient::Topping(Topping::Cheese),
+            ]
+        );
+        assert!(MenuItem::Fries.ingredients().is_empty());
+    }
 }
@@ -1,7 +1,24 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+    str::FromStr,
+};
 
 use crate::error::AspirinEatsError;
 
+/// Header used to correlate a request across the proxy, the origin and the access logs
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Largest request body `read_request` accepts, in bytes
+pub const MAX_BODY_SIZE: usize = 1 << 20;
+
+/// Header naming who is making a request, recorded in the history of any order it changes
+pub const ACTOR_HEADER: &str = "X-Actor";
+
+/// Header a client can set to make a `POST /orders` safe to retry. The origin remembers every
+/// key it has placed an order for, and answers a repeat with that order instead of placing another
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
 /// Simple wrapper for an HTTP Request
 #[derive(Debug)]
 pub struct HttpRequest {
@@ -11,22 +28,73 @@ pub struct HttpRequest {
     /// The path requested by the client
     pub path: Option<String>,
 
+    /// The headers sent with the request, in the order they were received
+    pub headers: Vec<(String, String)>,
+
     /// The body of the request
     pub body: Option<String>,
 }
 
+impl HttpRequest {
+    /// Get the value of a header by name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Set a header, replacing any existing header with the same name
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+}
+
 impl FromStr for HttpRequest {
     type Err = AspirinEatsError;
 
     // Parse a string into an HTTP Request
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        todo!()
+        let (head, body) = s.split_once("\r\n\r\n").unwrap_or((s, ""));
+        let mut lines = head.split("\r\n");
+
+        let request_line = lines.next().ok_or(AspirinEatsError::InvalidRequest)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().map(str::to_string);
+        let path = parts.next().map(str::to_string);
+        if method.is_none() {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+
+        Ok(HttpRequest {
+            method,
+            path,
+            headers: parse_headers(lines)?,
+            body: (!body.is_empty()).then(|| body.to_string()),
+        })
     }
 }
 
+impl Display for HttpRequest {
+    /// Convert an HttpRequest struct to a valid HTTP Request
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} HTTP/1.1\r\n",
+            self.method.as_deref().unwrap_or("GET"),
+            self.path.as_deref().unwrap_or("/")
+        )?;
+        for (name, value) in &self.headers {
+            write!(f, "{}: {}\r\n", name, value)?;
+        }
+        write!(f, "\r\n{}", self.body.as_deref().unwrap_or(""))
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct HttpResponse {
     status_code: u16,
     status_text: String,
+    headers: Vec<(String, String)>,
     body: String,
 }
 
@@ -35,28 +103,221 @@ impl HttpResponse {
         HttpResponse {
             status_code,
             status_text: status_text.to_string(),
+            headers: Vec::new(),
             body: body.to_string(),
         }
     }
+
+    /// Add a header to the response, replacing any existing header with the same name
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The numeric status code of the response
+    pub fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    /// Get the value of a header by name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// The body of the response
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+impl FromStr for HttpResponse {
+    type Err = AspirinEatsError;
+
+    /// Parse a string into an HTTP Response
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (head, body) = s.split_once("\r\n\r\n").unwrap_or((s, ""));
+        let mut lines = head.split("\r\n");
+
+        let status_line = lines.next().ok_or(AspirinEatsError::InvalidRequest)?;
+        let mut parts = status_line.splitn(3, ' ');
+        let _version = parts.next();
+        let status_code = parts
+            .next()
+            .and_then(|code| code.parse().ok())
+            .ok_or(AspirinEatsError::InvalidRequest)?;
+        let status_text = parts.next().unwrap_or("");
+
+        Ok(HttpResponse {
+            status_code,
+            status_text: status_text.to_string(),
+            headers: parse_headers(lines)?,
+            body: body.to_string(),
+        })
+    }
 }
 
 impl Display for HttpResponse {
     /// Convert an HttpResponse struct to a valid HTTP Response
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        write!(f, "HTTP/1.1 {} {}\r\n", self.status_code, self.status_text)?;
+        for (name, value) in &self.headers {
+            write!(f, "{}: {}\r\n", name, value)?;
+        }
+        write!(f, "\r\n{}", self.body)
     }
 }
 
 impl From<AspirinEatsError> for HttpResponse {
     /// Given an error type, convert it to an appropriate HTTP Response
     fn from(value: AspirinEatsError) -> Self {
-        todo!()
+        let (status_code, status_text) = match value {
+            AspirinEatsError::ParseError(_) | AspirinEatsError::InvalidRequest => {
+                (400, "Bad Request")
+            }
+            AspirinEatsError::NotFound => (404, "Not Found"),
+            AspirinEatsError::MethodNotAllowed => (405, "Method Not Allowed"),
+            AspirinEatsError::NotAcceptable => (406, "Not Acceptable"),
+            AspirinEatsError::PayloadTooLarge => (413, "Payload Too Large"),
+            AspirinEatsError::NotPending
+            | AspirinEatsError::OrderFinished
+            | AspirinEatsError::NotDeleted
+            | AspirinEatsError::OutOfStock(_) => (409, "Conflict"),
+            AspirinEatsError::BadGateway | AspirinEatsError::UnexpectedStatus(_) => {
+                (502, "Bad Gateway")
+            }
+            AspirinEatsError::ServiceUnavailable => (503, "Service Unavailable"),
+            AspirinEatsError::GatewayTimeout => (504, "Gateway Timeout"),
+            AspirinEatsError::Database(_)
+            | AspirinEatsError::Io(_)
+            | AspirinEatsError::Config(_) => {
+                return HttpResponse::new(500, "Internal Server Error", "Internal Server Error");
+            }
+        };
+        HttpResponse::new(status_code, status_text, &value.to_string())
+    }
+}
+
+/// Read a single HTTP message head and its body from a stream
+///
+/// The body length is taken from the `Content-Length` header; if there isn't one the message is
+/// assumed to have no body. Bodies longer than `MAX_BODY_SIZE` are refused with
+/// `PayloadTooLarge` before any of them is read.
+pub fn read_request<R: Read>(stream: R) -> Result<String, AspirinEatsError> {
+    let mut reader = BufReader::new(stream);
+    let mut message = String::new();
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            if message.is_empty() {
+                return Err(AspirinEatsError::InvalidRequest);
+            }
+            break;
+        }
+        message.push_str(&line);
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let content_length = message
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .map_err(|_| AspirinEatsError::InvalidRequest)?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_SIZE {
+        return Err(AspirinEatsError::PayloadTooLarge);
     }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    message.push_str(std::str::from_utf8(&body).map_err(|_| AspirinEatsError::InvalidRequest)?);
+    Ok(message)
+}
+
+fn parse_headers<'a, I>(lines: I) -> Result<Vec<(String, String)>, AspirinEatsError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.split_once(':')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or(AspirinEatsError::InvalidRequest)
+        })
+        .collect()
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::{collection::vec, prelude::*};
+
     use super::*;
+    use crate::food::{Ingredient, Patty};
+
+    fn http_response() -> impl Strategy<Value = HttpResponse> {
+        (
+            100u16..600,
+            "[A-Za-z][A-Za-z ]{0,20}",
+            // Names can't contain a colon, and the parser trims whitespace around values
+            vec(
+                ("[A-Za-z][A-Za-z0-9-]{0,15}", "([!-~]([ -~]{0,30}[!-~])?)?"),
+                0..5,
+            ),
+            any::<String>(),
+        )
+            .prop_map(|(status_code, status_text, headers, body)| HttpResponse {
+                status_code,
+                status_text,
+                headers,
+                body,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_http_response_round_trip(response in http_response()) {
+            prop_assert_eq!(HttpResponse::from_str(&response.to_string()).unwrap(), response);
+        }
+
+        #[test]
+        fn prop_http_request_parser_does_not_panic(raw in any::<String>()) {
+            let _ = HttpRequest::from_str(&raw);
+        }
+
+        #[test]
+        fn prop_read_request_reads_a_prefix(raw in vec(any::<u8>(), 0..256)) {
+            if let Ok(message) = read_request(raw.as_slice()) {
+                prop_assert!(raw.starts_with(message.as_bytes()));
+            }
+        }
+
+        #[test]
+        fn prop_read_request_stops_after_content_length(
+            content_length in 0usize..64,
+            body in "[ -~]{0,64}",
+        ) {
+            let head = format!("POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n", content_length);
+            let raw = format!("{}{}", head, body);
+            match read_request(raw.as_bytes()) {
+                Ok(message) => prop_assert_eq!(message, format!("{}{}", head, &body[..content_length])),
+                Err(_) => prop_assert!(body.len() < content_length),
+            }
+        }
+    }
 
     #[test]
     fn test_http_request_from_str() {
@@ -64,9 +325,31 @@ mod tests {
         let http_request = HttpRequest::from_str(request).unwrap();
         assert_eq!(http_request.method, Some("GET".to_string()));
         assert_eq!(http_request.path, Some("/orders".to_string()));
+        assert_eq!(http_request.header("host"), Some("localhost:8080"));
         assert_eq!(http_request.body, Some("this is the body.".to_string()));
     }
 
+    #[test]
+    fn test_http_request_round_trip() {
+        let mut request = HttpRequest::from_str("DELETE /orders/3 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.body, None);
+
+        request.set_header(REQUEST_ID_HEADER, "abc");
+        let parsed = HttpRequest::from_str(&request.to_string()).unwrap();
+        assert_eq!(parsed.method, Some("DELETE".to_string()));
+        assert_eq!(parsed.path, Some("/orders/3".to_string()));
+        assert_eq!(parsed.header("x-request-id"), Some("abc"));
+    }
+
+    #[test]
+    fn test_http_request_malformed_header() {
+        let request = "GET / HTTP/1.1\r\nnot a header\r\n\r\n";
+        assert!(matches!(
+            HttpRequest::from_str(request),
+            Err(AspirinEatsError::InvalidRequest)
+        ));
+    }
+
     #[test]
     fn test_http_response_to_string() {
         let response = HttpResponse::new(200, "OK", "Welcome to Aspirin Eats!");
@@ -76,6 +359,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_http_response_from_str() {
+        let response = HttpResponse::new(404, "Not Found", "Resource not found")
+            .with_header(REQUEST_ID_HEADER, "abc");
+        let parsed = HttpResponse::from_str(&response.to_string()).unwrap();
+        assert_eq!(parsed, response);
+        assert_eq!(parsed.header("x-request-id"), Some("abc"));
+    }
+
+    #[test]
+    fn test_read_request_uses_content_length() {
+        let raw = "POST /orders HTTP/1.1\r\nContent-Length: 4\r\n\r\nbodytrailing";
+        let message = read_request(raw.as_bytes()).unwrap();
+        assert_eq!(
+            message,
+            "POST /orders HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody"
+        );
+    }
+
+    #[test]
+    fn test_read_request_refuses_oversized_body() {
+        let raw = "POST /orders HTTP/1.1\r\nContent-Length: 1000000000000\r\n\r\n{}";
+        assert!(matches!(
+            read_request(raw.as_bytes()),
+            Err(AspirinEatsError::PayloadTooLarge)
+        ));
+
+        let raw = format!(
+            "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(matches!(
+            read_request(raw.as_bytes()),
+            Err(AspirinEatsError::PayloadTooLarge)
+        ));
+
+        let response: HttpResponse = AspirinEatsError::PayloadTooLarge.into();
+        assert_eq!(response.status_code, 413);
+    }
+
     #[test]
     fn test_http_response_from_aspirin_eats_error() {
         let error = AspirinEatsError::InvalidRequest;
@@ -96,7 +419,13 @@ mod tests {
         assert_eq!(response.status_text, "Method Not Allowed");
         assert_eq!(response.body, "Method not allowed");
 
-        let error = AspirinEatsError::Io(std::io::Error::new(std::io::ErrorKind::Other, "test"));
+        let error = AspirinEatsError::OutOfStock(vec![Ingredient::Patty(Patty::Veggie)]);
+        let response: HttpResponse = error.into();
+        assert_eq!(response.status_code, 409);
+        assert_eq!(response.status_text, "Conflict");
+        assert_eq!(response.body, r#"Out of stock: [{"Patty":"Veggie"}]"#);
+
+        let error = AspirinEatsError::Io(std::io::Error::other("test"));
         let response: HttpResponse = error.into();
         assert_eq!(response.status_code, 500);
         assert_eq!(response.status_text, "Internal Server Error");
@@ -1,4 +1,20 @@
+pub mod api;
+pub mod client;
+pub mod clock;
+pub mod config;
 pub mod db;
+pub mod dispatch;
 pub mod error;
+pub mod export;
 pub mod food;
 pub mod http;
+pub mod load;
+pub mod logging;
+pub mod metrics;
+pub mod openapi;
+pub mod proxy;
+pub mod receipt;
+pub mod retention;
+pub mod server;
+pub mod shutdown;
+pub mod upstream;
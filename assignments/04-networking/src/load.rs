@@ -0,0 +1,495 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    client::AspirinEatsClient,
+    error::AspirinEatsError,
+    food::{Bun, Burger, MenuItem, Order, OrderRequest, Patty, Topping},
+};
+
+/// Kind of request the load generator sends
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operation {
+    /// Fetch one of the orders placed so far, or every order if there are none yet
+    Get,
+
+    /// Place a random order
+    Post,
+
+    /// Remove one of the orders placed so far, or fetch every order if there are none left
+    Delete,
+}
+
+/// Relative weights of each operation, written as e.g. `get=70,post=20,delete=10`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Mix {
+    pub get: u32,
+    pub post: u32,
+    pub delete: u32,
+}
+
+impl Mix {
+    fn pick<R: Rng>(&self, rng: &mut R) -> Operation {
+        let roll = rng.gen_range(0..self.get + self.post + self.delete);
+        if roll < self.get {
+            Operation::Get
+        } else if roll < self.get + self.post {
+            Operation::Post
+        } else {
+            Operation::Delete
+        }
+    }
+}
+
+impl FromStr for Mix {
+    type Err = AspirinEatsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Mix {
+            get: 0,
+            post: 0,
+            delete: 0,
+        };
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (operation, weight) = part
+                .split_once('=')
+                .ok_or(AspirinEatsError::InvalidRequest)?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|_| AspirinEatsError::InvalidRequest)?;
+            match operation.trim().to_lowercase().as_str() {
+                "get" => mix.get = weight,
+                "post" => mix.post = weight,
+                "delete" => mix.delete = weight,
+                _ => return Err(AspirinEatsError::InvalidRequest),
+            }
+        }
+
+        if mix.get + mix.post + mix.delete == 0 {
+            return Err(AspirinEatsError::InvalidRequest);
+        }
+        Ok(mix)
+    }
+}
+
+/// What to send and how hard
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoadOptions {
+    /// Address of the origin or proxy under test
+    pub target: String,
+
+    /// Number of requests in flight at once
+    pub connections: usize,
+
+    /// Total number of requests to send
+    pub requests: usize,
+
+    pub mix: Mix,
+
+    /// Seed for the random requests, so a run can be replayed
+    pub seed: u64,
+}
+
+/// Orders the load generator placed and removed, so the final state of the database can be checked
+#[derive(Default)]
+struct Ledger {
+    /// Orders placed and not removed, along with what was asked for
+    live: HashMap<i64, OrderRequest>,
+
+    /// Orders removed successfully
+    removed: HashSet<i64>,
+
+    /// Orders whose fate is unknown because a request about them failed
+    unknown: HashSet<i64>,
+}
+
+/// Results of a load test
+#[derive(Debug)]
+pub struct Report {
+    /// Number of requests sent of each kind
+    pub sent: HashMap<&'static str, usize>,
+
+    /// Number of requests that failed, by error kind
+    pub errors: HashMap<&'static str, usize>,
+
+    /// Wall clock time the whole run took
+    pub elapsed: Duration,
+
+    /// Latency of every request, sorted
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// Total number of requests sent
+    pub fn requests(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Completed requests per second
+    pub fn throughput(&self) -> f64 {
+        self.requests() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Latency below which `percentile`% of requests completed
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failed = self.errors.values().sum::<usize>();
+        writeln!(f, "Requests:   {} ({} failed)", self.requests(), failed)?;
+        for operation in ["GET", "POST", "DELETE"] {
+            writeln!(
+                f,
+                "  {:<8} {}",
+                operation,
+                self.sent.get(operation).unwrap_or(&0)
+            )?;
+        }
+        let mut errors = self.errors.iter().collect::<Vec<_>>();
+        errors.sort();
+        for (kind, count) in errors {
+            writeln!(f, "  {:<8} {}", kind, count)?;
+        }
+        writeln!(f, "Duration:   {:.2?}", self.elapsed)?;
+        writeln!(f, "Throughput: {:.1} requests/s", self.throughput())?;
+        write!(
+            f,
+            "Latency:    p50 {:.2?}  p95 {:.2?}  p99 {:.2?}",
+            self.percentile(50.0),
+            self.percentile(95.0),
+            self.percentile(99.0)
+        )
+    }
+}
+
+/// Load generator that replays a random mix of requests against a running server
+pub struct LoadGenerator {
+    options: LoadOptions,
+    ledger: Mutex<Ledger>,
+    initial: Vec<i64>,
+}
+
+impl LoadGenerator {
+    /// Prepare a run, recording which orders already exist so they can be told apart from the
+    /// ones this run creates
+    pub fn new(options: LoadOptions) -> Result<Self, AspirinEatsError> {
+        let initial = AspirinEatsClient::new(&options.target)
+            .list_orders()?
+            .into_iter()
+            .filter_map(|order| order.id)
+            .collect();
+        Ok(LoadGenerator {
+            options,
+            ledger: Mutex::new(Ledger::default()),
+            initial,
+        })
+    }
+
+    /// Send every request and report how it went
+    pub fn run(&self) -> Report {
+        let remaining = AtomicUsize::new(self.options.requests);
+        let start = Instant::now();
+
+        let results = thread::scope(|scope| {
+            let workers = (0..self.options.connections.max(1))
+                .map(|worker| {
+                    let remaining = &remaining;
+                    scope.spawn(move || {
+                        let mut rng =
+                            StdRng::seed_from_u64(self.options.seed.wrapping_add(worker as u64));
+                        let client = AspirinEatsClient::new(&self.options.target);
+                        let mut results = Vec::new();
+                        while remaining
+                            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                            .is_ok()
+                        {
+                            results.push(self.send(&client, &mut rng));
+                        }
+                        results
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("load generator thread panicked"))
+                .collect::<Vec<_>>()
+        });
+
+        let mut report = Report {
+            sent: HashMap::new(),
+            errors: HashMap::new(),
+            elapsed: start.elapsed(),
+            latencies: Vec::with_capacity(results.len()),
+        };
+        for (method, latency, error) in results {
+            *report.sent.entry(method).or_default() += 1;
+            if let Some(kind) = error {
+                *report.errors.entry(kind).or_default() += 1;
+            }
+            report.latencies.push(latency);
+        }
+        report.latencies.sort();
+        report
+    }
+
+    /// Check the orders on the server against what this run did to them
+    ///
+    /// Returns a description of every inconsistency found, which is empty if the database is
+    /// consistent. Orders touched by failed requests are not checked, since there is no telling
+    /// whether the server acted on them.
+    pub fn verify(&self) -> Result<Vec<String>, AspirinEatsError> {
+        let orders = AspirinEatsClient::new(&self.options.target)
+            .list_orders()?
+            .into_iter()
+            .filter_map(|order| Some((order.id?, order)))
+            .collect::<HashMap<_, _>>();
+        let ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+        let mut problems = Vec::new();
+
+        for (id, request) in &ledger.live {
+            let mut expected = Order::from(request.clone());
+            expected.id = Some(*id);
+            match orders.get(id) {
+                None => problems.push(format!("order {} was placed but is missing", id)),
+                Some(order) if *order != expected => problems.push(format!(
+                    "order {} is {} but was placed as {}",
+                    id, order, expected
+                )),
+                Some(_) => {}
+            }
+        }
+        for id in &ledger.removed {
+            if orders.contains_key(id) {
+                problems.push(format!("order {} was removed but still exists", id));
+            }
+        }
+        for id in orders.keys() {
+            let known = self.initial.contains(id)
+                || ledger.live.contains_key(id)
+                || ledger.unknown.contains(id);
+            if !known {
+                problems.push(format!("order {} was never placed", id));
+            }
+        }
+
+        problems.sort();
+        Ok(problems)
+    }
+
+    /// Send a single random request, returning its method, latency and the kind of error if it
+    /// failed
+    fn send(
+        &self,
+        client: &AspirinEatsClient,
+        rng: &mut StdRng,
+    ) -> (&'static str, Duration, Option<&'static str>) {
+        let start = Instant::now();
+        let (method, result) = match self.options.mix.pick(rng) {
+            Operation::Get => match self.random_live_order(rng) {
+                Some(id) => ("GET", client.get_order(id).map(drop)),
+                None => ("GET", client.list_orders().map(drop)),
+            },
+            Operation::Post => {
+                let request = random_order_request(rng);
+                let result = client.place_order(request.clone());
+                if let Ok(Order { id: Some(id), .. }) = result {
+                    self.ledger().live.insert(id, request);
+                }
+                ("POST", result.map(drop))
+            }
+            Operation::Delete => match self.take_live_order(rng) {
+                Some(id) => {
+                    let result = client.remove_order(id);
+                    let mut ledger = self.ledger();
+                    match &result {
+                        Ok(()) => ledger.removed.insert(id),
+                        Err(_) => ledger.unknown.insert(id),
+                    };
+                    ("DELETE", result)
+                }
+                None => ("GET", client.list_orders().map(drop)),
+            },
+        };
+        (
+            method,
+            start.elapsed(),
+            result.err().map(|error| error.kind()),
+        )
+    }
+
+    fn random_live_order(&self, rng: &mut StdRng) -> Option<i64> {
+        let ledger = self.ledger();
+        let ids = ledger.live.keys().copied().collect::<Vec<_>>();
+        ids.choose(rng).copied()
+    }
+
+    /// Pick a live order and take it out of the ledger, so no other thread removes it too
+    fn take_live_order(&self, rng: &mut StdRng) -> Option<i64> {
+        let mut ledger = self.ledger();
+        let ids = ledger.live.keys().copied().collect::<Vec<_>>();
+        let id = *ids.choose(rng)?;
+        ledger.live.remove(&id);
+        Some(id)
+    }
+
+    fn ledger(&self) -> std::sync::MutexGuard<'_, Ledger> {
+        self.ledger.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A random order of one to four items
+pub fn random_order_request<R: Rng>(rng: &mut R) -> OrderRequest {
+    const CUSTOMERS: &[&str] = &["Amit", "Alice", "Bob", "Dana", "Sam", "Priya"];
+    const BUNS: &[Bun] = &[Bun::Sesame, Bun::Plain, Bun::GlutenFree];
+    const PATTIES: &[Patty] = &[Patty::Beef, Patty::Chicken, Patty::Veggie];
+    const TOPPINGS: &[Topping] = &[
+        Topping::Lettuce,
+        Topping::Tomato,
+        Topping::Onion,
+        Topping::Pickle,
+        Topping::Cheese,
+        Topping::Bacon,
+    ];
+
+    let food = (0..rng.gen_range(1..=4))
+        .map(|_| match rng.gen_range(0..3) {
+            0 => MenuItem::Burger(Burger::new(
+                BUNS.choose(rng).unwrap().clone(),
+                PATTIES.choose(rng).unwrap().clone(),
+                {
+                    let toppings = rng.gen_range(0..=3);
+                    TOPPINGS.choose_multiple(rng, toppings).cloned().collect()
+                },
+            )),
+            1 => MenuItem::Fries,
+            _ => MenuItem::Drink,
+        })
+        .collect();
+
+    OrderRequest {
+        customer: CUSTOMERS.choose(rng).unwrap().to_string(),
+        food,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::Arc};
+
+    use super::*;
+    use crate::{
+        db::AspirinEatsDb,
+        logging::AccessLog,
+        server::OriginServer,
+        shutdown::{serve, ServeOptions, Shutdown},
+    };
+
+    #[test]
+    fn test_mix_from_str() {
+        assert_eq!(
+            Mix::from_str("get=70, post=20,delete=10").unwrap(),
+            Mix {
+                get: 70,
+                post: 20,
+                delete: 10
+            }
+        );
+        assert_eq!(
+            Mix::from_str("POST=1").unwrap(),
+            Mix {
+                get: 0,
+                post: 1,
+                delete: 0
+            }
+        );
+        assert!(Mix::from_str("get=0").is_err());
+        assert!(Mix::from_str("put=5").is_err());
+        assert!(Mix::from_str("get").is_err());
+    }
+
+    #[test]
+    fn test_mix_pick_follows_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mix = Mix::from_str("get=1,post=0,delete=3").unwrap();
+        let picks = (0..1000).map(|_| mix.pick(&mut rng)).collect::<Vec<_>>();
+
+        assert!(!picks.contains(&Operation::Post));
+        let gets = picks.iter().filter(|op| **op == Operation::Get).count();
+        assert!((150..350).contains(&gets), "{} gets", gets);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let report = Report {
+            sent: HashMap::new(),
+            errors: HashMap::new(),
+            elapsed: Duration::from_secs(2),
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+        };
+        assert_eq!(report.throughput(), 50.0);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(report.percentile(0.0), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_run_against_origin() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let db = AspirinEatsDb::in_memory().unwrap();
+        // An order that exists before the run must not count as an inconsistency
+        db.add_order(random_order_request(&mut StdRng::seed_from_u64(1)).into())
+            .unwrap();
+        let server = Arc::new(OriginServer::new(db, AccessLog::disabled()));
+        let shutdown = Shutdown::new();
+        let handle = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let handler = Arc::new(move |mut stream| {
+                    let _ = server.handle_connection(&mut stream);
+                });
+                serve(listener, &shutdown, &ServeOptions::default(), handler)
+            })
+        };
+
+        let generator = LoadGenerator::new(LoadOptions {
+            target: addr,
+            connections: 4,
+            requests: 200,
+            mix: Mix::from_str("get=4,post=4,delete=2").unwrap(),
+            seed: 7,
+        })
+        .unwrap();
+        let report = generator.run();
+
+        assert_eq!(report.requests(), 200);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.sent["POST"] > 0);
+        assert!(report.sent["DELETE"] > 0);
+        assert!(report.percentile(50.0) <= report.percentile(99.0));
+        assert_eq!(generator.verify().unwrap(), Vec::<String>::new());
+
+        shutdown.trigger();
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::Write,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use display_json::DisplayAsJson;
+use serde::{Deserialize, Serialize};
+
+/// A single line of the access log
+#[derive(Serialize, Deserialize, DisplayAsJson, Debug, PartialEq, Clone)]
+pub struct AccessLogEntry {
+    /// Milliseconds since the Unix epoch when the request finished
+    pub timestamp_ms: u128,
+
+    /// Which binary handled the request ("origin" or "proxy")
+    pub service: String,
+
+    /// Request ID shared between the proxy and the origin
+    pub request_id: String,
+
+    /// HTTP method of the request
+    pub method: String,
+
+    /// Path of the request
+    pub path: String,
+
+    /// Status code sent back to the client
+    pub status: u16,
+
+    /// Time taken to produce the response, in milliseconds
+    pub latency_ms: f64,
+}
+
+impl AccessLogEntry {
+    pub fn new(
+        service: &str,
+        request_id: &str,
+        method: &str,
+        path: &str,
+        status: u16,
+        latency: Duration,
+    ) -> Self {
+        AccessLogEntry {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_millis())
+                .unwrap_or_default(),
+            service: service.to_string(),
+            request_id: request_id.to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            latency_ms: latency.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Writes access log entries as JSON lines to some output
+pub struct AccessLog {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Create an access log that writes to the given output
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        AccessLog {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Create an access log that writes to stdout
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+
+    /// Create an access log that discards everything. Useful for testing
+    pub fn disabled() -> Self {
+        Self::new(std::io::sink())
+    }
+
+    /// Write a single entry to the log. Failing to log never fails the request
+    pub fn record(&self, entry: &AccessLogEntry) {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(writer, "{}", entry).and_then(|_| writer.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use super::*;
+
+    /// Writer that shares its buffer so tests can inspect what was logged
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_writes_json_lines() {
+        let buffer = SharedBuffer::default();
+        let log = AccessLog::new(buffer.clone());
+
+        let entry = AccessLogEntry::new(
+            "origin",
+            "abc",
+            "GET",
+            "/orders",
+            200,
+            Duration::from_millis(3),
+        );
+        log.record(&entry);
+        log.record(&entry);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+
+        let parsed: AccessLogEntry = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed, entry);
+        assert_eq!(
+            serde_json::Value::from_str(lines[1]).unwrap()["request_id"],
+            "abc"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use crate::error::AspirinEatsError;
+
+/// Upper bounds (in seconds) of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Methods that get their own label. Anything else a client sends is counted as `OTHER`, so
+/// clients can't create new series at will
+const KNOWN_METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// Cumulative latency histogram for a single route
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct MetricsInner {
+    /// Request counts keyed by (method, route, status)
+    requests: BTreeMap<(&'static str, String, u16), u64>,
+
+    /// Latency histograms keyed by route
+    latency: BTreeMap<String, Histogram>,
+
+    /// Error counts keyed by `AspirinEatsError` variant
+    errors: BTreeMap<&'static str, u64>,
+}
+
+/// Request counters, latency histograms and error counts, rendered in the Prometheus text format
+pub struct Metrics {
+    prefix: &'static str,
+    inner: Mutex<MetricsInner>,
+}
+
+impl Metrics {
+    /// Create an empty set of metrics whose names all start with `prefix`
+    pub fn new(prefix: &'static str) -> Self {
+        Metrics {
+            prefix,
+            inner: Mutex::new(MetricsInner::default()),
+        }
+    }
+
+    /// Record a completed request. Methods other than the standard ones are labelled `OTHER`
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let method = KNOWN_METHODS
+            .into_iter()
+            .find(|known| *known == method)
+            .unwrap_or("OTHER");
+        let mut inner = self.lock();
+        *inner
+            .requests
+            .entry((method, route.to_string(), status))
+            .or_default() += 1;
+        inner
+            .latency
+            .entry(route.to_string())
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Record an error returned while handling a request
+    pub fn observe_error(&self, error: &AspirinEatsError) {
+        *self.lock().errors.entry(error.kind()).or_default() += 1;
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let inner = self.lock();
+        let prefix = self.prefix;
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_requests_total Total HTTP requests handled"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_requests_total counter");
+        for ((method, route, status), count) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "{prefix}_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_request_duration_seconds HTTP request latency"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_request_duration_seconds histogram");
+        for (route, histogram) in &inner.latency {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "{prefix}_request_duration_seconds_bucket{{route=\"{route}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{prefix}_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{prefix}_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{prefix}_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                histogram.count
+            );
+        }
+
+        let _ = writeln!(out, "# HELP {prefix}_errors_total Errors by kind");
+        let _ = writeln!(out, "# TYPE {prefix}_errors_total counter");
+        for (kind, count) in &inner.errors {
+            let _ = writeln!(out, "{prefix}_errors_total{{kind=\"{kind}\"}} {count}");
+        }
+
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_counters() {
+        let metrics = Metrics::new("test");
+        metrics.observe_request("GET", "/orders", 200, Duration::from_millis(2));
+        metrics.observe_request("GET", "/orders", 200, Duration::from_millis(2));
+        metrics.observe_request("POST", "/orders", 201, Duration::from_millis(2));
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("test_requests_total{method=\"GET\",route=\"/orders\",status=\"200\"} 2"));
+        assert!(rendered
+            .contains("test_requests_total{method=\"POST\",route=\"/orders\",status=\"201\"} 1"));
+    }
+
+    #[test]
+    fn test_unknown_methods_share_a_label() {
+        let metrics = Metrics::new("test");
+        for method in ["BREW", "get", "", "X-1"] {
+            metrics.observe_request(method, "/", 405, Duration::from_millis(1));
+        }
+
+        let rendered = metrics.render();
+        assert!(
+            rendered.contains("test_requests_total{method=\"OTHER\",route=\"/\",status=\"405\"} 4")
+        );
+        assert!(!rendered.contains("BREW"));
+    }
+
+    #[test]
+    fn test_latency_histogram_is_cumulative() {
+        let metrics = Metrics::new("test");
+        metrics.observe_request("GET", "/", 200, Duration::from_micros(100));
+        metrics.observe_request("GET", "/", 200, Duration::from_millis(20));
+
+        let rendered = metrics.render();
+        assert!(
+            rendered.contains("test_request_duration_seconds_bucket{route=\"/\",le=\"0.0005\"} 1")
+        );
+        assert!(
+            rendered.contains("test_request_duration_seconds_bucket{route=\"/\",le=\"0.025\"} 2")
+        );
+        assert!(
+            rendered.contains("test_request_duration_seconds_bucket{route=\"/\",le=\"+Inf\"} 2")
+        );
+        assert!(rendered.contains("test_request_duration_seconds_count{route=\"/\"} 2"));
+    }
+
+    #[test]
+    fn test_error_counts_by_variant() {
+        let metrics = Metrics::new("test");
+        metrics.observe_error(&AspirinEatsError::NotFound);
+        metrics.observe_error(&AspirinEatsError::NotFound);
+        metrics.observe_error(&AspirinEatsError::InvalidRequest);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("test_errors_total{kind=\"NotFound\"} 2"));
+        assert!(rendered.contains("test_errors_total{kind=\"InvalidRequest\"} 1"));
+    }
+}
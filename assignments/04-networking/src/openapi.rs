@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    api::{Body, ROUTES},
+    error::AspirinEatsError,
+    food::{Ingredient, Patty},
+    http::HttpResponse,
+};
+
+/// Whether, and how, an error is sent back in answer to a request
+#[derive(Clone, Copy, PartialEq)]
+enum Answered {
+    /// Always with the same message
+    Fixed,
+
+    /// With a message that depends on the details of the error
+    Varies,
+
+    /// Never, as it is only ever reported by the client or at startup
+    Never,
+}
+
+/// An example of every error the origin or the proxy in front of it can answer a request with.
+/// Their status codes and messages come from the `HttpResponse` conversion, so they stay in sync
+/// with what the servers actually send
+///
+/// The examples are chained through an exhaustive match, each variant naming the next, so a new
+/// variant fails to compile until it has been given a place here.
+fn documented_errors() -> Vec<(AspirinEatsError, Answered)> {
+    use AspirinEatsError::*;
+
+    let mut errors = Vec::new();
+    let mut next = Some(ParseError(
+        serde_json::from_str::<Value>("").expect_err("empty string is not valid json"),
+    ));
+    while let Some(error) = next {
+        let (answered, following) = match &error {
+            ParseError(_) => (Answered::Fixed, Database(rusqlite::Error::InvalidQuery)),
+            Database(_) => (Answered::Fixed, Io(std::io::Error::other("example"))),
+            Io(_) => (Answered::Fixed, InvalidRequest),
+            InvalidRequest => (Answered::Fixed, PayloadTooLarge),
+            PayloadTooLarge => (Answered::Fixed, NotFound),
+            NotFound => (Answered::Fixed, MethodNotAllowed),
+            MethodNotAllowed => (Answered::Fixed, NotAcceptable),
+            NotAcceptable => (Answered::Fixed, NotPending),
+            NotPending => (Answered::Fixed, OrderFinished),
+            OrderFinished => (Answered::Fixed, NotDeleted),
+            NotDeleted => (
+                Answered::Fixed,
+                OutOfStock(vec![Ingredient::Patty(Patty::Beef)]),
+            ),
+            OutOfStock(_) => (Answered::Varies, BadGateway),
+            BadGateway => (Answered::Fixed, ServiceUnavailable),
+            ServiceUnavailable => (Answered::Fixed, GatewayTimeout),
+            GatewayTimeout => (Answered::Fixed, UnexpectedStatus(599)),
+            UnexpectedStatus(_) => (Answered::Never, Config(String::new())),
+            // Last in the chain, and only ever reported at startup
+            Config(_) => break,
+        };
+        errors.push((error, answered));
+        next = Some(following);
+    }
+    errors.retain(|(_, answered)| *answered != Answered::Never);
+    errors
+}
+
+/// Generate an OpenAPI 3 description of the API from the route table and the food types
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let error_responses = error_responses();
+    let mut paths = Map::new();
+    for route in ROUTES {
+        let mut operation = Map::new();
+        operation.insert("summary".to_string(), json!(route.summary));
+
+        let params = route
+            .path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer", "format": "int64" },
+                })
+            })
+            .collect::<Vec<_>>();
+        if !params.is_empty() {
+            operation.insert("parameters".to_string(), json!(params));
+        }
+
+        if let Some(content) = content(route.request, &mut gen) {
+            operation.insert(
+                "requestBody".to_string(),
+                json!({ "required": true, "content": content }),
+            );
+        }
+
+        let (status, body) = route.response;
+        let mut responses = Map::new();
+        let mut success = json!({ "description": "Success" });
+        if let Some(content) = content(body, &mut gen) {
+            success["content"] = content;
+        }
+        responses.insert(status.to_string(), success);
+        for status in error_responses.keys() {
+            responses.insert(
+                status.to_string(),
+                json!({ "$ref": format!("#/components/responses/{}", status) }),
+            );
+        }
+        operation.insert("responses".to_string(), Value::Object(responses));
+
+        paths
+            .entry(route.path.to_string())
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("path items are objects")
+            .insert(route.method.to_lowercase(), Value::Object(operation));
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Aspirin Eats",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+            "responses": error_responses,
+        },
+    })
+}
+
+/// OpenAPI content object for a body, or `None` if there is no body
+fn content(body: Body, gen: &mut SchemaGenerator) -> Option<Value> {
+    match body {
+        Body::Empty => None,
+        Body::Text => Some(json!({ "text/plain": { "schema": { "type": "string" } } })),
+        Body::Json(schema) => Some(json!({ "application/json": { "schema": schema(gen) } })),
+    }
+}
+
+/// Errors answered with one status code
+#[derive(Default)]
+struct StatusErrors {
+    kinds: Vec<&'static str>,
+    bodies: Vec<String>,
+    examples: Map<String, Value>,
+
+    /// Whether every body is always the same, so they can be listed exhaustively
+    fixed: bool,
+}
+
+/// Response objects for every documented error, keyed by status code
+fn error_responses() -> BTreeMap<u16, Value> {
+    let mut statuses: BTreeMap<u16, StatusErrors> = BTreeMap::new();
+    for (error, answered) in documented_errors() {
+        let kind = error.kind();
+        let response = HttpResponse::from(error);
+        let errors = statuses
+            .entry(response.status_code())
+            .or_insert_with(|| StatusErrors {
+                fixed: true,
+                ..StatusErrors::default()
+            });
+        errors.kinds.push(kind);
+        errors.fixed &= answered == Answered::Fixed;
+        errors
+            .examples
+            .insert(kind.to_string(), json!({ "value": response.body() }));
+        if !errors.bodies.iter().any(|body| body == response.body()) {
+            errors.bodies.push(response.body().to_string());
+        }
+    }
+
+    statuses
+        .into_iter()
+        .map(|(status, errors)| {
+            let mut schema = json!({ "type": "string" });
+            if errors.fixed {
+                schema["enum"] = json!(errors.bodies);
+            }
+            let response = json!({
+                "description": errors.kinds.join(", "),
+                "content": {
+                    "text/plain": { "schema": schema, "examples": errors.examples },
+                },
+            });
+            (status, response)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_route_is_described() {
+        let doc = document();
+        for route in ROUTES {
+            let operation = &doc["paths"][route.path][route.method.to_lowercase()];
+            assert_eq!(operation["summary"], route.summary);
+            assert!(operation["responses"]["404"].is_object());
+        }
+    }
+
+    #[test]
+    fn test_food_schemas() {
+        let doc = document();
+        let schemas = &doc["components"]["schemas"];
+        for name in [
+            "Order",
+            "OrderRequest",
+            "OrderStatus",
+            "MenuItem",
+            "Burger",
+            "Bun",
+            "Patty",
+            "Topping",
+        ] {
+            assert!(schemas[name].is_object(), "missing schema for {}", name);
+        }
+
+        // Burgers are externally tagged: {"Burger":{"bun":...}}
+        let menu_item = schemas["MenuItem"].to_string();
+        assert!(menu_item.contains(r##""Burger":{"$ref":"#/components/schemas/Burger"}"##));
+        assert!(schemas["Burger"]["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("toppings")));
+    }
+
+    #[test]
+    fn test_error_responses_match_server() {
+        let doc = document();
+        let not_found = &doc["components"]["responses"]["404"];
+        assert_eq!(
+            not_found["content"]["text/plain"]["schema"]["enum"],
+            json!([AspirinEatsError::NotFound.to_string()])
+        );
+        assert_eq!(
+            doc["components"]["responses"]["400"]["description"],
+            "ParseError, InvalidRequest"
+        );
+
+        // Out of stock messages list the missing ingredients, so they can't be enumerated
+        let conflict = &doc["components"]["responses"]["409"];
+        assert_eq!(
+            conflict["description"],
+            "NotPending, OrderFinished, NotDeleted, OutOfStock"
+        );
+        assert!(conflict["content"]["text/plain"]["schema"]["enum"].is_null());
+        assert!(conflict["content"]["text/plain"]["examples"]["OutOfStock"].is_object());
+    }
+
+    #[test]
+    fn test_every_status_is_documented() {
+        let doc = document();
+        for route in ROUTES {
+            let responses = &doc["paths"][route.path][route.method.to_lowercase()]["responses"];
+            let success = route.response.0;
+            for status in [success, 400, 404, 405, 406, 409, 413, 500, 502, 503, 504] {
+                assert!(
+                    responses[status.to_string()].is_object(),
+                    "{} {} doesn't document {}",
+                    route.method,
+                    route.path,
+                    status
+                );
+            }
+        }
+    }
+}
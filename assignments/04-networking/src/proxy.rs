@@ -0,0 +1,374 @@
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+    time::Instant,
+};
+
+use uuid::Uuid;
+
+use crate::{
+    api::route_label,
+    error::AspirinEatsError,
+    http::{read_request, HttpRequest, HttpResponse, REQUEST_ID_HEADER},
+    logging::{AccessLog, AccessLogEntry},
+    metrics::Metrics,
+    upstream::{Upstream, UpstreamOptions},
+};
+
+/// Reverse proxy that forwards every request to a single origin server
+pub struct ReverseProxy {
+    upstream: Upstream,
+    metrics: Metrics,
+    access_log: AccessLog,
+}
+
+impl ReverseProxy {
+    pub fn new(origin_addr: &str, access_log: AccessLog) -> Self {
+        ReverseProxy {
+            upstream: Upstream::new(origin_addr, UpstreamOptions::default()),
+            metrics: Metrics::new("aspirin_eats_proxy"),
+            access_log,
+        }
+    }
+
+    /// Use the given timeouts, retry policy and circuit breaker settings for the origin
+    pub fn with_upstream_options(mut self, options: UpstreamOptions) -> Self {
+        self.upstream = Upstream::new(self.upstream.addr(), options);
+        self
+    }
+
+    /// Read a single request from the client, forward it to the origin and relay the response
+    ///
+    /// Every request is tagged with a freshly generated request ID before it is forwarded, so the
+    /// proxy and origin access logs can be joined on it.
+    pub fn handle_connection<S: Read + Write>(
+        &self,
+        client: &mut S,
+    ) -> Result<(), AspirinEatsError> {
+        let start = Instant::now();
+        let request_id = Uuid::new_v4().to_string();
+
+        let (method, path, response) =
+            match read_request(&mut *client).and_then(|raw| HttpRequest::from_str(&raw)) {
+                Ok(mut request) => {
+                    request.set_header(REQUEST_ID_HEADER, &request_id);
+                    (
+                        request.method.clone().unwrap_or_default(),
+                        request.path.clone().unwrap_or_default(),
+                        self.respond(&mut request),
+                    )
+                }
+                Err(error) => {
+                    self.metrics.observe_error(&error);
+                    (String::new(), String::new(), error.into())
+                }
+            };
+        let response = response.with_header(REQUEST_ID_HEADER, &request_id);
+
+        client.write_all(response.to_string().as_bytes())?;
+        client.flush()?;
+
+        let latency = start.elapsed();
+        self.metrics.observe_request(
+            &method,
+            route_label(Some(&path)),
+            response.status_code(),
+            latency,
+        );
+        self.access_log.record(&AccessLogEntry::new(
+            "proxy",
+            &request_id,
+            &method,
+            &path,
+            response.status_code(),
+            latency,
+        ));
+        Ok(())
+    }
+
+    /// Serve the proxy's own metrics, or forward the request to the origin
+    fn respond(&self, request: &mut HttpRequest) -> HttpResponse {
+        if route_label(request.path.as_deref()) == "/metrics" {
+            return HttpResponse::new(200, "OK", &self.metrics.render())
+                .with_header("Content-Type", "text/plain; version=0.0.4");
+        }
+
+        self.forward(request).unwrap_or_else(|error| {
+            self.metrics.observe_error(&error);
+            error.into()
+        })
+    }
+
+    /// Send the request to the origin and parse its response
+    fn forward(&self, request: &mut HttpRequest) -> Result<HttpResponse, AspirinEatsError> {
+        if let Some(length) = request.body.as_ref().map(String::len) {
+            request.set_header("Content-Length", &length.to_string());
+        }
+        self.upstream.send(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::http::IDEMPOTENCY_KEY_HEADER;
+
+    /// What a scripted origin does with each connection it accepts, in order
+    enum Step {
+        /// Close the connection without reading the request
+        Reset,
+
+        /// Wait before answering
+        Delay(Duration),
+
+        /// Answer straight away
+        Respond,
+    }
+
+    /// Start a fake origin that answers a single request with `response`, handing back the request
+    /// it received
+    fn fake_origin(response: &'static str) -> (String, thread::JoinHandle<HttpRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = HttpRequest::from_str(&read_request(&mut stream).unwrap()).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (addr, handle)
+    }
+
+    /// Start a fake origin that handles one connection per step, counting the connections it has
+    /// accepted so far
+    fn scripted_origin(steps: Vec<Step>) -> (String, Arc<AtomicUsize>, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let handle = {
+            let accepted = Arc::clone(&accepted);
+            thread::spawn(move || {
+                for step in steps {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    let delay = match step {
+                        Step::Reset => continue,
+                        Step::Delay(delay) => delay,
+                        Step::Respond => Duration::ZERO,
+                    };
+                    let _ = read_request(&mut stream);
+                    thread::sleep(delay);
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\nok");
+                }
+            })
+        };
+        (addr, accepted, handle)
+    }
+
+    fn fast_retries() -> UpstreamOptions {
+        UpstreamOptions {
+            retry_backoff: Duration::from_millis(1),
+            ..UpstreamOptions::default()
+        }
+    }
+
+    /// Send `request` through the proxy over a real socket and return the response
+    fn send_through(proxy: &ReverseProxy, request: &str) -> HttpResponse {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let request = request.to_string();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut raw = String::new();
+            stream.read_to_string(&mut raw).unwrap();
+            raw
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        proxy.handle_connection(&mut stream).unwrap();
+        drop(stream);
+        HttpResponse::from_str(&client.join().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_forwards_request_with_request_id() {
+        let (origin_addr, origin) = fake_origin("HTTP/1.1 200 OK\r\n\r\n[]");
+        let proxy = ReverseProxy::new(&origin_addr, AccessLog::disabled());
+
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        let forwarded = origin.join().unwrap();
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), "[]");
+        assert_eq!(forwarded.path, Some("/orders".to_string()));
+        assert_eq!(
+            forwarded.header(REQUEST_ID_HEADER),
+            response.header(REQUEST_ID_HEADER)
+        );
+    }
+
+    #[test]
+    fn test_forwards_body() {
+        let (origin_addr, origin) = fake_origin("HTTP/1.1 201 Created\r\n\r\n");
+        let proxy = ReverseProxy::new(&origin_addr, AccessLog::disabled());
+
+        let body = r#"{"customer":"Amit","food":["Fries"]}"#;
+        let request = format!(
+            "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let response = send_through(&proxy, &request);
+
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(origin.join().unwrap().body, Some(body.to_string()));
+    }
+
+    #[test]
+    fn test_unreachable_origin_is_bad_gateway() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let proxy = ReverseProxy::new(&addr, AccessLog::disabled());
+
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 502);
+
+        let metrics = send_through(&proxy, "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(metrics
+            .body()
+            .contains("aspirin_eats_proxy_errors_total{kind=\"BadGateway\"} 1"));
+    }
+
+    #[test]
+    fn test_retries_idempotent_request_after_reset() {
+        let (addr, accepted, origin) = scripted_origin(vec![Step::Reset, Step::Respond]);
+        let proxy =
+            ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(fast_retries());
+
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), "ok");
+        origin.join().unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_does_not_retry_post() {
+        let (addr, accepted, origin) = scripted_origin(vec![Step::Reset, Step::Respond]);
+        let proxy =
+            ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(fast_retries());
+
+        let response = send_through(&proxy, "POST /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 502);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        // The origin is still there for the next request
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 200);
+        origin.join().unwrap();
+    }
+
+    #[test]
+    fn test_retries_post_with_idempotency_key() {
+        let (addr, accepted, origin) = scripted_origin(vec![Step::Reset, Step::Respond]);
+        let proxy =
+            ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(fast_retries());
+
+        let request = format!(
+            "POST /orders HTTP/1.1\r\n{}: order-1\r\n\r\n",
+            IDEMPOTENCY_KEY_HEADER
+        );
+        let response = send_through(&proxy, &request);
+        assert_eq!(response.status_code(), 200);
+        origin.join().unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_does_not_retry_keyed_post_to_other_paths() {
+        let (addr, accepted, origin) = scripted_origin(vec![Step::Reset, Step::Respond]);
+        let proxy =
+            ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(fast_retries());
+
+        // The origin doesn't remember keys here, so a replay would restock twice
+        let request = format!(
+            "POST /inventory/restock HTTP/1.1\r\n{}: restock-1\r\n\r\n",
+            IDEMPOTENCY_KEY_HEADER
+        );
+        let response = send_through(&proxy, &request);
+        assert_eq!(response.status_code(), 502);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 200);
+        origin.join().unwrap();
+    }
+
+    #[test]
+    fn test_slow_origin_is_gateway_timeout() {
+        let (addr, _, origin) = scripted_origin(vec![Step::Delay(Duration::from_millis(500))]);
+        let proxy = ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(
+            UpstreamOptions {
+                timeout: Duration::from_millis(100),
+                retries: 0,
+                ..UpstreamOptions::default()
+            },
+        );
+
+        let start = Instant::now();
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 504);
+        assert!(start.elapsed() < Duration::from_millis(400));
+        origin.join().unwrap();
+    }
+
+    #[test]
+    fn test_circuit_breaker_fails_fast_then_recovers() {
+        let (addr, accepted, origin) =
+            scripted_origin(vec![Step::Reset, Step::Reset, Step::Respond]);
+        let proxy = ReverseProxy::new(&addr, AccessLog::disabled()).with_upstream_options(
+            UpstreamOptions {
+                retries: 0,
+                breaker_threshold: 2,
+                breaker_cooldown: Duration::from_millis(200),
+                ..UpstreamOptions::default()
+            },
+        );
+
+        for _ in 0..2 {
+            let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+            assert_eq!(response.status_code(), 502);
+        }
+
+        // The circuit is open, so the origin is not contacted at all
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 503);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        // After the cooldown a probe gets through and closes the circuit again
+        thread::sleep(Duration::from_millis(250));
+        let response = send_through(&proxy, "GET /orders HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), 200);
+        origin.join().unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+
+        let metrics = send_through(&proxy, "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(metrics
+            .body()
+            .contains("aspirin_eats_proxy_errors_total{kind=\"ServiceUnavailable\"} 1"));
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt::Write;
+
+use crate::food::{Bun, MenuItem, Order, Patty};
+
+/// Characters per line on the 58mm thermal printers at the front counter
+pub const RECEIPT_WIDTH: usize = 32;
+
+/// Formats a receipt can be rendered in
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReceiptFormat {
+    /// Fixed-width plain text
+    Text,
+
+    /// A standalone HTML page that prints cleanly from a browser
+    Html,
+}
+
+impl ReceiptFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReceiptFormat::Text => "text/plain; charset=utf-8",
+            ReceiptFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    /// Pick a format from the value of an `Accept` header, preferring the media type with the
+    /// highest quality. No header means any format will do, in which case text is used
+    ///
+    /// Returns `None` if the client accepts none of the formats.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let Some(accept) = accept else {
+            return Some(ReceiptFormat::Text);
+        };
+
+        let mut best: Option<(f32, ReceiptFormat)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_lowercase();
+            let quality = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let format = match media_type.as_str() {
+                "text/plain" | "text/*" | "*/*" => ReceiptFormat::Text,
+                "text/html" => ReceiptFormat::Html,
+                _ => continue,
+            };
+            if quality > 0.0 && !best.is_some_and(|(q, _)| q >= quality) {
+                best = Some((quality, format));
+            }
+        }
+        best.map(|(_, format)| format)
+    }
+}
+
+/// A single line on a receipt
+#[derive(Debug, PartialEq, Clone)]
+struct Line {
+    /// Whether this is a component of the item above it
+    detail: bool,
+    label: String,
+    price: f64,
+}
+
+/// An itemized receipt for an order
+#[derive(Debug, PartialEq, Clone)]
+pub struct Receipt {
+    id: Option<i64>,
+    customer: String,
+    status: String,
+    lines: Vec<Line>,
+    total: f64,
+}
+
+impl Receipt {
+    pub fn new(order: &Order) -> Self {
+        let mut lines = Vec::new();
+        for item in &order.food {
+            match item {
+                MenuItem::Burger(burger) => {
+                    lines.push(Line {
+                        detail: false,
+                        label: "Burger".to_string(),
+                        price: item.price(),
+                    });
+                    lines.push(Line {
+                        detail: true,
+                        label: bun_label(burger.bun()).to_string(),
+                        price: burger.bun().price(),
+                    });
+                    lines.push(Line {
+                        detail: true,
+                        label: patty_label(burger.patty()).to_string(),
+                        price: burger.patty().price(),
+                    });
+                    for topping in burger.toppings() {
+                        lines.push(Line {
+                            detail: true,
+                            label: format!("+ {:?}", topping),
+                            price: topping.price(),
+                        });
+                    }
+                }
+                MenuItem::Fries | MenuItem::Drink => lines.push(Line {
+                    detail: false,
+                    label: format!("{:?}", item),
+                    price: item.price(),
+                }),
+            }
+        }
+
+        Receipt {
+            id: order.id,
+            customer: order.customer.clone(),
+            status: format!("{:?}", order.status),
+            lines,
+            total: order.total,
+        }
+    }
+
+    pub fn render(&self, format: ReceiptFormat) -> String {
+        match format {
+            ReceiptFormat::Text => self.to_text(RECEIPT_WIDTH),
+            ReceiptFormat::Html => self.to_html(),
+        }
+    }
+
+    /// Render the receipt as plain text, `width` characters wide. Labels too long to fit next to
+    /// their price are cut short
+    pub fn to_text(&self, width: usize) -> String {
+        let mut text = String::new();
+        let rule = "-".repeat(width);
+
+        let _ = writeln!(text, "{:^width$}", "ASPIRIN EATS");
+        text.push_str(&columns(&self.order_label(), &self.status, width));
+        text.push_str(&columns(&format!("Customer: {}", self.customer), "", width));
+        let _ = writeln!(text, "{}", rule);
+        for line in &self.lines {
+            let label = match line.detail {
+                true => format!("  {}", line.label),
+                false => line.label.clone(),
+            };
+            text.push_str(&columns(&label, &money(line.price), width));
+        }
+        let _ = writeln!(text, "{}", rule);
+        text.push_str(&columns("TOTAL", &money(self.total), width));
+        text
+    }
+
+    /// Render the receipt as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(
+            html,
+            "<title>Aspirin Eats {}</title>",
+            escape(&self.order_label())
+        );
+        html.push_str(concat!(
+            "<style>\n",
+            "body { font-family: monospace; max-width: 24em; margin: 1em auto; }\n",
+            "table { width: 100%; border-collapse: collapse; }\n",
+            "td.price { text-align: right; }\n",
+            "tr.detail td:first-child { padding-left: 1.5em; color: #555; }\n",
+            "tfoot td { border-top: 1px dashed; font-weight: bold; }\n",
+            "@media print { body { margin: 0; } }\n",
+            "</style>\n</head>\n<body>\n",
+        ));
+        html.push_str("<h1>Aspirin Eats</h1>\n");
+        let _ = writeln!(
+            html,
+            "<p>{} &middot; {}<br>Customer: {}</p>",
+            escape(&self.order_label()),
+            escape(&self.status),
+            escape(&self.customer)
+        );
+
+        html.push_str("<table>\n<tbody>\n");
+        for line in &self.lines {
+            let _ = writeln!(
+                html,
+                "<tr{}><td>{}</td><td class=\"price\">{}</td></tr>",
+                if line.detail { " class=\"detail\"" } else { "" },
+                escape(&line.label),
+                money(line.price)
+            );
+        }
+        html.push_str("</tbody>\n<tfoot>\n");
+        let _ = writeln!(
+            html,
+            "<tr><td>Total</td><td class=\"price\">{}</td></tr>",
+            money(self.total)
+        );
+        html.push_str("</tfoot>\n</table>\n</body>\n</html>\n");
+        html
+    }
+
+    fn order_label(&self) -> String {
+        match self.id {
+            Some(id) => format!("Order #{}", id),
+            None => "Order".to_string(),
+        }
+    }
+}
+
+fn bun_label(bun: &Bun) -> &'static str {
+    match bun {
+        Bun::Sesame => "Sesame bun",
+        Bun::Plain => "Plain bun",
+        Bun::GlutenFree => "Gluten-free bun",
+    }
+}
+
+fn patty_label(patty: &Patty) -> &'static str {
+    match patty {
+        Patty::Beef => "Beef patty",
+        Patty::Chicken => "Chicken patty",
+        Patty::Veggie => "Veggie patty",
+    }
+}
+
+fn money(amount: f64) -> String {
+    format!("${:.2}", amount)
+}
+
+/// A line with `left` aligned left and `right` aligned right, truncating `left` if both don't fit
+fn columns(left: &str, right: &str, width: usize) -> String {
+    let right = right.chars().take(width).collect::<String>();
+    let gap = usize::from(!right.is_empty());
+    let room = width.saturating_sub(right.chars().count() + gap);
+    let left = left.chars().take(room).collect::<String>();
+    format!(
+        "{}{:>pad$}\n",
+        left,
+        right,
+        pad = width - left.chars().count()
+    )
+}
+
+/// Escape text for use in HTML, since customer names come straight from the request body
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::{Burger, OrderRequest, Topping};
+
+    fn order() -> Order {
+        let mut order = Order::from(OrderRequest {
+            customer: "Amit".to_string(),
+            food: vec![
+                MenuItem::Burger(Burger::new(
+                    Bun::Sesame,
+                    Patty::Beef,
+                    vec![Topping::Cheese, Topping::Lettuce],
+                )),
+                MenuItem::Fries,
+            ],
+        });
+        order.id = Some(12);
+        order
+    }
+
+    #[test]
+    fn test_text_receipt() {
+        let text = Receipt::new(&order()).to_text(32);
+        let expected = "          ASPIRIN EATS          \n\
+Order #12                Pending
+Customer: Amit                  
+--------------------------------
+Burger                    $10.00
+  Sesame bun               $1.00
+  Beef patty               $8.00
+  + Cheese                 $1.00
+  + Lettuce                $0.00
+Fries                      $5.00
+--------------------------------
+TOTAL                     $15.00
+";
+        assert_eq!(text, expected);
+        assert!(text.lines().all(|line| line.chars().count() == 32));
+    }
+
+    #[test]
+    fn test_text_receipt_truncates_long_names() {
+        let mut order = order();
+        order.customer = "A".repeat(50);
+        let text = Receipt::new(&order).to_text(20);
+        assert!(text.lines().all(|line| line.chars().count() == 20));
+    }
+
+    #[test]
+    fn test_html_receipt_escapes_customer() {
+        let mut order = order();
+        order.customer = "<script>alert('hi')</script>".to_string();
+        let html = Receipt::new(&order).to_html();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt;"));
+        assert!(html.contains(
+            "<tr class=\"detail\"><td>Beef patty</td><td class=\"price\">$8.00</td></tr>"
+        ));
+        assert!(html.contains("<tr><td>Total</td><td class=\"price\">$15.00</td></tr>"));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(ReceiptFormat::negotiate(None), Some(ReceiptFormat::Text));
+        assert_eq!(
+            ReceiptFormat::negotiate(Some("text/html")),
+            Some(ReceiptFormat::Html)
+        );
+        assert_eq!(
+            ReceiptFormat::negotiate(Some("text/html;q=0.5, text/plain")),
+            Some(ReceiptFormat::Text)
+        );
+        assert_eq!(
+            ReceiptFormat::negotiate(Some(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            )),
+            Some(ReceiptFormat::Html)
+        );
+        assert_eq!(ReceiptFormat::negotiate(Some("application/json")), None);
+        assert_eq!(ReceiptFormat::negotiate(Some("text/html;q=0")), None);
+    }
+}
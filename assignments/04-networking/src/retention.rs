@@ -0,0 +1,103 @@
+use std::{str::FromStr, time::Duration};
+
+use crate::{error::AspirinEatsError, food::OrderStatus};
+
+/// What anonymized orders have in place of the customer's name
+pub const REDACTED_CUSTOMER: &str = "[redacted]";
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long orders with each status are kept before something is done to them, measured from
+/// when they reached that status. Statuses that aren't listed are kept forever, unless the order
+/// has been deleted: then the longest limit applies from when it was deleted
+///
+/// Parses from a list of `status=days`, e.g. `completed=365,cancelled=30`.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct AgeLimits(pub Vec<(OrderStatus, Duration)>);
+
+impl AgeLimits {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for AgeLimits {
+    type Err = AspirinEatsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limits = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (status, days) = part
+                .split_once('=')
+                .ok_or(AspirinEatsError::InvalidRequest)?;
+            let status = match status.trim().to_lowercase().as_str() {
+                "pending" => OrderStatus::Pending,
+                "preparing" => OrderStatus::Preparing,
+                "transporting" => OrderStatus::Transporting,
+                "completed" => OrderStatus::Completed,
+                "cancelled" => OrderStatus::Cancelled,
+                _ => return Err(AspirinEatsError::InvalidRequest),
+            };
+            let days: u32 = days
+                .trim()
+                .parse()
+                .map_err(|_| AspirinEatsError::InvalidRequest)?;
+            limits.retain(|(existing, _)| *existing != status);
+            limits.push((status, DAY * days));
+        }
+        Ok(AgeLimits(limits))
+    }
+}
+
+/// What the retention job does to old orders, whether or not they have been deleted
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct RetentionPolicy {
+    /// When to replace the customer's name, in the order and throughout its history, with
+    /// `REDACTED_CUSTOMER`
+    pub anonymize_after: AgeLimits,
+
+    /// When to permanently remove the order along with its history
+    pub delete_after: AgeLimits,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy never does anything
+    pub fn is_empty(&self) -> bool {
+        self.anonymize_after.is_empty() && self.delete_after.is_empty()
+    }
+}
+
+/// What one run of the retention job did
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct RetentionReport {
+    /// Orders whose customer name was removed
+    pub anonymized: usize,
+
+    /// Orders permanently removed
+    pub deleted: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age_limits() {
+        assert_eq!(
+            AgeLimits::from_str("completed=365, Cancelled=0").unwrap(),
+            AgeLimits(vec![
+                (OrderStatus::Completed, DAY * 365),
+                (OrderStatus::Cancelled, Duration::ZERO)
+            ])
+        );
+        assert_eq!(
+            AgeLimits::from_str("completed=1,completed=2").unwrap(),
+            AgeLimits(vec![(OrderStatus::Completed, DAY * 2)])
+        );
+        assert!(AgeLimits::from_str("").unwrap().is_empty());
+
+        assert!(AgeLimits::from_str("completed").is_err());
+        assert!(AgeLimits::from_str("completed=-1").is_err());
+        assert!(AgeLimits::from_str("lost=30").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+    sync::{Mutex, MutexGuard},
+    time::Instant,
+};
+
+use uuid::Uuid;
+
+use crate::{
+    api::{handle_request, route_label},
+    db::AspirinEatsDb,
+    error::AspirinEatsError,
+    http::{read_request, HttpRequest, HttpResponse, REQUEST_ID_HEADER},
+    logging::{AccessLog, AccessLogEntry},
+    metrics::Metrics,
+    retention::{RetentionPolicy, RetentionReport},
+};
+
+/// The origin server: owns the database handle and serves the Aspirin Eats API
+pub struct OriginServer {
+    db: Mutex<AspirinEatsDb>,
+    metrics: Metrics,
+    access_log: AccessLog,
+}
+
+impl OriginServer {
+    pub fn new(db: AspirinEatsDb, access_log: AccessLog) -> Self {
+        OriginServer {
+            db: Mutex::new(db),
+            metrics: Metrics::new("aspirin_eats_origin"),
+            access_log,
+        }
+    }
+
+    /// Read a single request from the stream, handle it and write back the response
+    pub fn handle_connection<S: Read + Write>(
+        &self,
+        stream: &mut S,
+    ) -> Result<(), AspirinEatsError> {
+        let start = Instant::now();
+        let request = read_request(&mut *stream).and_then(|raw| HttpRequest::from_str(&raw));
+
+        let request_id = request
+            .as_ref()
+            .ok()
+            .and_then(|request| request.header(REQUEST_ID_HEADER))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let (method, path, response) = match request {
+            Ok(request) => (
+                request.method.clone().unwrap_or_default(),
+                request.path.clone().unwrap_or_default(),
+                self.respond(&request),
+            ),
+            Err(error) => {
+                self.metrics.observe_error(&error);
+                (String::new(), String::new(), error.into())
+            }
+        };
+        let response = response.with_header(REQUEST_ID_HEADER, &request_id);
+
+        stream.write_all(response.to_string().as_bytes())?;
+        stream.flush()?;
+
+        let latency = start.elapsed();
+        self.metrics.observe_request(
+            &method,
+            route_label(Some(&path)),
+            response.status_code(),
+            latency,
+        );
+        self.access_log.record(&AccessLogEntry::new(
+            "origin",
+            &request_id,
+            &method,
+            &path,
+            response.status_code(),
+            latency,
+        ));
+        Ok(())
+    }
+
+    /// Run the retention job against the database
+    pub fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, AspirinEatsError> {
+        Ok(self.db().apply_retention(policy)?)
+    }
+
+    /// Close the database once every connection has finished with the server
+    pub fn close(self) -> Result<(), AspirinEatsError> {
+        let db = self.db.into_inner().unwrap_or_else(|e| e.into_inner());
+        Ok(db.close()?)
+    }
+
+    fn db(&self) -> MutexGuard<'_, AspirinEatsDb> {
+        self.db.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Produce the response for a parsed request, counting any error it results in
+    fn respond(&self, request: &HttpRequest) -> HttpResponse {
+        if route_label(request.path.as_deref()) == "/metrics" {
+            return HttpResponse::new(200, "OK", &self.metrics.render())
+                .with_header("Content-Type", "text/plain; version=0.0.4");
+        }
+
+        handle_request(&self.db(), request).unwrap_or_else(|error| {
+            self.metrics.observe_error(&error);
+            error.into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// In-memory stream: reads come from `input`, writes go to `output`
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: &str) -> Self {
+            MockStream {
+                input: Cursor::new(input.as_bytes().to_vec()),
+                output: Vec::new(),
+            }
+        }
+
+        fn response(&self) -> HttpResponse {
+            HttpResponse::from_str(&String::from_utf8_lossy(&self.output)).unwrap()
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn server() -> OriginServer {
+        OriginServer::new(AspirinEatsDb::in_memory().unwrap(), AccessLog::disabled())
+    }
+
+    #[test]
+    fn test_propagates_request_id() {
+        let server = server();
+        let mut stream = MockStream::new("GET / HTTP/1.1\r\nX-Request-Id: abc-123\r\n\r\n");
+        server.handle_connection(&mut stream).unwrap();
+
+        let response = stream.response();
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.header(REQUEST_ID_HEADER), Some("abc-123"));
+    }
+
+    #[test]
+    fn test_generates_request_id() {
+        let server = server();
+        let mut stream = MockStream::new("GET / HTTP/1.1\r\n\r\n");
+        server.handle_connection(&mut stream).unwrap();
+
+        let request_id = stream
+            .response()
+            .header(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_string();
+        assert!(Uuid::parse_str(&request_id).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_body_is_refused() {
+        let server = server();
+        let mut stream =
+            MockStream::new("POST /orders HTTP/1.1\r\nContent-Length: 1000000000000\r\n\r\n{}");
+        server.handle_connection(&mut stream).unwrap();
+        assert_eq!(stream.response().status_code(), 413);
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let server = server();
+        server
+            .handle_connection(&mut MockStream::new("GET /orders/9 HTTP/1.1\r\n\r\n"))
+            .unwrap();
+
+        let mut stream = MockStream::new("GET /metrics HTTP/1.1\r\n\r\n");
+        server.handle_connection(&mut stream).unwrap();
+
+        let body = stream.response().body().to_string();
+        assert!(body.contains(
+            "aspirin_eats_origin_requests_total{method=\"GET\",route=\"/orders/{id}\",status=\"404\"} 1"
+        ));
+        assert!(body.contains("aspirin_eats_origin_errors_total{kind=\"NotFound\"} 1"));
+    }
+
+    #[test]
+    fn test_close_after_requests() {
+        let server = server();
+        server
+            .handle_connection(&mut MockStream::new("GET /orders HTTP/1.1\r\n\r\n"))
+            .unwrap();
+        server.close().unwrap();
+    }
+}
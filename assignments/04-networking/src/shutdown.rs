@@ -0,0 +1,244 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+/// How often the accept loop wakes up to check whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Flag shared between the accept loop and whatever decides it is time to stop
+#[derive(Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger this shutdown on SIGINT or SIGTERM
+    ///
+    /// A second signal received while draining exits the process immediately.
+    pub fn register_signals(&self) -> io::Result<()> {
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&self.0))?;
+            signal_hook::flag::register(signal, Arc::clone(&self.0))?;
+        }
+        Ok(())
+    }
+
+    /// Ask the server to stop accepting connections
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether shutdown has been requested
+    pub fn is_triggered(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Tuning for the accept loop in `serve`
+#[derive(Debug, PartialEq, Clone)]
+pub struct ServeOptions {
+    /// Maximum number of connections handled at once. Further connections wait in the backlog
+    pub workers: usize,
+
+    /// Read/write timeout applied to every connection, or `None` to wait forever
+    pub read_timeout: Option<Duration>,
+
+    /// How long in-flight connections get to finish once shutdown is triggered
+    pub drain_timeout: Duration,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            workers: 8,
+            read_timeout: Some(Duration::from_secs(5)),
+            drain_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Accept connections and hand each one to `handler` on its own thread until `shutdown` is
+/// triggered. Once it is, the listener is closed and in-flight connections get up to
+/// `options.drain_timeout` to finish.
+///
+/// Returns the number of connections that were still running when the deadline passed.
+pub fn serve<H>(
+    listener: TcpListener,
+    shutdown: &Shutdown,
+    options: &ServeOptions,
+    handler: Arc<H>,
+) -> io::Result<usize>
+where
+    H: Fn(TcpStream) + Send + Sync + 'static,
+{
+    listener.set_nonblocking(true)?;
+    let mut in_flight: Vec<JoinHandle<()>> = Vec::new();
+
+    while !shutdown.is_triggered() {
+        if in_flight.len() >= options.workers {
+            thread::sleep(POLL_INTERVAL);
+            in_flight.retain(|handle| !handle.is_finished());
+            continue;
+        }
+
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(options.read_timeout)?;
+                stream.set_write_timeout(options.read_timeout)?;
+                let handler = Arc::clone(&handler);
+                in_flight.push(thread::spawn(move || handler(stream)));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => eprintln!("Failed to accept connection: {}", e),
+        }
+        in_flight.retain(|handle| !handle.is_finished());
+    }
+    drop(listener);
+
+    let deadline = Instant::now() + options.drain_timeout;
+    while !in_flight.is_empty() && Instant::now() < deadline {
+        in_flight.retain(|handle| !handle.is_finished());
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+    in_flight.retain(|handle| !handle.is_finished());
+    Ok(in_flight.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    /// Handler that waits `delay` before answering, to simulate a slow request
+    fn slow_handler(delay: Duration) -> Arc<impl Fn(TcpStream) + Send + Sync + 'static> {
+        Arc::new(move |mut stream: TcpStream| {
+            let mut buf = [0; 64];
+            let _ = stream.read(&mut buf);
+            thread::sleep(delay);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\ndone");
+        })
+    }
+
+    fn request(addr: std::net::SocketAddr) -> thread::JoinHandle<io::Result<String>> {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr)?;
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        })
+    }
+
+    #[test]
+    fn test_sigterm_drains_in_flight_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        shutdown.register_signals().unwrap();
+
+        let server = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let options = ServeOptions {
+                    drain_timeout: Duration::from_secs(5),
+                    ..ServeOptions::default()
+                };
+                serve(
+                    listener,
+                    &shutdown,
+                    &options,
+                    slow_handler(Duration::from_millis(300)),
+                )
+            })
+        };
+
+        let clients = (0..3).map(|_| request(addr)).collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(100));
+        signal_hook::low_level::raise(SIGTERM).unwrap();
+
+        for client in clients {
+            assert!(client.join().unwrap().unwrap().ends_with("done"));
+        }
+        assert_eq!(server.join().unwrap().unwrap(), 0);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_drain_deadline_abandons_stuck_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+
+        let server = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let options = ServeOptions {
+                    drain_timeout: Duration::from_millis(100),
+                    ..ServeOptions::default()
+                };
+                serve(
+                    listener,
+                    &shutdown,
+                    &options,
+                    slow_handler(Duration::from_secs(2)),
+                )
+            })
+        };
+
+        let _client = request(addr);
+        thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        shutdown.trigger();
+
+        assert_eq!(server.join().unwrap().unwrap(), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_workers_limit_concurrency() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+
+        let server = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let options = ServeOptions {
+                    workers: 1,
+                    ..ServeOptions::default()
+                };
+                serve(
+                    listener,
+                    &shutdown,
+                    &options,
+                    slow_handler(Duration::from_millis(200)),
+                )
+            })
+        };
+
+        let start = Instant::now();
+        let clients = (0..3).map(|_| request(addr)).collect::<Vec<_>>();
+        for client in clients {
+            assert!(client.join().unwrap().unwrap().ends_with("done"));
+        }
+        // With a single worker the requests are handled one after another
+        assert!(start.elapsed() >= Duration::from_millis(600));
+
+        shutdown.trigger();
+        assert_eq!(server.join().unwrap().unwrap(), 0);
+    }
+}
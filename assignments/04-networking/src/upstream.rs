@@ -0,0 +1,303 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::AspirinEatsError,
+    http::{HttpRequest, HttpResponse, IDEMPOTENCY_KEY_HEADER},
+};
+
+/// The only path the origin deduplicates POSTs on by their idempotency key
+const PLACE_ORDER_PATH: &str = "/orders";
+
+/// Methods that can be repeated without changing the result
+const IDEMPOTENT_METHODS: &[&str] = &["GET", "HEAD", "PUT", "DELETE", "OPTIONS"];
+
+/// How the proxy talks to the origin
+#[derive(Debug, PartialEq, Clone)]
+pub struct UpstreamOptions {
+    /// How long to wait for a connection to the origin
+    pub connect_timeout: Duration,
+
+    /// How long to wait on each read/write to the origin
+    pub timeout: Duration,
+
+    /// How many times to retry a failed idempotent request
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled for each one after
+    pub retry_backoff: Duration,
+
+    /// Consecutive failures before the circuit opens, or 0 to never open it
+    pub breaker_threshold: u32,
+
+    /// How long the circuit stays open before a probe request is let through
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for UpstreamOptions {
+    fn default() -> Self {
+        UpstreamOptions {
+            connect_timeout: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            retry_backoff: Duration::from_millis(50),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BreakerState {
+    /// Requests flow normally
+    Closed { failures: u32 },
+
+    /// Requests fail fast until the cooldown is over
+    Open { until: Instant },
+
+    /// A single probe request is in flight to test whether the origin recovered
+    HalfOpen,
+}
+
+/// Stops sending requests to an origin that keeps failing, then probes it again after a cooldown
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a request may be sent now. Once the cooldown is over, lets exactly one probe
+    /// through until its outcome is recorded
+    pub fn allow(&self) -> bool {
+        let mut state = self.lock();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.lock() = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.lock();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } | BreakerState::HalfOpen => self.threshold,
+        };
+        *state = if self.threshold > 0 && failures >= self.threshold {
+            BreakerState::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+
+    /// Whether requests are currently being rejected
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.lock(), BreakerState::Closed { .. })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Connection to the origin server with timeouts, retries and a circuit breaker
+pub struct Upstream {
+    addr: String,
+    options: UpstreamOptions,
+    breaker: CircuitBreaker,
+}
+
+impl Upstream {
+    pub fn new(addr: &str, options: UpstreamOptions) -> Self {
+        Upstream {
+            addr: addr.to_string(),
+            breaker: CircuitBreaker::new(options.breaker_threshold, options.breaker_cooldown),
+            options,
+        }
+    }
+
+    /// Address of the origin server
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    /// Send a request to the origin, retrying it if that is safe
+    ///
+    /// Errors:
+    /// - `ServiceUnavailable` if the circuit breaker is open
+    /// - `GatewayTimeout` if the origin did not answer in time
+    /// - `BadGateway` if the connection failed or the origin sent back garbage
+    pub fn send(&self, request: &HttpRequest) -> Result<HttpResponse, AspirinEatsError> {
+        let attempts = if is_retryable(request) {
+            self.options.retries + 1
+        } else {
+            1
+        };
+
+        let mut backoff = self.options.retry_backoff;
+        let mut last_error = AspirinEatsError::BadGateway;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            if !self.breaker.allow() {
+                return Err(AspirinEatsError::ServiceUnavailable);
+            }
+
+            match self.attempt(request) {
+                Ok(response) => {
+                    self.breaker.record_success();
+                    return Ok(response);
+                }
+                Err(error) => {
+                    self.breaker.record_failure();
+                    last_error = error;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Make a single round trip to the origin
+    fn attempt(&self, request: &HttpRequest) -> Result<HttpResponse, AspirinEatsError> {
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or(AspirinEatsError::BadGateway)?;
+
+        let mut origin =
+            TcpStream::connect_timeout(&addr, self.options.connect_timeout).map_err(io_error)?;
+        origin
+            .set_read_timeout(Some(self.options.timeout))
+            .map_err(io_error)?;
+        origin
+            .set_write_timeout(Some(self.options.timeout))
+            .map_err(io_error)?;
+
+        origin
+            .write_all(request.to_string().as_bytes())
+            .map_err(io_error)?;
+        let mut raw = String::new();
+        origin.read_to_string(&mut raw).map_err(io_error)?;
+        HttpResponse::from_str(&raw).map_err(|_| AspirinEatsError::BadGateway)
+    }
+}
+
+/// Whether a request can safely be sent to the origin more than once
+///
+/// The origin only remembers idempotency keys when placing orders, so a keyed POST to any other
+/// path could still take effect twice.
+fn is_retryable(request: &HttpRequest) -> bool {
+    let method = request.method.as_deref().unwrap_or("GET");
+    let path = request.path.as_deref().unwrap_or("/");
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    IDEMPOTENT_METHODS.contains(&method)
+        || (method == "POST"
+            && path == PLACE_ORDER_PATH
+            && request.header(IDEMPOTENCY_KEY_HEADER).is_some())
+}
+
+fn io_error(error: std::io::Error) -> AspirinEatsError {
+    match error.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => AspirinEatsError::GatewayTimeout,
+        _ => AspirinEatsError::BadGateway,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn test_breaker_success_resets_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_breaker_half_open_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure();
+        assert!(!breaker.allow());
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        // Only one probe at a time
+        assert!(!breaker.allow());
+
+        // A failed probe re-opens the circuit for another cooldown
+        breaker.record_failure();
+        assert!(!breaker.allow());
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_zero_threshold_never_opens() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..10 {
+            breaker.record_failure();
+        }
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let request = |raw: &str| HttpRequest::from_str(raw).unwrap();
+        assert!(is_retryable(&request("GET /orders HTTP/1.1\r\n\r\n")));
+        assert!(is_retryable(&request("DELETE /orders/1 HTTP/1.1\r\n\r\n")));
+        assert!(!is_retryable(&request("POST /orders HTTP/1.1\r\n\r\n")));
+        assert!(is_retryable(&request(
+            "POST /orders HTTP/1.1\r\nIdempotency-Key: abc\r\n\r\n"
+        )));
+    }
+}
@@ -0,0 +1,87 @@
+//! Sorts the same 10 million elements as `main.rs` on a FIFO and a work-stealing pool, both by
+//! splitting into one chunk per worker and by recursively splitting into jobs that spawn subjobs,
+//! then compares the `ParallelSort` algorithms on the work-stealing pool
+
+use std::{sync::Arc, thread};
+
+use concurrency::{
+    par_iter::Parallel,
+    parallel_sort::{MergeSort, ParallelSort, RadixSort, SampleSort},
+    scheduler::Scheduler,
+    sort::{concurrent_merge_sort, recursive_merge_sort},
+    thread_pool::ThreadPool,
+};
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup, BenchmarkId,
+    Criterion,
+};
+use rand::Rng;
+
+const LEN: usize = 10_000_000;
+
+fn random_vec(capacity: usize) -> Vec<i64> {
+    let mut vec = vec![0; capacity];
+    rand::thread_rng().fill(&mut vec[..]);
+    vec
+}
+
+fn sort(c: &mut Criterion) {
+    let num_threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let data = random_vec(LEN);
+    let mut group = c.benchmark_group("sort_10m");
+    group.sample_size(10);
+
+    for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+        let pool = ThreadPool::<()>::with_scheduler(num_threads, scheduler).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("chunked", format!("{:?}", scheduler)),
+            &data,
+            |b, data| {
+                b.iter_batched_ref(
+                    || data.clone(),
+                    |data| concurrent_merge_sort(&pool, data).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+
+        let pool = Arc::new(ThreadPool::with_scheduler(num_threads, scheduler).unwrap());
+        group.bench_with_input(
+            BenchmarkId::new("recursive", format!("{:?}", scheduler)),
+            &data,
+            |b, data| {
+                b.iter_batched(
+                    || data.clone(),
+                    |data| recursive_merge_sort(&pool, data).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    let pool = ThreadPool::<()>::with_scheduler(num_threads, Scheduler::WorkStealing).unwrap();
+    let par = pool.parallel();
+    bench_algorithm(&mut group, "merge", &par, &data, MergeSort);
+    bench_algorithm(&mut group, "sample", &par, &data, SampleSort);
+    bench_algorithm(&mut group, "radix", &par, &data, RadixSort);
+    group.finish();
+}
+
+fn bench_algorithm(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    par: &Parallel<'_, ()>,
+    data: &Vec<i64>,
+    algorithm: impl ParallelSort<i64>,
+) {
+    group.bench_with_input(BenchmarkId::new("algorithm", name), data, |b, data| {
+        b.iter_batched_ref(
+            || data.clone(),
+            |data| algorithm.sort(par, data).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, sort);
+criterion_main!(benches);
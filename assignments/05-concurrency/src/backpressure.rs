@@ -0,0 +1,251 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::{
+    job::HELP_INTERVAL,
+    scheduler::{self, Job},
+};
+
+/// How long a blocked submitter sleeps before checking for space again, in case a wakeup was
+/// missed
+const BLOCKED_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// What `ThreadPool::execute` does when the job queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait until a worker takes a job off the queue. A worker submitting jobs runs queued jobs
+    /// while it waits instead
+    #[default]
+    Block,
+
+    /// Fail with `ThreadPoolError::QueueFull`
+    Fail,
+
+    /// Drop the job that has been queued the longest to make room. Its handle reports it as
+    /// `Cancelled`
+    DropOldest,
+
+    /// Run the job straight away on the thread submitting it
+    CallerRuns,
+}
+
+/// Snapshot of a pool's job queue, from `ThreadPool::queue_metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueMetrics {
+    /// Jobs waiting for a worker right now
+    pub depth: usize,
+
+    /// Most jobs that have been waiting for a worker at once
+    pub peak_depth: usize,
+
+    /// Most jobs allowed to wait at once, or `None` if the queue is unbounded
+    pub capacity: Option<usize>,
+    pub policy: Backpressure,
+
+    /// Jobs whose submitter had to wait for room, with `Backpressure::Block`
+    pub blocked: u64,
+
+    /// Jobs turned away, with `Backpressure::Fail`
+    pub rejected: u64,
+
+    /// Queued jobs dropped to make room, with `Backpressure::DropOldest`
+    pub dropped: u64,
+
+    /// Jobs run by their submitter, with `Backpressure::CallerRuns`
+    pub ran_on_caller: u64,
+}
+
+/// Keeps track of how many jobs are queued, and whether another one fits
+#[derive(Default)]
+pub(crate) struct QueueLimit {
+    capacity: Option<usize>,
+    policy: Backpressure,
+    depth: Arc<Depth>,
+    blocked: AtomicU64,
+    rejected: AtomicU64,
+    dropped: AtomicU64,
+    ran_on_caller: AtomicU64,
+}
+
+/// Number of queued jobs, shared with the jobs themselves so they can leave the count
+#[derive(Default)]
+struct Depth {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+
+    /// Submitters waiting on `freed`, so a job leaving the queue only locks `space` when there are
+    waiting: AtomicUsize,
+    space: Mutex<()>,
+    freed: Condvar,
+}
+
+impl QueueLimit {
+    pub(crate) fn new(capacity: Option<usize>, policy: Backpressure) -> Self {
+        QueueLimit {
+            capacity,
+            policy,
+            ..QueueLimit::default()
+        }
+    }
+
+    pub(crate) fn policy(&self) -> Backpressure {
+        self.policy
+    }
+
+    /// Take a place in the queue if there is one free
+    pub(crate) fn try_reserve(&self) -> bool {
+        let capacity = self.capacity.unwrap_or(usize::MAX);
+        let reserved =
+            self.depth
+                .current
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| {
+                    (depth < capacity).then_some(depth + 1)
+                });
+        match reserved {
+            Ok(depth) => {
+                self.depth.peak.fetch_max(depth + 1, Ordering::SeqCst);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Wrap a job that has a place in the queue, so it gives it up once a worker takes it, or it
+    /// is dropped
+    pub(crate) fn track(&self, job: Job) -> Job {
+        let queued = Queued {
+            job,
+            _place: Place(Arc::clone(&self.depth)),
+        };
+        Box::new(move || queued.run())
+    }
+
+    /// Wait until there might be room in the queue
+    pub(crate) fn wait_for_space(&self) {
+        let depth = &self.depth;
+        let capacity = self.capacity.unwrap_or(usize::MAX);
+        let has_space = || depth.current.load(Ordering::SeqCst) < capacity;
+        scheduler::help_until(has_space, || depth.park(HELP_INTERVAL, capacity));
+        depth.park(BLOCKED_TIMEOUT, capacity);
+    }
+
+    pub(crate) fn record_blocked(&self) {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_ran_on_caller(&self) {
+        self.ran_on_caller.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of jobs waiting for a worker
+    pub(crate) fn depth(&self) -> usize {
+        self.depth.current.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn metrics(&self) -> QueueMetrics {
+        QueueMetrics {
+            depth: self.depth(),
+            peak_depth: self.depth.peak.load(Ordering::SeqCst),
+            capacity: self.capacity,
+            policy: self.policy,
+            blocked: self.blocked.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            ran_on_caller: self.ran_on_caller.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Depth {
+    /// Sleep for at most `timeout`, unless the queue has room for a job
+    fn park(&self, timeout: Duration, capacity: usize) {
+        let space = self.lock();
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        if self.current.load(Ordering::SeqCst) >= capacity {
+            let _ = self.freed.wait_timeout(space, timeout);
+        }
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.space.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A job's place in the queue, given up when dropped
+struct Place(Arc<Depth>);
+
+impl Drop for Place {
+    fn drop(&mut self) {
+        let depth = &self.0;
+        depth.current.fetch_sub(1, Ordering::SeqCst);
+        if depth.waiting.load(Ordering::SeqCst) > 0 {
+            let _space = depth.lock();
+            depth.freed.notify_all();
+        }
+    }
+}
+
+/// A job holding a place in the queue
+struct Queued {
+    job: Job,
+    _place: Place,
+}
+
+impl Queued {
+    fn run(self) {
+        let Queued { job, _place: place } = self;
+        // A running job is no longer queued
+        drop(place);
+        job();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_places_are_given_up_when_run_or_dropped() {
+        let limit = QueueLimit::new(Some(2), Backpressure::Fail);
+        assert!(limit.try_reserve());
+        let first = limit.track(Box::new(|| {}));
+        assert!(limit.try_reserve());
+        let second = limit.track(Box::new(|| {}));
+        assert!(!limit.try_reserve());
+        assert_eq!(limit.metrics().depth, 2);
+
+        first();
+        assert_eq!(limit.metrics().depth, 1);
+        drop(second);
+        let metrics = limit.metrics();
+        assert_eq!((metrics.depth, metrics.peak_depth), (0, 2));
+    }
+
+    #[test]
+    fn test_unbounded_queue_is_never_full() {
+        let limit = QueueLimit::new(None, Backpressure::Fail);
+        let jobs = (0..1000)
+            .map(|_| {
+                assert!(limit.try_reserve());
+                limit.track(Box::new(|| {}))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(limit.metrics().depth, 1000);
+        drop(jobs);
+        assert_eq!(limit.metrics().depth, 0);
+    }
+}
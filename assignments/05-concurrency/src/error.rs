@@ -4,6 +4,24 @@ use thiserror::Error;
 pub enum ThreadPoolError {
     #[error("Number of threads must be greater than 0")]
     ZeroThreads,
+    #[error("Minimum number of threads ({0}) must not be greater than the maximum ({1})")]
+    InvalidThreadRange(usize, usize),
+    #[error("Queue capacity must be greater than 0")]
+    ZeroCapacity,
+    #[error("Chunk size must be greater than 0")]
+    ZeroChunkSize,
     #[error("Failed to send job to worker")]
     Send,
+    #[error("Thread pool has been shut down")]
+    ShutDown,
+    #[error("Job queue is full")]
+    QueueFull,
+    #[error("Job was cancelled before it started")]
+    Cancelled,
+    #[error("Job's deadline passed before it started")]
+    Expired,
+    #[error("Job result was already taken")]
+    ResultTaken,
+    #[error("Job panicked: {0}")]
+    Panicked(String),
 }
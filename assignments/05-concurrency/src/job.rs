@@ -0,0 +1,306 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::Sender;
+
+use crate::{
+    error::ThreadPoolError,
+    priority::Priority,
+    scheduler::{self, Job},
+};
+
+/// How long a worker waiting on a job with nothing else to run blocks before looking for queued
+/// jobs again
+pub(crate) const HELP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// What a job hands back: its return value, or why it didn't produce one
+pub type JobResult<T> = Result<T, ThreadPoolError>;
+
+/// How a job submitted with `ThreadPool::execute_with` is scheduled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JobOptions {
+    /// Only `Scheduler::Priority` pools take this into account
+    pub priority: Priority,
+
+    /// If no worker has started the job by then, it is skipped and reported as `Expired`
+    pub deadline: Option<Instant>,
+}
+
+/// Where a job is in its lifecycle
+enum Status<T> {
+    Queued,
+    Running,
+    Finished(JobResult<T>),
+    Cancelled,
+    Taken,
+}
+
+impl<T> Status<T> {
+    fn is_pending(&self) -> bool {
+        matches!(self, Status::Queued | Status::Running)
+    }
+}
+
+struct Slot<T> {
+    status: Status<T>,
+
+    /// Set once the handle is dropped, after which the result goes to `ThreadPool::get_results`
+    detached: bool,
+}
+
+/// State shared between a job running on the pool and the `JobHandle` for it
+pub(crate) struct JobState<T> {
+    slot: Mutex<Slot<T>>,
+    changed: Condvar,
+
+    /// Where the result goes if nobody is holding the handle. Scoped jobs have nowhere, and their
+    /// result is dropped along with the handle
+    results: Option<Sender<JobResult<T>>>,
+}
+
+impl<T> JobState<T> {
+    pub(crate) fn new(results: Option<Sender<JobResult<T>>>) -> Arc<Self> {
+        Arc::new(JobState {
+            slot: Mutex::new(Slot {
+                status: Status::Queued,
+                detached: false,
+            }),
+            changed: Condvar::new(),
+            results,
+        })
+    }
+
+    /// Mark the job as running. Returns `false` if it was cancelled and should be skipped
+    pub(crate) fn start(&self) -> bool {
+        let mut slot = self.lock();
+        match slot.status {
+            Status::Queued => {
+                slot.status = Status::Running;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stop the job from running if no worker has picked it up yet. Returns whether it was
+    /// cancelled
+    pub(crate) fn cancel(&self) -> bool {
+        let mut slot = self.lock();
+        match slot.status {
+            Status::Queued => {
+                slot.status = Status::Cancelled;
+                self.changed.notify_all();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Hand over the job's result, to the handle if there still is one
+    pub(crate) fn finish(&self, result: JobResult<T>) {
+        let mut slot = self.lock();
+        if slot.detached {
+            // The pool may already be gone, in which case nobody wants the result
+            if let Some(results) = &self.results {
+                let _ = results.send(result);
+            }
+            slot.status = Status::Taken;
+        } else {
+            slot.status = Status::Finished(result);
+        }
+        self.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Handle to a job submitted with `ThreadPool::execute` or `Scope::execute`, used to get that
+/// job's result
+///
+/// The result goes to whoever holds the handle. If the handle is dropped before the result has
+/// been taken from it, the result is passed on to `ThreadPool::get_results` instead, or dropped
+/// for a scoped job.
+pub struct JobHandle<T> {
+    state: Arc<JobState<T>>,
+}
+
+impl<T> JobHandle<T> {
+    pub(crate) fn new(state: Arc<JobState<T>>) -> Self {
+        JobHandle { state }
+    }
+
+    /// Whether the job has finished or been cancelled
+    pub fn is_finished(&self) -> bool {
+        !self.state.lock().status.is_pending()
+    }
+
+    /// Take the result if the job is done, without blocking. Returns `None` if it is still queued
+    /// or running
+    ///
+    /// Errors:
+    /// - `Panicked` if the job panicked
+    /// - `Cancelled` if the job was cancelled before it started
+    /// - `ResultTaken` if the result has already been taken from this handle
+    pub fn poll(&self) -> Option<JobResult<T>> {
+        let mut slot = self.state.lock();
+        take(&mut slot)
+    }
+
+    /// Block until the job is done, for at most `timeout`. Returns `None` if it still isn't
+    ///
+    /// Errors are the same as for `poll`.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<JobResult<T>> {
+        let slot = self.state.lock();
+        let (mut slot, _) = self
+            .state
+            .changed
+            .wait_timeout_while(slot, timeout, |slot| slot.status.is_pending())
+            .unwrap_or_else(|e| e.into_inner());
+        take(&mut slot)
+    }
+
+    /// Block until the job is done and take its result. Called from a worker, this runs other
+    /// queued jobs while it waits
+    ///
+    /// Errors are the same as for `poll`.
+    pub fn wait(self) -> JobResult<T> {
+        scheduler::help_until(
+            || self.is_finished(),
+            || {
+                let slot = self.state.lock();
+                let _ = self
+                    .state
+                    .changed
+                    .wait_timeout_while(slot, HELP_INTERVAL, |slot| slot.status.is_pending());
+            },
+        );
+
+        let slot = self.state.lock();
+        let mut slot = self
+            .state
+            .changed
+            .wait_while(slot, |slot| slot.status.is_pending())
+            .unwrap_or_else(|e| e.into_inner());
+        take(&mut slot).expect("job is no longer pending")
+    }
+
+    /// Stop the job from running if no worker has picked it up yet. Returns whether it was
+    /// cancelled
+    pub fn cancel(&self) -> bool {
+        self.state.cancel()
+    }
+}
+
+impl<T> Drop for JobHandle<T> {
+    fn drop(&mut self) {
+        let mut slot = self.state.lock();
+        slot.detached = true;
+        if let Status::Finished(_) = slot.status {
+            if let (Some(result), Some(results)) = (take(&mut slot), &self.state.results) {
+                let _ = results.send(result);
+            }
+        }
+    }
+}
+
+/// A job that was still queued when the pool was shut down with `ThreadPool::shutdown_now`
+///
+/// Its handle keeps waiting until the job is either run here or dropped, which cancels it.
+pub struct PendingJob(Job);
+
+impl PendingJob {
+    pub(crate) fn new(job: Job) -> Self {
+        PendingJob(job)
+    }
+
+    /// Run the job on the current thread, handing its result to its handle as the pool would
+    pub fn run(self) {
+        (self.0)();
+    }
+}
+
+/// Take the result out of a finished job, or `None` if it is still pending
+fn take<T>(slot: &mut Slot<T>) -> Option<JobResult<T>> {
+    match std::mem::replace(&mut slot.status, Status::Taken) {
+        Status::Finished(result) => Some(result),
+        Status::Cancelled => {
+            slot.status = Status::Cancelled;
+            Some(Err(ThreadPoolError::Cancelled))
+        }
+        Status::Taken => Some(Err(ThreadPoolError::ResultTaken)),
+        pending => {
+            slot.status = pending;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam::channel::unbounded;
+
+    use super::*;
+
+    #[test]
+    fn test_result_goes_to_handle() {
+        let (results, drained) = unbounded();
+        let state = JobState::new(Some(results));
+        let handle = JobHandle::new(Arc::clone(&state));
+
+        assert!(handle.poll().is_none());
+        assert!(state.start());
+        assert!(handle.wait_timeout(Duration::from_millis(10)).is_none());
+        state.finish(Ok(7));
+
+        assert!(handle.is_finished());
+        assert_eq!(handle.poll(), Some(Ok(7)));
+        assert_eq!(handle.poll(), Some(Err(ThreadPoolError::ResultTaken)));
+        drop(handle);
+        assert!(drained.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_dropped_handle_passes_result_on() {
+        let (results, drained) = unbounded();
+
+        // Dropped after the job finished
+        let state = JobState::new(Some(results.clone()));
+        let handle = JobHandle::new(Arc::clone(&state));
+        state.start();
+        state.finish(Ok(1));
+        drop(handle);
+
+        // Dropped before the job finished
+        let state = JobState::new(Some(results));
+        drop(JobHandle::new(Arc::clone(&state)));
+        state.start();
+        state.finish(Err(ThreadPoolError::Panicked("oops".to_string())));
+
+        assert_eq!(
+            drained.try_iter().collect::<Vec<_>>(),
+            vec![Ok(1), Err(ThreadPoolError::Panicked("oops".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (results, _drained) = unbounded::<JobResult<i32>>();
+        let state = JobState::new(Some(results));
+        let handle = JobHandle::new(Arc::clone(&state));
+
+        assert!(handle.cancel());
+        assert!(!state.start());
+        assert_eq!(handle.wait(), Err(ThreadPoolError::Cancelled));
+
+        let state = JobState::<i32>::new(None);
+        let handle = JobHandle::new(Arc::clone(&state));
+        state.start();
+        assert!(!handle.cancel());
+        state.finish(Ok(3));
+        assert_eq!(handle.wait(), Ok(3));
+    }
+}
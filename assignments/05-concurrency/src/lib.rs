@@ -0,0 +1,11 @@
+pub mod backpressure;
+pub mod error;
+pub mod job;
+pub mod par_iter;
+pub mod parallel_sort;
+pub mod priority;
+pub mod scheduler;
+pub mod scope;
+pub mod sort;
+pub mod thread_pool;
+mod workers;
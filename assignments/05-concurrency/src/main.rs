@@ -1,8 +1,12 @@
-use anyhow::Result;
-use rand::Rng;
+use std::{env, thread, time::Instant};
 
-mod error;
-mod thread_pool;
+use anyhow::{bail, Result};
+use concurrency::{
+    parallel_sort::{MergeSort, ParallelSort, RadixSort, SampleSort},
+    scheduler::Scheduler,
+    thread_pool::ThreadPool,
+};
+use rand::Rng;
 
 /// Generate a random vector of size capacity filled with random i64s
 fn random_vec(capacity: usize) -> Vec<i64> {
@@ -12,6 +16,43 @@ fn random_vec(capacity: usize) -> Vec<i64> {
 }
 
 fn main() -> Result<()> {
-    let data = random_vec(10_000_000);
+    let num_threads = match env::args().nth(1) {
+        Some(arg) => arg.parse()?,
+        None => thread::available_parallelism()?.get(),
+    };
+    let scheduler = match env::args().nth(2).as_deref() {
+        None | Some("fifo") => Scheduler::Fifo,
+        Some("work-stealing") => Scheduler::WorkStealing,
+        Some(other) => bail!(
+            "Unknown scheduler {:?}, expected fifo or work-stealing",
+            other
+        ),
+    };
+    let algorithm = env::args().nth(3).unwrap_or_else(|| "merge".to_string());
+    let mut data = random_vec(10_000_000);
+    let pool = ThreadPool::<()>::with_scheduler(num_threads, scheduler)?;
+    let par = pool.parallel();
+
+    let start = Instant::now();
+    match algorithm.as_str() {
+        "merge" => MergeSort.sort(&par, &mut data)?,
+        "sample" => SampleSort.sort(&par, &mut data)?,
+        "radix" => RadixSort.sort(&par, &mut data)?,
+        other => bail!(
+            "Unknown algorithm {:?}, expected merge, sample or radix",
+            other
+        ),
+    }
+    let elapsed = start.elapsed();
+
+    assert!(data.windows(2).all(|pair| pair[0] <= pair[1]));
+    println!(
+        "Sorted {} elements by {} sort with {} threads ({:?}) in {:?}",
+        data.len(),
+        algorithm,
+        num_threads,
+        scheduler,
+        elapsed
+    );
     Ok(())
 }
@@ -0,0 +1,282 @@
+use crate::{
+    error::ThreadPoolError,
+    job::JobHandle,
+    parallel_sort::{MergeSort, ParallelSort},
+    thread_pool::ThreadPool,
+};
+
+/// How slice operations are split up into jobs, created with `ThreadPool::parallel`
+///
+/// Every operation splits its slice into chunks of `chunk_size` elements, one job per chunk, and
+/// waits for all of them before returning. Results always come back in the order of the slice,
+/// however the jobs happen to be scheduled.
+pub struct Parallel<'pool, P> {
+    pool: &'pool ThreadPool<P>,
+
+    /// `None` for one chunk per worker
+    chunk_size: Option<usize>,
+}
+
+impl<P: Send + 'static> ThreadPool<P> {
+    /// Run slice operations from `ParallelSlice` on this pool, one chunk per worker
+    pub fn parallel(&self) -> Parallel<'_, P> {
+        Parallel {
+            pool: self,
+            chunk_size: None,
+        }
+    }
+}
+
+impl<P: Send + 'static> Parallel<'_, P> {
+    /// Split slices into chunks of `chunk_size` elements, rather than one chunk per worker
+    ///
+    /// Errors:
+    /// - If chunk_size is 0, return an error
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Result<Self, ThreadPoolError> {
+        if chunk_size == 0 {
+            return Err(ThreadPoolError::ZeroChunkSize);
+        }
+        self.chunk_size = Some(chunk_size);
+        Ok(self)
+    }
+
+    pub(crate) fn pool(&self) -> &ThreadPool<P> {
+        self.pool
+    }
+
+    /// How many elements of a slice `len` long go in each chunk
+    pub(crate) fn chunk_size(&self, len: usize) -> usize {
+        self.chunk_size
+            .unwrap_or_else(|| len.div_ceil(self.pool.num_threads().max(1)))
+            .max(1)
+    }
+
+    /// Run `f` on every chunk of `data` on the pool, returning the results in order
+    fn run_chunks<'data, T, F, R>(&self, data: &'data [T], f: F) -> Result<Vec<R>, ThreadPoolError>
+    where
+        T: Sync,
+        F: Fn(&'data [T]) -> R + Sync,
+        R: Send,
+    {
+        let f = &f;
+        self.pool.scope(|scope| {
+            let handles = data
+                .chunks(self.chunk_size(data.len()))
+                .map(|chunk| scope.execute(move || f(chunk)))
+                .collect::<Result<Vec<_>, _>>()?;
+            handles.into_iter().map(JobHandle::wait).collect()
+        })
+    }
+}
+
+/// Data-parallel operations on slices, and on `Vec`s through them, run on a `ThreadPool`
+///
+/// Every operation keeps the order of the slice: `par_map`, `par_filter` and `par_chunks` return
+/// their results in slice order, `par_reduce` combines neighbouring elements from left to right,
+/// and `par_sort` is stable.
+///
+/// Errors:
+/// - If a chunk can't be submitted, or its job panics, each operation returns that error once
+///   every other chunk has finished
+pub trait ParallelSlice<T> {
+    /// Apply `f` to every element
+    fn par_map<P, F, R>(&self, par: &Parallel<'_, P>, f: F) -> Result<Vec<R>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&T) -> R + Sync,
+        R: Send;
+
+    /// Clone every element for which `predicate` holds
+    fn par_filter<P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        predicate: F,
+    ) -> Result<Vec<T>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&T) -> bool + Sync,
+        T: Clone + Send;
+
+    /// Combine every element with `op`, which must be associative but needn't be commutative.
+    /// `None` if the slice is empty
+    fn par_reduce<P, F>(&self, par: &Parallel<'_, P>, op: F) -> Result<Option<T>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(T, T) -> T + Sync,
+        T: Clone + Send;
+
+    /// Apply `f` to every chunk, as split up by `par`
+    fn par_chunks<P, F, R>(&self, par: &Parallel<'_, P>, f: F) -> Result<Vec<R>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&[T]) -> R + Sync,
+        R: Send;
+
+    /// Sort with `MergeSort`: merge sort every chunk on the pool, then merge them
+    fn par_sort<P>(&mut self, par: &Parallel<'_, P>) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Ord + Clone + Send;
+}
+
+impl<T: Sync> ParallelSlice<T> for [T] {
+    fn par_map<P, F, R>(&self, par: &Parallel<'_, P>, f: F) -> Result<Vec<R>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&T) -> R + Sync,
+        R: Send,
+    {
+        let mapped = par.run_chunks(self, |chunk| chunk.iter().map(&f).collect::<Vec<_>>())?;
+        Ok(mapped.into_iter().flatten().collect())
+    }
+
+    fn par_filter<P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        predicate: F,
+    ) -> Result<Vec<T>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&T) -> bool + Sync,
+        T: Clone + Send,
+    {
+        let kept = par.run_chunks(self, |chunk| {
+            chunk
+                .iter()
+                .filter(|x| predicate(x))
+                .cloned()
+                .collect::<Vec<_>>()
+        })?;
+        Ok(kept.into_iter().flatten().collect())
+    }
+
+    fn par_reduce<P, F>(&self, par: &Parallel<'_, P>, op: F) -> Result<Option<T>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(T, T) -> T + Sync,
+        T: Clone + Send,
+    {
+        let partial = par.run_chunks(self, |chunk| chunk.iter().cloned().reduce(&op))?;
+        Ok(partial.into_iter().flatten().reduce(&op))
+    }
+
+    fn par_chunks<P, F, R>(&self, par: &Parallel<'_, P>, f: F) -> Result<Vec<R>, ThreadPoolError>
+    where
+        P: Send + 'static,
+        F: Fn(&[T]) -> R + Sync,
+        R: Send,
+    {
+        par.run_chunks(self, f)
+    }
+
+    fn par_sort<P>(&mut self, par: &Parallel<'_, P>) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Ord + Clone + Send,
+    {
+        MergeSort.sort(par, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::*;
+    use crate::scheduler::Scheduler;
+
+    #[test]
+    fn test_results_keep_slice_order() {
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::<()>::with_scheduler(3, scheduler).unwrap();
+            let par = pool.parallel().with_chunk_size(7).unwrap();
+            let data = (0..100).collect::<Vec<i64>>();
+
+            assert_eq!(
+                data.par_map(&par, |x| x * 2).unwrap(),
+                (0..100).map(|x| x * 2).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                data.par_filter(&par, |x| x % 3 == 0).unwrap(),
+                (0..100).filter(|x| x % 3 == 0).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                data.par_chunks(&par, |chunk| chunk.len()).unwrap(),
+                [vec![7; 14], vec![2]].concat()
+            );
+
+            // Concatenation isn't commutative, so this only works if chunks are combined in order
+            let words = data.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            assert_eq!(
+                words.par_reduce(&par, |a, b| a + &b).unwrap(),
+                Some(words.concat())
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_slices_and_zero_chunk_size() {
+        let pool = ThreadPool::<()>::new(2).unwrap();
+        let par = pool.parallel();
+        let empty: &[i64] = &[];
+        assert_eq!(empty.par_map(&par, |x| x + 1).unwrap(), Vec::<i64>::new());
+        assert_eq!(empty.par_reduce(&par, |a, b| a + b).unwrap(), None);
+        assert!(matches!(
+            pool.parallel().with_chunk_size(0),
+            Err(ThreadPoolError::ZeroChunkSize)
+        ));
+    }
+
+    /// Ordered by `key` alone, so a stable sort keeps `index` increasing among equal keys
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Keyed {
+        key: u8,
+        index: usize,
+    }
+
+    impl PartialOrd for Keyed {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Keyed {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    #[test]
+    fn test_par_sort_is_stable() {
+        let pool = ThreadPool::<()>::new(3).unwrap();
+        let par = pool.parallel().with_chunk_size(10).unwrap();
+        let mut data = (0..200)
+            .map(|index| Keyed {
+                key: (index * 7 % 5) as u8,
+                index,
+            })
+            .collect::<Vec<_>>();
+        let mut expected = data.clone();
+        expected.sort();
+
+        data.par_sort(&par).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_panicking_chunk_is_reported() {
+        let pool = ThreadPool::<()>::new(2).unwrap();
+        let par = pool.parallel().with_chunk_size(4).unwrap();
+        let data = (0..16).collect::<Vec<i64>>();
+        let result = data.par_map(&par, |&x| {
+            if x == 9 {
+                panic!("bad element");
+            }
+            x
+        });
+        assert_eq!(
+            result,
+            Err(ThreadPoolError::Panicked("bad element".to_string()))
+        );
+    }
+}
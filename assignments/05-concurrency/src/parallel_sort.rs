@@ -0,0 +1,395 @@
+use std::{cmp::Ordering, mem};
+
+use crate::{
+    error::ThreadPoolError,
+    job::JobHandle,
+    par_iter::{Parallel, ParallelSlice},
+    sort::{k_way_merge_by, merge_sort_by},
+};
+
+/// How many samples `SampleSort` takes per bucket to pick the splitters between buckets from
+const OVERSAMPLING: usize = 8;
+
+/// A parallel sorting algorithm for elements ordered by a key of type `K`
+///
+/// Every algorithm is stable, and splits its work into jobs on the pool of `par` as
+/// `ParallelSlice` does. The key is worked out again whenever it is needed, so it should be cheap.
+///
+/// Errors:
+/// - If a job can't be submitted, or panics, both methods return that error once every other job
+///   has finished, leaving `data` in some order
+pub trait ParallelSort<K> {
+    /// Sort `data` by the key `key` extracts from each element
+    fn sort_by_key<T, P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        data: &mut [T],
+        key: F,
+    ) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Clone + Send + Sync,
+        F: Fn(&T) -> K + Sync;
+
+    /// Sort `data`, using each element as its own key
+    fn sort<P>(&self, par: &Parallel<'_, P>, data: &mut [K]) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        K: Clone + Send + Sync,
+    {
+        self.sort_by_key(par, data, K::clone)
+    }
+}
+
+/// Merge sort every chunk on the pool, then merge the chunks on the calling thread with a k-way
+/// heap merge
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeSort;
+
+/// Sort a sample of the keys to split the range of keys into one bucket per chunk, move every
+/// element to its bucket, then merge sort each bucket on the pool
+///
+/// Unlike `MergeSort` nothing is left for the calling thread, but buckets are only as even as the
+/// sample is representative, and every element with the same key lands in the same bucket.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleSort;
+
+/// Least significant digit radix sort on integer keys, a byte at a time. Each pass counts and
+/// moves the chunks on the pool, and passes over bytes that are the same for every key are skipped
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RadixSort;
+
+impl<K: Ord> ParallelSort<K> for MergeSort {
+    fn sort_by_key<T, P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        data: &mut [T],
+        key: F,
+    ) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Clone + Send + Sync,
+        F: Fn(&T) -> K + Sync,
+    {
+        let compare = |a: &T, b: &T| key(a).cmp(&key(b));
+        let chunk_size = par.chunk_size(data.len());
+        sort_each(par, data.chunks_mut(chunk_size), &compare)?;
+
+        let merged = k_way_merge_by(&data.chunks(chunk_size).collect::<Vec<_>>(), compare);
+        data.clone_from_slice(&merged);
+        Ok(())
+    }
+}
+
+impl<K: Ord + Send + Sync> ParallelSort<K> for SampleSort {
+    fn sort_by_key<T, P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        data: &mut [T],
+        key: F,
+    ) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Clone + Send + Sync,
+        F: Fn(&T) -> K + Sync,
+    {
+        if data.len() <= 1 {
+            return Ok(());
+        }
+        let buckets = data.len().div_ceil(par.chunk_size(data.len()));
+
+        // Evenly spaced samples, so already sorted data splits evenly too
+        let samples = (buckets * OVERSAMPLING).min(data.len());
+        let stride = data.len() / samples;
+        let mut sample = (0..samples)
+            .map(|i| key(&data[i * stride]))
+            .collect::<Vec<_>>();
+        sample.sort();
+        let splitters = sample
+            .into_iter()
+            .skip(OVERSAMPLING)
+            .step_by(OVERSAMPLING)
+            .collect::<Vec<_>>();
+
+        let mut sorted = data.to_vec();
+        let bucket_of = |x: &T| {
+            let key = key(x);
+            splitters.partition_point(|splitter| *splitter <= key)
+        };
+        let sizes = scatter(par, data, &mut sorted, splitters.len() + 1, bucket_of)?;
+
+        let compare = |a: &T, b: &T| key(a).cmp(&key(b));
+        sort_each(par, split_into(&mut sorted, &sizes), &compare)?;
+        data.clone_from_slice(&sorted);
+        Ok(())
+    }
+}
+
+impl<K: RadixKey + Send + Sync> ParallelSort<K> for RadixSort {
+    fn sort_by_key<T, P, F>(
+        &self,
+        par: &Parallel<'_, P>,
+        data: &mut [T],
+        key: F,
+    ) -> Result<(), ThreadPoolError>
+    where
+        P: Send + 'static,
+        T: Clone + Send + Sync,
+        F: Fn(&T) -> K + Sync,
+    {
+        // Bits set in some keys but not others
+        let bits = data.par_chunks(par, |chunk| {
+            chunk.iter().fold((0, u64::MAX), |(any, all), x| {
+                let radix = key(x).radix();
+                (any | radix, all & radix)
+            })
+        })?;
+        // Combined across chunks before comparing, as a bit can be the same throughout every
+        // chunk and still differ between them
+        let (any, all) = bits
+            .into_iter()
+            .fold((0, u64::MAX), |(any, all), (chunk_any, chunk_all)| {
+                (any | chunk_any, all & chunk_all)
+            });
+        let varying = any ^ all;
+
+        let mut buffer = data.to_vec();
+        let mut in_buffer = false;
+        for shift in (0..K::BITS).step_by(8) {
+            if (varying >> shift) & 0xff == 0 {
+                continue;
+            }
+            let (src, dst) = if in_buffer {
+                (&buffer[..], &mut data[..])
+            } else {
+                (&data[..], &mut buffer[..])
+            };
+            let digit = |x: &T| ((key(x).radix() >> shift) & 0xff) as usize;
+            scatter(par, src, dst, 256, digit)?;
+            in_buffer = !in_buffer;
+        }
+        if in_buffer {
+            data.clone_from_slice(&buffer);
+        }
+        Ok(())
+    }
+}
+
+/// An integer key for `RadixSort`
+pub trait RadixKey: Copy {
+    const BITS: u32;
+
+    /// The key as an unsigned integer that sorts the same way
+    fn radix(self) -> u64;
+}
+
+macro_rules! radix_key_unsigned {
+    ($($t:ty),*) => {$(
+        impl RadixKey for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            fn radix(self) -> u64 {
+                self as u64
+            }
+        }
+    )*};
+}
+
+macro_rules! radix_key_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl RadixKey for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            /// Flipping the sign bit puts negative numbers before positive ones
+            fn radix(self) -> u64 {
+                ((self as $u) ^ (1 << (<$u>::BITS - 1))) as u64
+            }
+        }
+    )*};
+}
+
+radix_key_unsigned!(u8, u16, u32, u64, usize);
+radix_key_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, isize => usize);
+
+/// Merge sort each of `slices` in its own job on the pool
+fn sort_each<'a, T, P, F>(
+    par: &Parallel<'_, P>,
+    slices: impl IntoIterator<Item = &'a mut [T]>,
+    compare: &F,
+) -> Result<(), ThreadPoolError>
+where
+    P: Send + 'static,
+    T: Clone + Send + 'a,
+    F: Fn(&T, &T) -> Ordering + Sync,
+{
+    par.pool().scope(|scope| {
+        let handles = slices
+            .into_iter()
+            .map(|slice| scope.execute(move || merge_sort_by(slice, compare)))
+            .collect::<Result<Vec<_>, _>>()?;
+        handles.into_iter().try_for_each(JobHandle::wait)
+    })
+}
+
+/// Clone every element of `src` into `dst`, grouped into `buckets` by `bucket_of` and otherwise
+/// in the same order, moving each chunk of `src` in its own job. Returns the size of each bucket
+///
+/// Every chunk gets its own region of each bucket to write to, sized by counting the chunk's
+/// elements in each bucket first, so the jobs never write to the same place.
+fn scatter<T, P, F>(
+    par: &Parallel<'_, P>,
+    src: &[T],
+    dst: &mut [T],
+    buckets: usize,
+    bucket_of: F,
+) -> Result<Vec<usize>, ThreadPoolError>
+where
+    P: Send + 'static,
+    T: Clone + Send + Sync,
+    F: Fn(&T) -> usize + Sync,
+{
+    let bucket_of = &bucket_of;
+    let counts = src.par_chunks(par, |chunk| {
+        let mut counts = vec![0; buckets];
+        for x in chunk {
+            counts[bucket_of(x)] += 1;
+        }
+        counts
+    })?;
+    let sizes = (0..buckets)
+        .map(|bucket| counts.iter().map(|counts| counts[bucket]).sum())
+        .collect::<Vec<usize>>();
+
+    // Bucket by bucket, and chunk by chunk within each bucket
+    let mut regions = counts
+        .iter()
+        .map(|_| Vec::with_capacity(buckets))
+        .collect::<Vec<_>>();
+    let mut rest = dst;
+    for bucket in 0..buckets {
+        for (chunk, counts) in counts.iter().enumerate() {
+            let (region, tail) = mem::take(&mut rest).split_at_mut(counts[bucket]);
+            regions[chunk].push(region);
+            rest = tail;
+        }
+    }
+
+    par.pool().scope(|scope| {
+        let handles = src
+            .chunks(par.chunk_size(src.len()))
+            .zip(regions)
+            .map(|(chunk, mut regions)| {
+                scope.execute(move || {
+                    let mut filled = vec![0; buckets];
+                    for x in chunk {
+                        let bucket = bucket_of(x);
+                        regions[bucket][filled[bucket]] = x.clone();
+                        filled[bucket] += 1;
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        handles.into_iter().try_for_each(JobHandle::wait)
+    })?;
+    Ok(sizes)
+}
+
+/// Split `data` into consecutive slices of the given sizes
+fn split_into<'a, T>(mut data: &'a mut [T], sizes: &[usize]) -> Vec<&'a mut [T]> {
+    sizes
+        .iter()
+        .map(|&size| {
+            let (slice, rest) = mem::take(&mut data).split_at_mut(size);
+            data = rest;
+            slice
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{scheduler::Scheduler, thread_pool::ThreadPool};
+
+    /// Sort `data` with every algorithm, on a pool of `threads` workers split into chunks of
+    /// `chunk_size`, checking each against `slice::sort_by_key`
+    fn check_all<T, K, F>(data: &[T], threads: usize, chunk_size: Option<usize>, key: F)
+    where
+        T: Clone + Send + Sync + PartialEq + std::fmt::Debug,
+        K: RadixKey + Ord + Send + Sync,
+        F: Fn(&T) -> K + Sync + Copy,
+    {
+        let mut expected = data.to_vec();
+        expected.sort_by_key(key);
+
+        let pool = ThreadPool::<()>::with_scheduler(threads, Scheduler::WorkStealing).unwrap();
+        let par = match chunk_size {
+            Some(chunk_size) => pool.parallel().with_chunk_size(chunk_size).unwrap(),
+            None => pool.parallel(),
+        };
+
+        let mut merged = data.to_vec();
+        MergeSort.sort_by_key(&par, &mut merged, key).unwrap();
+        assert_eq!(merged, expected, "merge sort");
+
+        let mut sampled = data.to_vec();
+        SampleSort.sort_by_key(&par, &mut sampled, key).unwrap();
+        assert_eq!(sampled, expected, "sample sort");
+
+        let mut radixed = data.to_vec();
+        RadixSort.sort_by_key(&par, &mut radixed, key).unwrap();
+        assert_eq!(radixed, expected, "radix sort");
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        for data in [vec![], vec![5], vec![7; 100], (0..100).rev().collect()] {
+            check_all(&data, 2, None, |&x: &i64| x);
+        }
+        check_all(
+            &[i64::MIN, -1, 0, i64::MAX, 1, i64::MIN],
+            2,
+            Some(2),
+            |&x| x,
+        );
+        // Every chunk on its own has the same bits throughout
+        check_all(&[3, 1, 2], 3, Some(1), |&x: &u8| x);
+    }
+
+    #[test]
+    fn test_sort_without_key() {
+        let pool = ThreadPool::<()>::new(2).unwrap();
+        let par = pool.parallel();
+        let mut words = vec!["pear", "apple", "fig", "apple"];
+        MergeSort.sort(&par, &mut words).unwrap();
+        assert_eq!(words, vec!["apple", "apple", "fig", "pear"]);
+        SampleSort.sort(&par, &mut words).unwrap();
+        assert_eq!(words, vec!["apple", "apple", "fig", "pear"]);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_sorts_match_std(
+            data in prop::collection::vec(any::<i64>(), 0..2000),
+            threads in 1..5usize,
+            chunk_size in prop::option::of(1..500usize),
+        ) {
+            check_all(&data, threads, chunk_size, |&x| x);
+        }
+
+        /// Few distinct keys, tagged with where they started, so an unstable sort would show
+        #[test]
+        fn prop_sorts_are_stable(
+            keys in prop::collection::vec(-3..3i16, 0..2000),
+            threads in 1..5usize,
+            chunk_size in prop::option::of(1..500usize),
+        ) {
+            let data = keys.into_iter().enumerate().map(|(i, key)| (key, i)).collect::<Vec<_>>();
+            check_all(&data, threads, chunk_size, |&(key, _)| key);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::scheduler::{Job, Next};
+
+/// How urgent a job is. Only `Scheduler::Priority` pools take any notice
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    fn level(self) -> u32 {
+        match self {
+            Priority::Low => 0,
+            Priority::Normal => 1,
+            Priority::High => 2,
+        }
+    }
+}
+
+/// Where a job stands in the queue: smallest first, then in the order jobs were submitted
+type Key = (u128, u64);
+
+/// A single queue that hands out the job with the highest priority first
+///
+/// Jobs age as they wait: a job gains a priority level for every `aging` it has been queued, so
+/// a steady stream of urgent jobs can't hold back the others forever. As every queued job ages at
+/// the same rate, this only needs each job's key to be worked out once, when it is queued: its
+/// submission time, brought forward by `aging` for every level of priority it has.
+pub(crate) struct PriorityQueue {
+    jobs: Mutex<Jobs>,
+    available: Condvar,
+    closed: AtomicBool,
+    aging: Duration,
+    created: Instant,
+}
+
+#[derive(Default)]
+struct Jobs {
+    queued: BTreeMap<Key, Job>,
+    submitted: u64,
+}
+
+impl PriorityQueue {
+    pub(crate) fn new(aging: Duration) -> Self {
+        PriorityQueue {
+            jobs: Mutex::default(),
+            available: Condvar::new(),
+            closed: AtomicBool::new(false),
+            aging,
+            created: Instant::now(),
+        }
+    }
+
+    pub(crate) fn push(&self, job: Job, priority: Priority) {
+        let boost = self.aging.as_nanos() * u128::from(Priority::High.level() - priority.level());
+        let time = self.created.elapsed().as_nanos() + boost;
+        let mut jobs = self.lock();
+        let seq = jobs.submitted;
+        jobs.submitted += 1;
+        jobs.queued.insert((time, seq), job);
+        self.available.notify_one();
+    }
+
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _jobs = self.lock();
+        self.available.notify_all();
+    }
+
+    /// Wait for the most urgent job, for at most `timeout`
+    pub(crate) fn next_job(&self, timeout: Duration) -> Next {
+        let jobs = self.lock();
+        let (mut jobs, _) = self
+            .available
+            .wait_timeout_while(jobs, timeout, |jobs| {
+                jobs.queued.is_empty() && !self.closed.load(Ordering::SeqCst)
+            })
+            .unwrap_or_else(|e| e.into_inner());
+        match jobs.queued.pop_first() {
+            Some((_, job)) => Next::Job(job),
+            None if self.closed.load(Ordering::SeqCst) => Next::Closed,
+            None => Next::Idle,
+        }
+    }
+
+    /// Take the most urgent job, if there is one
+    pub(crate) fn try_next(&self) -> Option<Job> {
+        self.lock().queued.pop_first().map(|(_, job)| job)
+    }
+
+    /// Take the job that was submitted first, whatever its priority
+    pub(crate) fn take_oldest(&self) -> Option<Job> {
+        let mut jobs = self.lock();
+        let oldest = *jobs.queued.keys().min_by_key(|(_, seq)| *seq)?;
+        jobs.queued.remove(&oldest)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.lock().queued.is_empty()
+    }
+
+    pub(crate) fn drain(&self) -> Vec<Job> {
+        let queued = std::mem::take(&mut self.lock().queued);
+        queued.into_values().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    /// A job that records `label` when run
+    fn labelled(order: &Arc<Mutex<Vec<&'static str>>>, label: &'static str) -> Job {
+        let order = Arc::clone(order);
+        Box::new(move || order.lock().unwrap().push(label))
+    }
+
+    fn run_all(queue: &PriorityQueue) {
+        while let Some(job) = queue.try_next() {
+            job();
+        }
+    }
+
+    #[test]
+    fn test_higher_priority_first() {
+        let queue = PriorityQueue::new(Duration::from_secs(60));
+        let order = Arc::default();
+        queue.push(labelled(&order, "low"), Priority::Low);
+        queue.push(labelled(&order, "normal 1"), Priority::Normal);
+        queue.push(labelled(&order, "high"), Priority::High);
+        queue.push(labelled(&order, "normal 2"), Priority::Normal);
+
+        run_all(&queue);
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["high", "normal 1", "normal 2", "low"]
+        );
+    }
+
+    #[test]
+    fn test_waiting_jobs_age() {
+        let queue = PriorityQueue::new(Duration::from_millis(10));
+        let order = Arc::default();
+        queue.push(labelled(&order, "low"), Priority::Low);
+        thread::sleep(Duration::from_millis(30));
+        queue.push(labelled(&order, "high"), Priority::High);
+
+        run_all(&queue);
+        assert_eq!(*order.lock().unwrap(), vec!["low", "high"]);
+    }
+
+    #[test]
+    fn test_oldest_and_closed() {
+        let queue = PriorityQueue::new(Duration::from_secs(60));
+        let order = Arc::default();
+        queue.push(labelled(&order, "low"), Priority::Low);
+        queue.push(labelled(&order, "high"), Priority::High);
+
+        queue.take_oldest().unwrap()();
+        assert_eq!(*order.lock().unwrap(), vec!["low"]);
+
+        queue.close();
+        assert!(matches!(queue.next_job(Duration::ZERO), Next::Job(_)));
+        assert!(matches!(queue.next_job(Duration::ZERO), Next::Closed));
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    ptr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crossbeam::{
+    channel::{unbounded, Receiver, RecvTimeoutError, Sender},
+    deque::{Injector, Steal},
+    sync::{ShardedLock, ShardedLockReadGuard},
+};
+
+use crate::{
+    error::ThreadPoolError,
+    priority::{Priority, PriorityQueue},
+};
+
+/// A unit of work sent to the workers
+pub(crate) type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How jobs are handed out to the workers of a `ThreadPool`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Every job goes through one shared queue and runs in the order it was submitted
+    #[default]
+    Fifo,
+
+    /// Every worker has its own deque. Jobs submitted from inside a worker go on its deque, where
+    /// the worker runs the newest first, and idle workers steal the oldest jobs from the others
+    WorkStealing,
+
+    /// Every job goes through one shared queue, and the one with the highest `Priority` runs
+    /// first. A queued job gains a level of priority for every `aging` it waits, so lower
+    /// priority jobs still get their turn
+    Priority { aging: Duration },
+}
+
+thread_local! {
+    /// Where the worker running on this thread gets its jobs from, and which worker it is
+    static CURRENT: RefCell<Option<(JobSource, usize)>> = const { RefCell::new(None) };
+}
+
+/// The pool's end of the queue, used to submit jobs. Dropping it lets the workers exit once
+/// every job has run
+pub(crate) enum JobQueue {
+    Fifo(Sender<Job>),
+    WorkStealing(Arc<Deques>),
+    Priority(Arc<PriorityQueue>),
+}
+
+/// What a worker gets when it asks for its next job
+pub(crate) enum Next {
+    Job(Job),
+
+    /// Nothing turned up in time
+    Idle,
+
+    /// The queue is closed and every job has been taken, so the worker can exit
+    Closed,
+}
+
+/// The workers' end of the queue
+#[derive(Clone)]
+pub(crate) enum JobSource {
+    Fifo(Receiver<Job>),
+    WorkStealing(Arc<Deques>),
+    Priority(Arc<PriorityQueue>),
+}
+
+/// Create a queue for workers to be added to
+pub(crate) fn queue(scheduler: Scheduler) -> (JobQueue, JobSource) {
+    match scheduler {
+        Scheduler::Fifo => {
+            let (jobs, queue) = unbounded();
+            (JobQueue::Fifo(jobs), JobSource::Fifo(queue))
+        }
+        Scheduler::WorkStealing => {
+            let deques = Arc::new(Deques::new());
+            (
+                JobQueue::WorkStealing(Arc::clone(&deques)),
+                JobSource::WorkStealing(deques),
+            )
+        }
+        Scheduler::Priority { aging } => {
+            let jobs = Arc::new(PriorityQueue::new(aging));
+            (
+                JobQueue::Priority(Arc::clone(&jobs)),
+                JobSource::Priority(jobs),
+            )
+        }
+    }
+}
+
+impl JobQueue {
+    /// Submit a job. Its `priority` is ignored unless the queue is ordered by priority
+    ///
+    /// Errors:
+    /// - If the workers are gone, report an error
+    pub(crate) fn push(&self, job: Job, priority: Priority) -> Result<(), ThreadPoolError> {
+        match self {
+            JobQueue::Fifo(jobs) => jobs.send(job).map_err(|_| ThreadPoolError::Send),
+            JobQueue::WorkStealing(deques) => {
+                deques.push(job);
+                Ok(())
+            }
+            JobQueue::Priority(jobs) => {
+                jobs.push(job, priority);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for JobQueue {
+    fn drop(&mut self) {
+        // A FIFO queue is closed by dropping its sender
+        match self {
+            JobQueue::Fifo(_) => {}
+            JobQueue::WorkStealing(deques) => deques.close(),
+            JobQueue::Priority(jobs) => jobs.close(),
+        }
+    }
+}
+
+impl JobSource {
+    /// Make room for worker `index`, before it is started
+    pub(crate) fn add_worker(&self, index: usize) {
+        if let JobSource::WorkStealing(deques) = self {
+            deques.add_worker(index);
+        }
+    }
+
+    /// Let the jobs of worker `index` be taken by other workers, after it has stopped for good
+    pub(crate) fn remove_worker(&self, index: usize) {
+        if let JobSource::WorkStealing(deques) = self {
+            deques.remove_worker(index);
+        }
+    }
+
+    /// Mark this thread as worker `index`, so it knows where to put the jobs it submits and to
+    /// take jobs from while it waits
+    pub(crate) fn enter(&self, index: usize) {
+        CURRENT.with(|current| *current.borrow_mut() = Some((self.clone(), index)));
+    }
+
+    /// Wait for the next job for worker `index`, for at most `timeout`
+    pub(crate) fn next_job(&self, index: usize, timeout: Duration) -> Next {
+        match self {
+            JobSource::Fifo(queue) => match queue.recv_timeout(timeout) {
+                Ok(job) => Next::Job(job),
+                Err(RecvTimeoutError::Timeout) => Next::Idle,
+                Err(RecvTimeoutError::Disconnected) => Next::Closed,
+            },
+            JobSource::WorkStealing(deques) => deques.next_job(index, timeout),
+            JobSource::Priority(jobs) => jobs.next_job(timeout),
+        }
+    }
+
+    /// Take the job that has been queued the longest, or near enough with work stealing
+    pub(crate) fn take_oldest(&self) -> Option<Job> {
+        match self {
+            JobSource::Fifo(queue) => queue.try_recv().ok(),
+            JobSource::WorkStealing(deques) => deques.take_oldest(),
+            JobSource::Priority(jobs) => jobs.take_oldest(),
+        }
+    }
+
+    /// Whether no job is waiting to be picked up
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            JobSource::Fifo(queue) => queue.is_empty(),
+            JobSource::WorkStealing(deques) => deques.is_empty(),
+            JobSource::Priority(jobs) => jobs.is_empty(),
+        }
+    }
+
+    /// Take every job no worker has picked up yet
+    pub(crate) fn drain(&self) -> Vec<Job> {
+        match self {
+            JobSource::Fifo(queue) => queue.try_iter().collect(),
+            JobSource::WorkStealing(deques) => deques.drain(),
+            JobSource::Priority(jobs) => jobs.drain(),
+        }
+    }
+
+    /// Take the next job for worker `index`, if there is one ready
+    fn try_next(&self, index: usize) -> Option<Job> {
+        match self {
+            JobSource::Fifo(queue) => queue.try_recv().ok(),
+            JobSource::WorkStealing(deques) => deques.try_next(index),
+            JobSource::Priority(jobs) => jobs.try_next(),
+        }
+    }
+}
+
+/// On a worker thread, run queued jobs of the pool it works for until `done`, calling `park`
+/// whenever there are none. Does nothing on any other thread
+///
+/// This is how a worker waiting on other jobs stays useful: if a job it waits on is still queued,
+/// it may well be the one that ends up running it.
+pub(crate) fn help_until(done: impl Fn() -> bool, park: impl Fn()) {
+    if !CURRENT.with(|current| current.borrow().is_some()) {
+        return;
+    }
+    while !done() {
+        if !run_pending_job() {
+            park();
+        }
+    }
+}
+
+/// Run one queued job of the pool this thread works for. Returns `false` if there was nothing to
+/// run
+fn run_pending_job() -> bool {
+    let job = CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|(source, index)| source.try_next(*index))
+    });
+    match job {
+        Some(job) => {
+            job();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Queues of a work-stealing pool: one shared queue for jobs submitted from outside the pool, and
+/// one deque per worker for jobs submitted by the jobs it runs
+pub(crate) struct Deques {
+    global: Injector<Job>,
+
+    /// Indexed by worker, each behind its own lock, which is only contended when a worker is being
+    /// stolen from. Only grows, as the deques of removed workers are reused by new ones
+    locals: ShardedLock<Vec<Mutex<VecDeque<Job>>>>,
+    closed: AtomicBool,
+
+    /// Number of workers asleep on `wake`, so submitting a job only locks `sleep` when one is
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    wake: Condvar,
+}
+
+impl Deques {
+    fn new() -> Self {
+        Deques {
+            global: Injector::new(),
+            locals: ShardedLock::default(),
+            closed: AtomicBool::new(false),
+            sleepers: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Queue a job on the current worker's deque if it belongs to this pool, otherwise on the
+    /// shared queue
+    fn push(&self, job: Job) {
+        match self.current_worker() {
+            Some(index) => lock(&self.locals()[index]).push_back(job),
+            None => self.global.push(job),
+        }
+
+        // Pairs with the sleeper count going up before a worker looks for work one last time
+        atomic::fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_one();
+        }
+    }
+
+    fn add_worker(&self, index: usize) {
+        let mut locals = self.locals.write().unwrap_or_else(|e| e.into_inner());
+        while locals.len() <= index {
+            locals.push(Mutex::default());
+        }
+    }
+
+    /// Move whatever is left on the worker's deque to the shared queue
+    fn remove_worker(&self, index: usize) {
+        let jobs = lock(&self.locals()[index]).drain(..).collect::<Vec<_>>();
+        let moved = !jobs.is_empty();
+        for job in jobs {
+            self.global.push(job);
+        }
+        if moved {
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_all();
+        }
+    }
+
+    fn locals(&self) -> ShardedLockReadGuard<'_, Vec<Mutex<VecDeque<Job>>>> {
+        self.locals.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _sleep = lock(&self.sleep);
+        self.wake.notify_all();
+    }
+
+    /// Which worker of this pool is running on this thread, if any
+    fn current_worker(&self) -> Option<usize> {
+        CURRENT.with(|current| match &*current.borrow() {
+            Some((JobSource::WorkStealing(deques), index))
+                if ptr::eq(Arc::as_ptr(deques), self) =>
+            {
+                Some(*index)
+            }
+            _ => None,
+        })
+    }
+
+    fn next_job(&self, index: usize, timeout: Duration) -> Next {
+        match self.try_next(index).or_else(|| self.idle(index, timeout)) {
+            Some(job) => Next::Job(job),
+            None if self.closed.load(Ordering::SeqCst) => Next::Closed,
+            None => Next::Idle,
+        }
+    }
+
+    /// Newest job on our own deque, else the oldest submitted from outside the pool, else the
+    /// oldest on another worker's deque
+    fn try_next(&self, index: usize) -> Option<Job> {
+        let locals = self.locals();
+        if let Some(job) = lock(&locals[index]).pop_back() {
+            return Some(job);
+        }
+        loop {
+            match self.global.steal() {
+                Steal::Success(job) => return Some(job),
+                Steal::Empty => break,
+                Steal::Retry => {}
+            }
+        }
+        let n = locals.len();
+        (1..n).find_map(|offset| lock(&locals[(index + offset) % n]).pop_front())
+    }
+
+    /// The oldest job submitted from outside the pool, else the oldest job on any worker's deque
+    fn take_oldest(&self) -> Option<Job> {
+        loop {
+            match self.global.steal() {
+                Steal::Success(job) => return Some(job),
+                Steal::Empty => break,
+                Steal::Retry => {}
+            }
+        }
+        self.locals()
+            .iter()
+            .find_map(|local| lock(local).pop_front())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.global.is_empty() && self.locals().iter().all(|local| lock(local).is_empty())
+    }
+
+    fn drain(&self) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        loop {
+            match self.global.steal() {
+                Steal::Success(job) => jobs.push(job),
+                Steal::Empty => break,
+                Steal::Retry => {}
+            }
+        }
+        for local in self.locals().iter() {
+            jobs.extend(lock(local).drain(..));
+        }
+        jobs
+    }
+
+    /// Sleep until a job is submitted or the queue is closed, for at most `timeout`, unless one
+    /// turns up first
+    fn idle(&self, index: usize, timeout: Duration) -> Option<Job> {
+        let sleep = lock(&self.sleep);
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        // Looking again now means a job submitted before we counted as asleep isn't missed
+        let job = self.try_next(index);
+        if job.is_none() && !self.closed.load(Ordering::SeqCst) {
+            let _ = self.wake.wait_timeout(sleep, timeout);
+        }
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+        job
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// A work-stealing queue with room for `num_workers` workers
+    fn work_stealing(num_workers: usize) -> (JobQueue, JobSource) {
+        let (queue, source) = queue(Scheduler::WorkStealing);
+        for index in 0..num_workers {
+            source.add_worker(index);
+        }
+        (queue, source)
+    }
+
+    fn deques(source: &JobSource) -> &Deques {
+        match source {
+            JobSource::WorkStealing(deques) => deques,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_outside_jobs_go_to_shared_queue() {
+        let (queue, source) = work_stealing(2);
+        queue.push(Box::new(|| {}), Priority::Normal).unwrap();
+        let deques = deques(&source);
+        assert!(!deques.global.is_empty());
+        assert!(deques.locals().iter().all(|local| lock(local).is_empty()));
+        assert!(source.try_next(1).is_some());
+        assert!(source.try_next(0).is_none());
+    }
+
+    #[test]
+    fn test_worker_jobs_go_to_local_deque_and_can_be_stolen() {
+        let (queue, source) = work_stealing(3);
+
+        // Submit from worker 1's thread, as a job running there would
+        thread::spawn(move || {
+            source.enter(1);
+            let deques = deques(&source);
+            for _ in 0..3 {
+                deques.push(Box::new(|| {}));
+            }
+            assert!(deques.global.is_empty());
+            assert_eq!(lock(&deques.locals()[1]).len(), 3);
+
+            assert!(deques.try_next(2).is_some());
+            assert!(deques.try_next(1).is_some());
+
+            // Whatever a removed worker leaves behind goes back to the shared queue
+            deques.remove_worker(1);
+            assert!(!deques.global.is_empty());
+            assert!(deques.try_next(0).is_some());
+            assert!(deques.try_next(0).is_none());
+        })
+        .join()
+        .unwrap();
+        drop(queue);
+    }
+
+    #[test]
+    fn test_workers_exit_once_closed_and_drained() {
+        let (queue, source) = work_stealing(1);
+        let ran = Arc::new(AtomicUsize::new(0));
+        for _ in 0..5 {
+            let ran = Arc::clone(&ran);
+            queue
+                .push(
+                    Box::new(move || {
+                        ran.fetch_add(1, Ordering::SeqCst);
+                    }),
+                    Priority::Normal,
+                )
+                .unwrap();
+        }
+        let worker = thread::spawn(move || loop {
+            match source.next_job(0, Duration::from_millis(10)) {
+                Next::Job(job) => job(),
+                Next::Idle => {}
+                Next::Closed => return,
+            }
+        });
+        drop(queue);
+        worker.join().unwrap();
+        assert_eq!(ran.load(Ordering::SeqCst), 5);
+    }
+}
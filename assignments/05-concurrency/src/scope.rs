@@ -0,0 +1,222 @@
+use std::{
+    marker::PhantomData,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+use crate::{
+    error::ThreadPoolError,
+    job::{JobHandle, JobState, HELP_INTERVAL},
+    priority::Priority,
+    scheduler::{self, Job},
+    thread_pool::{self, ThreadPool},
+};
+
+/// Number of jobs of a scope that are still queued or running
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Pending {
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.count.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_done(&self) -> bool {
+        *self.lock() == 0
+    }
+}
+
+/// Counts as pending from creation until dropped
+struct PendingGuard(Arc<Pending>);
+
+impl PendingGuard {
+    fn new(pending: &Arc<Pending>) -> Self {
+        *pending.lock() += 1;
+        PendingGuard(Arc::clone(pending))
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        let mut count = self.0.lock();
+        *count -= 1;
+        if *count == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+/// A scoped job, which stops counting as pending once it has run or been thrown away
+struct ScopedJob<'scope> {
+    // Fields are dropped in order, so whatever the job borrows is gone before the scope hears of it
+    job: Box<dyn FnOnce() + Send + 'scope>,
+    _pending: PendingGuard,
+}
+
+impl ScopedJob<'_> {
+    fn run(self) {
+        (self.job)();
+    }
+}
+
+/// Jobs on a `ThreadPool` that may borrow from outside the scope, created with `ThreadPool::scope`
+pub struct Scope<'scope, 'env: 'scope, T> {
+    pool: &'scope ThreadPool<T>,
+    pending: Arc<Pending>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, T> Scope<'scope, '_, T> {
+    /// Execute the provided function on the pool, returning a handle to its result. The function
+    /// may borrow anything that outlives the scope
+    ///
+    /// Unlike with `ThreadPool::execute` the job can return any type, and its result is dropped
+    /// along with its handle rather than going to `get_results`.
+    ///
+    /// Errors:
+    /// - If we fail to send a message, report an error
+    pub fn execute<F, R>(&'scope self, f: F) -> Result<JobHandle<R>, ThreadPoolError>
+    where
+        F: FnOnce() -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        let state = JobState::new(None);
+        let scoped = ScopedJob {
+            job: thread_pool::job(f, Arc::clone(&state), None),
+            _pending: PendingGuard::new(&self.pending),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || scoped.run());
+        // SAFETY: the job only borrows data that outlives 'scope, and `ThreadPool::scope` doesn't
+        // return until every job of the scope has been run or dropped
+        let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        self.pool.push(job, Priority::default())?;
+        Ok(JobHandle::new(state))
+    }
+
+    /// Block until every job of the scope has been run or dropped
+    fn wait(&self) {
+        let park = || {
+            let count = self.pending.lock();
+            let _ = self
+                .pending
+                .done
+                .wait_timeout_while(count, HELP_INTERVAL, |count| *count > 0);
+        };
+        scheduler::help_until(|| self.pending.is_done(), park);
+
+        let count = self.pending.lock();
+        let _count = self
+            .pending
+            .done
+            .wait_while(count, |count| *count > 0)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+impl<T> ThreadPool<T> {
+    /// Run `f` with a `Scope` for submitting jobs that borrow non-`'static` data, such as chunks
+    /// of a slice. Every job of the scope has finished by the time this returns, even if `f`
+    /// panics, in which case the panic is passed on afterwards
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env, T>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            pending: Arc::default(),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+        match result {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::scheduler::Scheduler;
+
+    #[test]
+    fn test_jobs_borrow_from_the_stack() {
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::<()>::with_scheduler(3, scheduler).unwrap();
+            let mut data = (0..100).collect::<Vec<i64>>();
+            let label = "doubled".to_string();
+            let len = pool.scope(|scope| {
+                for chunk in data.chunks_mut(7) {
+                    scope
+                        .execute(move || chunk.iter_mut().for_each(|x| *x *= 2))
+                        .unwrap();
+                }
+                scope.execute(|| label.len()).unwrap()
+            });
+            assert_eq!(data, (0..100).map(|x| x * 2).collect::<Vec<_>>());
+            assert_eq!(len.wait(), Ok(7));
+        }
+    }
+
+    #[test]
+    fn test_scope_waits_for_dropped_handles_and_panics() {
+        let pool = ThreadPool::<()>::new(2).unwrap();
+        let mut done = [false; 4];
+        let handle = pool.scope(|scope| {
+            for flag in done.iter_mut() {
+                drop(scope.execute(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    *flag = true;
+                }));
+            }
+            scope.execute(|| panic!("scoped")).unwrap()
+        });
+        assert_eq!(done, [true; 4]);
+        assert_eq!(
+            handle.wait(),
+            Err(ThreadPoolError::Panicked("scoped".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_scope_panic_is_passed_on_after_jobs_finish() {
+        let pool = ThreadPool::<()>::new(1).unwrap();
+        let mut ran = false;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|scope| {
+                scope.execute(|| ran = true).unwrap();
+                panic!("scope body");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(ran);
+    }
+
+    #[test]
+    fn test_nested_scopes_on_one_worker() {
+        let pool = Arc::new(ThreadPool::with_scheduler(1, Scheduler::WorkStealing).unwrap());
+        let handle = {
+            let shared = Arc::clone(&pool);
+            pool.execute(move || {
+                let mut halves = [0, 0];
+                shared.scope(|scope| {
+                    for (i, half) in halves.iter_mut().enumerate() {
+                        scope.execute(move || *half = i + 1).unwrap();
+                    }
+                });
+                halves[0] + halves[1]
+            })
+            .unwrap()
+        };
+        assert_eq!(handle.wait(), Ok(3));
+    }
+}
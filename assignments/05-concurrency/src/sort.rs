@@ -0,0 +1,229 @@
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc};
+
+use crate::{
+    error::ThreadPoolError, job::JobResult, par_iter::ParallelSlice, thread_pool::ThreadPool,
+};
+
+/// Below this many elements, `recursive_merge_sort` sorts in place rather than spawning a job
+const SPLIT_THRESHOLD: usize = 1 << 16;
+
+/// Sort a vector with a plain single-threaded merge sort
+pub fn merge_sort<T: Ord + Clone>(mut data: Vec<T>) -> Vec<T> {
+    merge_sort_in_place(&mut data);
+    data
+}
+
+/// Sort a slice with a plain single-threaded merge sort. Equal elements keep their order
+pub fn merge_sort_in_place<T: Ord + Clone>(data: &mut [T]) {
+    merge_sort_by(data, T::cmp);
+}
+
+/// Sort a slice with a plain single-threaded merge sort, ordered by `compare`. Equal elements
+/// keep their order
+pub fn merge_sort_by<T, F>(data: &mut [T], compare: F)
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut scratch = data.to_vec();
+    sort_with_scratch(data, &mut scratch, &compare);
+}
+
+/// Merge sort `data`, using `scratch` (of the same length) to merge into
+fn sort_with_scratch<T, F>(data: &mut [T], scratch: &mut [T], compare: &F)
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    if data.len() <= 1 {
+        return;
+    }
+    let mid = data.len() / 2;
+    let (left, right) = data.split_at_mut(mid);
+    let (left_scratch, right_scratch) = scratch.split_at_mut(mid);
+    sort_with_scratch(left, left_scratch, compare);
+    sort_with_scratch(right, right_scratch, compare);
+    merge_into(left, right, scratch, compare);
+    data.clone_from_slice(scratch);
+}
+
+/// Merge two sorted slices into one sorted vector
+pub fn merge<T: Ord + Clone>(left: &[T], right: &[T]) -> Vec<T> {
+    let mut merged = [left, right].concat();
+    merge_into(left, right, &mut merged, &T::cmp);
+    merged
+}
+
+/// Merge two sorted slices into `out`, which must be exactly as long as both together
+fn merge_into<T, F>(left: &[T], right: &[T], out: &mut [T], compare: &F)
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let (mut i, mut j) = (0, 0);
+    for slot in out.iter_mut() {
+        if j == right.len() || (i < left.len() && compare(&left[i], &right[j]).is_le()) {
+            *slot = left[i].clone();
+            i += 1;
+        } else {
+            *slot = right[j].clone();
+            j += 1;
+        }
+    }
+}
+
+/// Merge sorted slices into one sorted vector. Equal elements come out in the order of the
+/// slices they came from
+pub fn k_way_merge<T: Ord + Clone>(chunks: &[&[T]]) -> Vec<T> {
+    k_way_merge_by(chunks, T::cmp)
+}
+
+/// Merge slices sorted by `compare` into one sorted vector. Equal elements come out in the order
+/// of the slices they came from
+pub fn k_way_merge_by<T, F>(chunks: &[&[T]], compare: F) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut merged = Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+    let mut iters = chunks.iter().map(|chunk| chunk.iter()).collect::<Vec<_>>();
+
+    // Smallest head of each chunk, along with the chunk it came from
+    let mut heads = BinaryHeap::new();
+    for (chunk, iter) in iters.iter_mut().enumerate() {
+        if let Some(value) = iter.next() {
+            heads.push(Head::new(value, chunk, &compare));
+        }
+    }
+    while let Some(head) = heads.pop() {
+        merged.push(head.value.clone());
+        if let Some(next) = iters[head.chunk].next() {
+            heads.push(Head::new(next, head.chunk, &compare));
+        }
+    }
+    merged
+}
+
+/// The next element of a chunk in `k_way_merge_by`, ordered so the heap pops the smallest first
+struct Head<'a, T, F> {
+    value: &'a T,
+    chunk: usize,
+    compare: &'a F,
+}
+
+impl<'a, T, F: Fn(&T, &T) -> Ordering> Head<'a, T, F> {
+    fn new(value: &'a T, chunk: usize, compare: &'a F) -> Self {
+        Head {
+            value,
+            chunk,
+            compare,
+        }
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Ord for Head<'_, T, F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(other.value, self.value).then(other.chunk.cmp(&self.chunk))
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> PartialOrd for Head<'_, T, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> PartialEq for Head<'_, T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Eq for Head<'_, T, F> {}
+
+/// Sort `data` on the pool, one chunk per worker, with `ParallelSlice::par_sort`
+///
+/// Errors:
+/// - If a chunk can't be submitted or its job fails, return that error
+pub fn concurrent_merge_sort<T: Send + 'static>(
+    pool: &ThreadPool<T>,
+    data: &mut [i64],
+) -> Result<(), ThreadPoolError> {
+    data.par_sort(&pool.parallel())
+}
+
+/// Merge sort `data` by handing one half to a new job on the pool and sorting the other half in
+/// place, all the way down to `SPLIT_THRESHOLD` elements
+///
+/// Errors:
+/// - If a half can't be submitted or its job fails, return that error
+pub fn recursive_merge_sort(
+    pool: &Arc<ThreadPool<JobResult<Vec<i64>>>>,
+    mut data: Vec<i64>,
+) -> JobResult<Vec<i64>> {
+    if data.len() <= SPLIT_THRESHOLD {
+        return Ok(merge_sort(data));
+    }
+    let right = data.split_off(data.len() / 2);
+    let right = {
+        let shared = Arc::clone(pool);
+        pool.execute(move || recursive_merge_sort(&shared, right))?
+    };
+    let left = recursive_merge_sort(pool, data)?;
+    Ok(merge(&left, &right.wait()??))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::scheduler::Scheduler;
+
+    fn random_vec(capacity: usize) -> Vec<i64> {
+        let mut vec = vec![0; capacity];
+        rand::thread_rng().fill(&mut vec[..]);
+        vec
+    }
+
+    fn sorted(data: &[i64]) -> Vec<i64> {
+        let mut sorted = data.to_vec();
+        sorted.sort();
+        sorted
+    }
+
+    #[test]
+    fn test_merge_sort() {
+        assert_eq!(merge_sort(Vec::<i64>::new()), Vec::<i64>::new());
+        assert_eq!(merge_sort(vec![3, -1, 2, 2, 0]), vec![-1, 0, 2, 2, 3]);
+        assert_eq!(merge(&[1, 1, 4], &[0, 2]), vec![0, 1, 1, 2, 4]);
+        assert_eq!(
+            k_way_merge(&[&[1, 4], &[], &[2, 3, 5]]),
+            vec![1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_merge_sort() {
+        let data = random_vec(10_000);
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::<()>::with_scheduler(3, scheduler).unwrap();
+            let mut sorting = data.clone();
+            concurrent_merge_sort(&pool, &mut sorting).unwrap();
+            assert_eq!(sorting, sorted(&data));
+        }
+    }
+
+    #[test]
+    fn test_recursive_merge_sort() {
+        // Deep enough that there are far more waiting jobs than workers
+        let data = random_vec(SPLIT_THRESHOLD * 16 + 1);
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = Arc::new(ThreadPool::with_scheduler(2, scheduler).unwrap());
+            assert_eq!(
+                recursive_merge_sort(&pool, data.clone()).unwrap(),
+                sorted(&data)
+            );
+        }
+    }
+}
@@ -1,23 +1,887 @@
-pub struct ThreadPool {}
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-impl ThreadPool {
-    /// Create a new LocalThreadPool with num_threads threads.
+use crossbeam::{
+    channel::{unbounded, Receiver, Sender},
+    sync::ShardedLock,
+};
+
+use crate::{
+    backpressure::{Backpressure, QueueLimit, QueueMetrics},
+    error::ThreadPoolError,
+    job::{JobHandle, JobOptions, JobResult, JobState, PendingJob},
+    priority::Priority,
+    scheduler::{self, Job, JobQueue, JobSource, Scheduler},
+    workers::Workers,
+};
+
+/// A set of worker threads that run jobs returning a `T`
+///
+/// A job that panics doesn't take its worker down with it: the panic is caught and handed back
+/// as that job's result. Should a worker die anyway it is replaced, so the pool never shrinks
+/// unless told to with `resize` or `set_elastic`.
+///
+/// Jobs may submit more jobs and wait on them: a worker waiting on a handle runs queued jobs in
+/// the meantime, so recursive divide-and-conquer jobs can't tie up every worker. The pool can be
+/// shared with its own jobs through an `Arc`.
+///
+/// Dropping the pool shuts it down with `shutdown`.
+pub struct ThreadPool<T> {
+    workers: Arc<Workers>,
+
+    /// `None` once the pool has been shut down. Read on every `execute`, which `ShardedLock` is
+    /// made for
+    jobs: ShardedLock<Option<JobQueue>>,
+
+    /// The workers' end of the queue, for taking back jobs in `shutdown_now` and dropping them
+    /// with `Backpressure::DropOldest`
+    queue: JobSource,
+    limit: QueueLimit,
+
+    /// Results of jobs whose handles were dropped, waiting for `get_results`
+    results: (Sender<JobResult<T>>, Receiver<JobResult<T>>),
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    /// Create a new ThreadPool with num_threads threads, which runs jobs in the order they are
+    /// submitted.
+    ///
+    /// Errors:
+    /// - If num_threads is 0, return an error
+    pub fn new(num_threads: usize) -> Result<Self, ThreadPoolError> {
+        Self::with_scheduler(num_threads, Scheduler::Fifo)
+    }
+
+    /// Create a new ThreadPool with num_threads threads, which hands out jobs with `scheduler`.
+    ///
+    /// Errors:
+    /// - If num_threads is 0, return an error
+    pub fn with_scheduler(
+        num_threads: usize,
+        scheduler: Scheduler,
+    ) -> Result<Self, ThreadPoolError> {
+        if num_threads == 0 {
+            return Err(ThreadPoolError::ZeroThreads);
+        }
+
+        let (jobs, queue) = scheduler::queue(scheduler);
+        Ok(ThreadPool {
+            workers: Workers::start(queue.clone(), num_threads),
+            jobs: ShardedLock::new(Some(jobs)),
+            queue,
+            limit: QueueLimit::default(),
+            results: unbounded(),
+        })
+    }
+
+    /// Let at most `capacity` jobs wait for a worker, applying `policy` to any job submitted
+    /// while that many are waiting. Jobs already running don't count
+    ///
+    /// Errors:
+    /// - If capacity is 0, return an error
+    pub fn with_bounded_queue(
+        mut self,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> Result<Self, ThreadPoolError> {
+        if capacity == 0 {
+            return Err(ThreadPoolError::ZeroCapacity);
+        }
+        self.limit = QueueLimit::new(Some(capacity), policy);
+        Ok(self)
+    }
+
+    /// Number of worker threads
+    pub fn num_threads(&self) -> usize {
+        self.workers.live()
+    }
+
+    /// Change the number of worker threads, leaving elastic mode if the pool was in it. New
+    /// workers start straight away, while surplus ones retire as soon as they finish their job.
+    /// Queued jobs are kept either way
     ///
     /// Errors:
     /// - If num_threads is 0, return an error
-    pub fn new(num_threads: usize) {
-        todo!()
+    pub fn resize(&self, num_threads: usize) -> Result<(), ThreadPoolError> {
+        if num_threads == 0 {
+            return Err(ThreadPoolError::ZeroThreads);
+        }
+        self.workers.resize(num_threads, num_threads, None);
+        Ok(())
+    }
+
+    /// Scale the pool with its load: keep at least `min_threads` workers, start more whenever
+    /// jobs are queued with no idle worker to take them, up to `max_threads`, and retire workers
+    /// that have been idle for `keep_alive` down to `min_threads` again
+    ///
+    /// Errors:
+    /// - If max_threads is 0, return an error
+    /// - If min_threads is greater than max_threads, return an error
+    pub fn set_elastic(
+        &self,
+        min_threads: usize,
+        max_threads: usize,
+        keep_alive: Duration,
+    ) -> Result<(), ThreadPoolError> {
+        if max_threads == 0 {
+            return Err(ThreadPoolError::ZeroThreads);
+        }
+        if min_threads > max_threads {
+            return Err(ThreadPoolError::InvalidThreadRange(
+                min_threads,
+                max_threads,
+            ));
+        }
+        self.workers
+            .resize(min_threads, max_threads, Some(keep_alive));
+        Ok(())
+    }
+
+    /// Execute the provided function on the thread pool, returning a handle to its result
+    ///
+    /// With the work-stealing scheduler, jobs submitted by a job running on this pool go on the
+    /// queue of the worker running it.
+    ///
+    /// If the queue is bounded and full, what happens depends on its `Backpressure` policy.
+    ///
+    /// Errors:
+    /// - If the pool has been shut down, return `ShutDown`
+    /// - If the queue is full and its policy is `Backpressure::Fail`, return `QueueFull`
+    /// - If we fail to send a message, report an error
+    pub fn execute<F>(&self, f: F) -> Result<JobHandle<T>, ThreadPoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.execute_with(JobOptions::default(), f)
+    }
+
+    /// Execute the provided function on the thread pool with the given `priority`, as with
+    /// `execute_with`
+    ///
+    /// Errors:
+    /// - As for `execute`
+    pub fn execute_with_priority<F>(
+        &self,
+        priority: Priority,
+        f: F,
+    ) -> Result<JobHandle<T>, ThreadPoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let options = JobOptions {
+            priority,
+            ..JobOptions::default()
+        };
+        self.execute_with(options, f)
+    }
+
+    /// Execute the provided function on the thread pool, as with `execute`, scheduled according
+    /// to `options`
+    ///
+    /// With `Scheduler::Priority`, jobs with a higher priority are taken off the queue first.
+    /// Other schedulers ignore priorities. A job no worker has started by its deadline is skipped,
+    /// and its result is an `Expired` error.
+    ///
+    /// Errors:
+    /// - As for `execute`
+    pub fn execute_with<F>(
+        &self,
+        options: JobOptions,
+        f: F,
+    ) -> Result<JobHandle<T>, ThreadPoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let state = JobState::new(Some(self.results.0.clone()));
+        self.push(
+            job(f, Arc::clone(&state), options.deadline),
+            options.priority,
+        )?;
+        Ok(JobHandle::new(state))
+    }
+
+    /// Retrieve any results from the thread pool that have been computed, other than those
+    /// taken through their `JobHandle`. Jobs that panicked have a `Panicked` error as their result
+    pub fn get_results(&self) -> Vec<JobResult<T>> {
+        self.results.1.try_iter().collect()
+    }
+}
+
+impl<T> ThreadPool<T> {
+    /// Stop accepting jobs, let the workers finish every job already submitted, then wait for
+    /// them to exit. Does nothing more if the pool was already shut down
+    pub fn shutdown(&self) {
+        self.close();
+        self.join();
+    }
+
+    /// Stop accepting jobs and take back every job no worker has started yet, then wait for the
+    /// workers to finish the jobs they are running and exit
+    ///
+    /// The handles of the returned jobs keep waiting until each job is run or dropped, which
+    /// cancels it.
+    pub fn shutdown_now(&self) -> Vec<PendingJob> {
+        self.close();
+        let pending = self.queue.drain();
+        self.join();
+        pending.into_iter().map(PendingJob::new).collect()
+    }
+
+    /// Current depth of the job queue, and what its backpressure policy has done so far
+    pub fn queue_metrics(&self) -> QueueMetrics {
+        self.limit.metrics()
     }
 
-    /// Execute the provided function on the thread pool
+    /// Whether `shutdown` or `shutdown_now` has been called
+    pub fn is_shut_down(&self) -> bool {
+        self.jobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    /// Hand a job to the workers, once there is room for it in the queue
     ///
     /// Errors:
+    /// - If the pool has been shut down, return `ShutDown`
+    /// - If the queue is full and its policy is `Backpressure::Fail`, return `QueueFull`
     /// - If we fail to send a message, report an error
-    pub fn execute<F>(&self, f: F) {
-        todo!()
+    pub(crate) fn push(&self, job: Job, priority: Priority) -> Result<(), ThreadPoolError> {
+        if self.is_shut_down() {
+            return Err(ThreadPoolError::ShutDown);
+        }
+
+        let mut blocked = false;
+        while !self.limit.try_reserve() {
+            match self.limit.policy() {
+                Backpressure::Block => {
+                    if !blocked {
+                        self.limit.record_blocked();
+                        blocked = true;
+                    }
+                    self.limit.wait_for_space();
+                    if self.is_shut_down() {
+                        return Err(ThreadPoolError::ShutDown);
+                    }
+                }
+                Backpressure::Fail => {
+                    self.limit.record_rejected();
+                    return Err(ThreadPoolError::QueueFull);
+                }
+                Backpressure::DropOldest => {
+                    // Comes up empty if workers took every job first, which makes room just as
+                    // well, or if the queue is full of jobs still on their way in
+                    match self.queue.take_oldest() {
+                        Some(oldest) => {
+                            self.limit.record_dropped();
+                            drop(oldest);
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                Backpressure::CallerRuns => {
+                    self.limit.record_ran_on_caller();
+                    job();
+                    return Ok(());
+                }
+            }
+        }
+
+        let job = self.limit.track(job);
+        self.jobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .ok_or(ThreadPoolError::ShutDown)?
+            .push(job, priority)?;
+        self.workers.grow_if_backed_up(self.limit.depth());
+        Ok(())
+    }
+
+    /// Stop accepting jobs, which lets the workers exit once there are none left
+    fn close(&self) {
+        let queue = self.jobs.write().unwrap_or_else(|e| e.into_inner()).take();
+        drop(queue);
+        self.workers.close();
+    }
+
+    /// Wait for every worker to exit
+    fn join(&self) {
+        self.workers.join();
+    }
+}
+
+impl<T> Drop for ThreadPool<T> {
+    fn drop(&mut self) {
+        self.shutdown();
     }
-    /// Retrieve any results from the thread pool that have been computed
-    pub fn get_results(&self) {
-        todo!()
+}
+
+/// Cancels a job that is dropped without ever being run, so its handle doesn't wait forever
+struct CancelOnDrop<R>(Arc<JobState<R>>);
+
+impl<R> Drop for CancelOnDrop<R> {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Wrap `f` into a job that runs it unless cancelled or started after `deadline`, and hands its
+/// result or panic to `state`
+pub(crate) fn job<'a, F, R>(
+    f: F,
+    state: Arc<JobState<R>>,
+    deadline: Option<Instant>,
+) -> Box<dyn FnOnce() + Send + 'a>
+where
+    F: FnOnce() -> R + Send + 'a,
+    R: Send + 'a,
+{
+    let state = CancelOnDrop(state);
+    Box::new(move || {
+        let CancelOnDrop(state) = &state;
+        if !state.start() {
+            return;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            state.finish(Err(ThreadPoolError::Expired));
+            return;
+        }
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => state.finish(Ok(result)),
+            Err(payload) => {
+                state.finish(Err(ThreadPoolError::Panicked(panic_message(&*payload))));
+                // Only dropped once the result is in, as this can panic too
+                drop(payload);
+            }
+        }
+    })
+}
+
+/// The message a panic was raised with, if it has one
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Barrier, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_zero_threads() {
+        assert!(matches!(
+            ThreadPool::<()>::new(0),
+            Err(ThreadPoolError::ZeroThreads)
+        ));
+    }
+
+    #[test]
+    fn test_handles_match_their_jobs() {
+        let pool = ThreadPool::new(4).unwrap();
+        let handles = (0..100)
+            .map(|i| pool.execute(move || i * 2).unwrap())
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.wait(), Ok(i * 2));
+        }
+        assert!(pool.get_results().is_empty());
+    }
+
+    #[test]
+    fn test_get_results_drains_unclaimed_results() {
+        let pool = ThreadPool::new(2).unwrap();
+        let kept = pool.execute(|| 1).unwrap();
+        for i in 2..=4 {
+            drop(pool.execute(move || i).unwrap());
+        }
+        assert_eq!(kept.wait(), Ok(1));
+
+        // The other jobs may still be running, so keep draining until they have all arrived
+        let mut results = Vec::new();
+        while results.len() < 3 {
+            results.extend(pool.get_results());
+            thread::yield_now();
+        }
+        let mut results = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(results, vec![2, 3, 4]);
+    }
+
+    /// Whether the pool can run `n` jobs at the same time
+    fn runs_at_once(pool: &ThreadPool<bool>, n: usize) -> bool {
+        let started = Arc::new(AtomicUsize::new(0));
+        let handles = (0..n)
+            .map(|_| {
+                let started = Arc::clone(&started);
+                pool.execute(move || {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    let deadline = Instant::now() + Duration::from_secs(5);
+                    while started.load(Ordering::SeqCst) < n && Instant::now() < deadline {
+                        thread::yield_now();
+                    }
+                    started.load(Ordering::SeqCst) >= n
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().all(|handle| handle.wait() == Ok(true))
+    }
+
+    #[test]
+    fn test_panics_are_returned_as_errors() {
+        let pool = ThreadPool::new(2).unwrap();
+        let handles = (0..10)
+            .map(|i| {
+                pool.execute(move || {
+                    if i % 2 == 0 {
+                        panic!("job {} failed", i);
+                    }
+                    true
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            match i % 2 {
+                0 => assert_eq!(
+                    handle.wait(),
+                    Err(ThreadPoolError::Panicked(format!("job {} failed", i)))
+                ),
+                _ => assert_eq!(handle.wait(), Ok(true)),
+            }
+        }
+        assert!(runs_at_once(&pool, 2));
+
+        drop(pool.execute(|| panic!("nobody is waiting")).unwrap());
+        let results = loop {
+            let results = pool.get_results();
+            if !results.is_empty() {
+                break results;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(
+            results,
+            vec![Err(ThreadPoolError::Panicked(
+                "nobody is waiting".to_string()
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_dead_workers_are_replaced() {
+        /// Panic payload that panics again when dropped, which kills the worker dropping it
+        struct PanicOnDrop;
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("dropped");
+            }
+        }
+
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::with_scheduler(3, scheduler).unwrap();
+            for _ in 0..3 {
+                let handle = pool.execute(|| panic::panic_any(PanicOnDrop)).unwrap();
+                assert_eq!(
+                    handle.wait(),
+                    Err(ThreadPoolError::Panicked("Box<dyn Any>".to_string()))
+                );
+            }
+            assert!(runs_at_once(&pool, 3));
+            assert_eq!(pool.num_threads(), 3);
+        }
+    }
+
+    #[test]
+    fn test_jobs_can_wait_on_their_own_subjobs() {
+        /// Sum 0..n by having each job spawn one for the rest of the range and wait on it
+        fn sum(pool: &Arc<ThreadPool<usize>>, n: usize) -> usize {
+            if n == 0 {
+                return 0;
+            }
+            let rest = {
+                let shared = Arc::clone(pool);
+                pool.execute(move || sum(&shared, n - 1)).unwrap()
+            };
+            n - 1 + rest.wait().unwrap()
+        }
+
+        // A single worker has to run every subjob while waiting on it
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = Arc::new(ThreadPool::with_scheduler(1, scheduler).unwrap());
+            let handle = {
+                let shared = Arc::clone(&pool);
+                pool.execute(move || sum(&shared, 100)).unwrap()
+            };
+            assert_eq!(handle.wait(), Ok(4950));
+        }
+    }
+
+    #[test]
+    fn test_shutdown_finishes_queued_jobs() {
+        let pool = ThreadPool::new(2).unwrap();
+        let handles = (0..6)
+            .map(|i| {
+                pool.execute(move || {
+                    thread::sleep(Duration::from_millis(5));
+                    i
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        pool.shutdown();
+        assert!(pool.is_shut_down());
+        assert!(handles.iter().all(JobHandle::is_finished));
+        assert!(matches!(pool.execute(|| 0), Err(ThreadPoolError::ShutDown)));
+        assert_eq!(pool.num_threads(), 0);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn test_shutdown_now_returns_queued_jobs() {
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::with_scheduler(1, scheduler).unwrap();
+            let (started, has_started) = crossbeam::channel::bounded(1);
+            let gate = Arc::new(Barrier::new(2));
+            let blocker = {
+                let gate = Arc::clone(&gate);
+                pool.execute(move || {
+                    started.send(()).unwrap();
+                    gate.wait();
+                    0
+                })
+                .unwrap()
+            };
+            let queued = (1..=3)
+                .map(|i| pool.execute(move || i).unwrap())
+                .collect::<Vec<_>>();
+
+            has_started.recv().unwrap();
+            let opener = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                gate.wait();
+            });
+            let mut pending = pool.shutdown_now();
+            opener.join().unwrap();
+
+            assert_eq!(pending.len(), 3);
+            assert_eq!(blocker.wait(), Ok(0));
+            pending.remove(0).run();
+            drop(pending);
+            assert_eq!(
+                queued.into_iter().map(JobHandle::wait).collect::<Vec<_>>(),
+                vec![
+                    Ok(1),
+                    Err(ThreadPoolError::Cancelled),
+                    Err(ThreadPoolError::Cancelled)
+                ]
+            );
+            assert!(matches!(pool.execute(|| 0), Err(ThreadPoolError::ShutDown)));
+        }
+    }
+
+    /// Wait for up to five seconds for the pool to have `n` workers
+    fn settles_at(pool: &ThreadPool<bool>, n: usize) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.num_threads() != n && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        pool.num_threads() == n
+    }
+
+    #[test]
+    fn test_resize_keeps_queued_jobs() {
+        for scheduler in [Scheduler::Fifo, Scheduler::WorkStealing] {
+            let pool = ThreadPool::with_scheduler(2, scheduler).unwrap();
+            assert!(matches!(pool.resize(0), Err(ThreadPoolError::ZeroThreads)));
+
+            pool.resize(4).unwrap();
+            assert_eq!(pool.num_threads(), 4);
+            assert!(runs_at_once(&pool, 4));
+
+            let handles = (0..20)
+                .map(|_| {
+                    pool.execute(|| {
+                        thread::sleep(Duration::from_millis(2));
+                        true
+                    })
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+            pool.resize(1).unwrap();
+            assert!(handles.into_iter().all(|handle| handle.wait() == Ok(true)));
+            assert!(settles_at(&pool, 1));
+            assert!(pool.execute(|| true).unwrap().wait().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_elastic_pool_grows_and_shrinks() {
+        let pool = ThreadPool::new(1).unwrap();
+        assert!(matches!(
+            pool.set_elastic(3, 2, Duration::ZERO),
+            Err(ThreadPoolError::InvalidThreadRange(3, 2))
+        ));
+        pool.set_elastic(1, 4, Duration::from_millis(50)).unwrap();
+        assert_eq!(pool.num_threads(), 1);
+
+        // Only possible if workers are added as the jobs queue up
+        assert!(runs_at_once(&pool, 4));
+        assert_eq!(pool.num_threads(), 4);
+        assert!(settles_at(&pool, 1));
+
+        pool.set_elastic(2, 2, Duration::from_millis(50)).unwrap();
+        assert_eq!(pool.num_threads(), 2);
+    }
+
+    #[test]
+    fn test_elastic_pool_without_minimum_runs_every_job() {
+        for scheduler in [
+            Scheduler::Fifo,
+            Scheduler::WorkStealing,
+            Scheduler::Priority {
+                aging: Duration::from_millis(10),
+            },
+        ] {
+            let pool = ThreadPool::with_scheduler(1, scheduler).unwrap();
+            pool.set_elastic(0, 2, Duration::ZERO).unwrap();
+            assert!(settles_at(&pool, 0));
+
+            // Workers retire as soon as they are idle, so jobs keep arriving just as the last one
+            // is leaving
+            for i in 0..100 {
+                let handle = pool.execute(|| true).unwrap();
+                assert_eq!(
+                    handle.wait_timeout(Duration::from_secs(5)),
+                    Some(Ok(true)),
+                    "job {} was stranded",
+                    i
+                );
+                thread::sleep(Duration::from_micros(i % 50 * 500));
+            }
+        }
+    }
+
+    /// A one-thread pool with room for two queued jobs, whose worker is stuck on a job until the
+    /// returned barrier is waited on
+    fn busy_bounded_pool(
+        policy: Backpressure,
+    ) -> (
+        ThreadPool<thread::ThreadId>,
+        Arc<Barrier>,
+        JobHandle<thread::ThreadId>,
+    ) {
+        let pool = ThreadPool::new(1)
+            .unwrap()
+            .with_bounded_queue(2, policy)
+            .unwrap();
+        let (started, has_started) = crossbeam::channel::bounded(1);
+        let gate = Arc::new(Barrier::new(2));
+        let blocker = {
+            let gate = Arc::clone(&gate);
+            pool.execute(move || {
+                started.send(()).unwrap();
+                gate.wait();
+                thread::current().id()
+            })
+            .unwrap()
+        };
+        has_started.recv().unwrap();
+        (pool, gate, blocker)
+    }
+
+    fn current_thread() -> thread::ThreadId {
+        thread::current().id()
+    }
+
+    #[test]
+    fn test_full_queue_fails() {
+        let (pool, gate, _blocker) = busy_bounded_pool(Backpressure::Fail);
+        let queued = (0..2)
+            .map(|_| pool.execute(current_thread).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            pool.execute(current_thread),
+            Err(ThreadPoolError::QueueFull)
+        ));
+
+        let metrics = pool.queue_metrics();
+        assert_eq!((metrics.depth, metrics.capacity), (2, Some(2)));
+        assert_eq!(metrics.rejected, 1);
+        gate.wait();
+        assert!(queued.into_iter().all(|handle| handle.wait().is_ok()));
+        assert_eq!(pool.queue_metrics().depth, 0);
+    }
+
+    #[test]
+    fn test_full_queue_drops_oldest() {
+        let (pool, gate, _blocker) = busy_bounded_pool(Backpressure::DropOldest);
+        let oldest = pool.execute(current_thread).unwrap();
+        let rest = (0..2)
+            .map(|_| pool.execute(current_thread).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(oldest.wait(), Err(ThreadPoolError::Cancelled));
+        let metrics = pool.queue_metrics();
+        assert_eq!((metrics.depth, metrics.dropped), (2, 1));
+        gate.wait();
+        assert!(rest.into_iter().all(|handle| handle.wait().is_ok()));
+    }
+
+    #[test]
+    fn test_full_queue_runs_on_caller() {
+        let (pool, gate, blocker) = busy_bounded_pool(Backpressure::CallerRuns);
+        let queued = (0..2)
+            .map(|_| pool.execute(current_thread).unwrap())
+            .collect::<Vec<_>>();
+        let overflow = pool.execute(current_thread).unwrap();
+
+        assert_eq!(overflow.poll(), Some(Ok(thread::current().id())));
+        assert_eq!(pool.queue_metrics().ran_on_caller, 1);
+        gate.wait();
+        let worker = blocker.wait().unwrap();
+        assert!(queued.into_iter().all(|handle| handle.wait() == Ok(worker)));
+    }
+
+    #[test]
+    fn test_full_queue_blocks_submitter() {
+        let (pool, gate, _blocker) = busy_bounded_pool(Backpressure::Block);
+        let pool = Arc::new(pool);
+        let queued = (0..2)
+            .map(|_| pool.execute(current_thread).unwrap())
+            .collect::<Vec<_>>();
+        let submitter = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.execute(current_thread).unwrap().wait())
+        };
+
+        while pool.queue_metrics().blocked == 0 {
+            thread::yield_now();
+        }
+        assert!(!submitter.is_finished());
+        gate.wait();
+        assert!(submitter.join().unwrap().is_ok());
+        assert!(queued.into_iter().all(|handle| handle.wait().is_ok()));
+
+        let metrics = pool.queue_metrics();
+        assert_eq!((metrics.depth, metrics.peak_depth), (0, 2));
+    }
+
+    #[test]
+    fn test_wait_timeout_and_cancel() {
+        let pool = ThreadPool::new(1).unwrap();
+        let gate = Arc::new(Barrier::new(2));
+        let blocker = {
+            let gate = Arc::clone(&gate);
+            pool.execute(move || {
+                gate.wait();
+                "blocker"
+            })
+            .unwrap()
+        };
+        let queued = pool.execute(|| "queued").unwrap();
+
+        assert_eq!(blocker.wait_timeout(Duration::from_millis(20)), None);
+        assert!(queued.cancel());
+        gate.wait();
+
+        assert_eq!(
+            blocker.wait_timeout(Duration::from_secs(5)),
+            Some(Ok("blocker"))
+        );
+        assert_eq!(queued.wait(), Err(ThreadPoolError::Cancelled));
+        assert!(pool.get_results().is_empty());
+    }
+
+    #[test]
+    fn test_priority_pool_runs_urgent_jobs_first() {
+        let aging = Duration::from_secs(60);
+        let pool = ThreadPool::with_scheduler(1, Scheduler::Priority { aging }).unwrap();
+        let (started, has_started) = crossbeam::channel::bounded(1);
+        let gate = Arc::new(Barrier::new(2));
+        {
+            let gate = Arc::clone(&gate);
+            pool.execute(move || {
+                started.send(()).unwrap();
+                gate.wait();
+                "blocker"
+            })
+            .unwrap();
+        }
+        has_started.recv().unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (priority, label) in [
+            (Priority::Low, "low"),
+            (Priority::Normal, "normal"),
+            (Priority::High, "high"),
+        ] {
+            let order = Arc::clone(&order);
+            drop(
+                pool.execute_with_priority(priority, move || {
+                    order.lock().unwrap().push(label);
+                    label
+                })
+                .unwrap(),
+            );
+        }
+        gate.wait();
+        pool.shutdown();
+        assert_eq!(*order.lock().unwrap(), vec!["high", "normal", "low"]);
+    }
+
+    #[test]
+    fn test_jobs_past_their_deadline_expire() {
+        let pool = ThreadPool::new(1).unwrap();
+        let gate = Arc::new(Barrier::new(2));
+        let blocker = {
+            let gate = Arc::clone(&gate);
+            pool.execute(move || {
+                gate.wait();
+                0
+            })
+            .unwrap()
+        };
+        let soon = JobOptions {
+            deadline: Some(Instant::now() + Duration::from_millis(10)),
+            ..JobOptions::default()
+        };
+        let later = JobOptions {
+            deadline: Some(Instant::now() + Duration::from_secs(60)),
+            ..JobOptions::default()
+        };
+        let expired = pool.execute_with(soon, || 1).unwrap();
+        let in_time = pool.execute_with(later, || 2).unwrap();
+        drop(pool.execute_with(soon, || 3).unwrap());
+
+        thread::sleep(Duration::from_millis(20));
+        gate.wait();
+        assert_eq!(blocker.wait(), Ok(0));
+        assert_eq!(expired.wait(), Err(ThreadPoolError::Expired));
+        assert_eq!(in_time.wait(), Ok(2));
+        pool.shutdown();
+        assert_eq!(pool.get_results(), vec![Err(ThreadPoolError::Expired)]);
     }
 }
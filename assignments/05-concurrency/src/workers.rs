@@ -0,0 +1,267 @@
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::scheduler::{JobSource, Next};
+
+/// How long an idle worker waits for a job before checking whether it should retire
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The worker threads of a pool, shared between the pool and the workers themselves
+///
+/// The pool keeps between `min` and `max` workers. Workers retire once there are more than `max`,
+/// or once they have been idle for `keep_alive` while there are more than `min`. New ones are
+/// started when jobs queue up faster than idle workers can take them and there are fewer than
+/// `max`.
+pub(crate) struct Workers {
+    queue: JobSource,
+
+    /// Join handles of every worker started, including any that replaced workers that died
+    handles: Mutex<Vec<JoinHandle<()>>>,
+
+    /// Which worker indices are taken, so retired workers' deques can be reused
+    slots: Mutex<Vec<bool>>,
+
+    /// Workers that haven't retired, or exited after the pool was shut down
+    live: AtomicUsize,
+
+    /// Workers waiting for a job
+    idle: AtomicUsize,
+    min: AtomicUsize,
+    max: AtomicUsize,
+    keep_alive_ms: AtomicU64,
+
+    /// Set once the pool is shut down, after which no more workers are started
+    closed: AtomicBool,
+}
+
+impl Workers {
+    /// Start `num_threads` workers running jobs from `queue`
+    pub(crate) fn start(queue: JobSource, num_threads: usize) -> Arc<Self> {
+        let workers = Arc::new(Workers {
+            queue,
+            handles: Mutex::default(),
+            slots: Mutex::default(),
+            live: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            min: AtomicUsize::new(num_threads),
+            max: AtomicUsize::new(num_threads),
+            keep_alive_ms: AtomicU64::new(u64::MAX),
+            closed: AtomicBool::new(false),
+        });
+        workers.fill();
+        workers
+    }
+
+    /// Number of workers that haven't retired or exited
+    pub(crate) fn live(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    /// Keep between `min` and `max` workers, retiring any idle for longer than `keep_alive`
+    /// while there are more than `min`. Surplus workers retire once they finish their job
+    pub(crate) fn resize(self: &Arc<Self>, min: usize, max: usize, keep_alive: Option<Duration>) {
+        let keep_alive_ms = keep_alive.map_or(u64::MAX, |keep_alive| {
+            u64::try_from(keep_alive.as_millis()).unwrap_or(u64::MAX)
+        });
+        self.keep_alive_ms.store(keep_alive_ms, Ordering::SeqCst);
+        self.max.store(max, Ordering::SeqCst);
+        self.min.store(min, Ordering::SeqCst);
+        self.fill();
+    }
+
+    /// Start a worker if more jobs are queued than there are idle workers to take them, or there
+    /// are no workers at all, and there is room for another
+    pub(crate) fn grow_if_backed_up(self: &Arc<Self>, queued: usize) {
+        if queued > self.idle.load(Ordering::SeqCst) || self.live() == 0 {
+            self.try_add(|live| live < self.max.load(Ordering::SeqCst));
+        }
+    }
+
+    /// Stop starting workers, as the queue is closed
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Wait for every worker to exit
+    pub(crate) fn join(&self) {
+        // Workers replacing ones that die while we wait add themselves to the list, so keep going
+        // until it stays empty
+        loop {
+            let handles = mem::take(&mut *lock(&self.handles));
+            if handles.is_empty() {
+                break;
+            }
+            for handle in handles {
+                // The pool may be shut down, or its last reference dropped, by one of its own jobs
+                if handle.thread().id() != thread::current().id() {
+                    let _ = handle.join();
+                }
+            }
+        }
+    }
+
+    /// Start workers until there are at least `min`
+    fn fill(self: &Arc<Self>) {
+        while self.try_add(|live| live < self.min.load(Ordering::SeqCst)) {}
+    }
+
+    /// Start a worker if `wanted` says so for the current number of workers
+    fn try_add(self: &Arc<Self>, wanted: impl Fn(usize) -> bool) -> bool {
+        if self.closed.load(Ordering::SeqCst) {
+            return false;
+        }
+        let added = self
+            .live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                wanted(live).then_some(live + 1)
+            });
+        if added.is_err() {
+            return false;
+        }
+
+        let index = {
+            let mut slots = lock(&self.slots);
+            match slots.iter().position(|taken| !taken) {
+                Some(index) => {
+                    slots[index] = true;
+                    index
+                }
+                None => {
+                    slots.push(true);
+                    slots.len() - 1
+                }
+            }
+        };
+        self.queue.add_worker(index);
+        self.spawn(index);
+        true
+    }
+
+    /// Start a thread to be worker `index`
+    fn spawn(self: &Arc<Self>, index: usize) {
+        let handle = {
+            let workers = Arc::clone(self);
+            thread::spawn(move || {
+                let sentinel = Sentinel { workers, index };
+                sentinel.workers.run(index);
+            })
+        };
+        let mut handles = lock(&self.handles);
+        // Retired workers are gone for good, so their handles only need keeping until then
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(handle);
+    }
+
+    /// Run jobs as worker `index` until the queue is closed, or the worker retires
+    fn run(&self, index: usize) {
+        self.queue.enter(index);
+        let mut idle_since = None;
+        loop {
+            self.idle.fetch_add(1, Ordering::SeqCst);
+            let next = self.queue.next_job(index, IDLE_CHECK_INTERVAL);
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+
+            let idle_for = match next {
+                Next::Job(job) => {
+                    job();
+                    idle_since = None;
+                    Duration::ZERO
+                }
+                Next::Idle => idle_since.get_or_insert_with(Instant::now).elapsed(),
+                Next::Closed => {
+                    self.live.fetch_sub(1, Ordering::SeqCst);
+                    return;
+                }
+            };
+            if self.try_retire(index, idle_for) {
+                return;
+            }
+        }
+    }
+
+    /// Give up worker `index`'s place in the pool if there are too many workers, or it has been
+    /// idle too long. Any jobs on its deque are left for the others
+    ///
+    /// The last worker stays while jobs are queued. A job submitted as it stopped waiting may have
+    /// counted it as idle and not started another worker, and with `min` at 0 nothing else would
+    /// ever run the job. Jobs submitted after the queue is checked see no workers, and start one.
+    fn try_retire(&self, index: usize, idle_for: Duration) -> bool {
+        let expired = idle_for.as_millis() >= u128::from(self.keep_alive_ms.load(Ordering::SeqCst));
+        let retired = self
+            .live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                let surplus = live > self.max.load(Ordering::SeqCst);
+                let spare = expired && live > self.min.load(Ordering::SeqCst);
+                (surplus || spare).then_some(live - 1)
+            });
+        let Ok(live) = retired else {
+            return false;
+        };
+        self.queue.remove_worker(index);
+        if live == 1
+            && !self.queue.is_empty()
+            && self
+                .live
+                .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            return false;
+        }
+        lock(&self.slots)[index] = false;
+        true
+    }
+}
+
+/// Lives on a worker's stack and starts a replacement if the worker unwinds
+struct Sentinel {
+    workers: Arc<Workers>,
+    index: usize,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.workers.spawn(self.index);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        priority::Priority,
+        scheduler::{queue, Scheduler},
+    };
+
+    #[test]
+    fn test_last_worker_stays_while_jobs_are_queued() {
+        let (jobs, source) = queue(Scheduler::Fifo);
+        let workers = Workers::start(source, 0);
+        workers.resize(0, 1, Some(Duration::ZERO));
+
+        // Stand in for a worker that has just stopped waiting, as a job arrives that counted it
+        // as idle and so didn't start another
+        workers.live.store(1, Ordering::SeqCst);
+        lock(&workers.slots).push(true);
+        jobs.push(Box::new(|| {}), Priority::Normal).unwrap();
+
+        assert!(!workers.try_retire(0, Duration::ZERO));
+        assert_eq!(workers.live(), 1);
+
+        assert!(workers.queue.take_oldest().is_some());
+        assert!(workers.try_retire(0, Duration::ZERO));
+        assert_eq!(workers.live(), 0);
+    }
+}